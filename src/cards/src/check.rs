@@ -1,17 +1,17 @@
 
-
 #[allow(unused_imports)]
 use log::{error, warn, info, debug, trace};
 #[allow(unused_imports)]
 use anyhow::{Result, Error, bail, anyhow, Context};
 
-use std::path::{Path};
+use std::sync::Arc;
 
-use tokio::fs;
+use super::store::CardStore;
 
-pub struct Arg<'a> {
+pub struct Arg {
     pub id: u64,
-    pub out_dir: &'a Path,
+    pub store: Arc<dyn CardStore>,
+    pub refetch: bool,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -19,18 +19,12 @@ pub struct Ret {
     pub id: Option<u64>,
 }
 
-pub async fn run<'a>(arg: Arg<'a>) -> Result<Ret> {
-    let file_path = super::file_spec::get(arg.out_dir, arg.id);
-    match fs::metadata(&file_path).await {
-        Err(err) => {
-            match err.kind() {
-                std::io::ErrorKind::NotFound => Ok(Ret{id: Some(arg.id)}),
-                _ => Err(Error::new(err).context(format!("{:?}", &file_path))),
-            }
-        },
-        Ok(_) => {
-            Ok(Ret{id: None})
-        },
+pub async fn run(arg: Arg) -> Result<Ret> {
+    let Arg { id, store, refetch } = arg;
+    if !refetch && tokio::task::spawn_blocking(move || store.exists(id)).await?? {
+        Ok(Ret{id: None})
+    } else {
+        Ok(Ret{id: Some(id)})
     }
 }
 
@@ -43,16 +37,24 @@ mod tests {
     #[allow(unused_imports)]
     use log::{error, warn, info, debug, trace};
     use super::*;
+    use std::path::Path;
 
     #[tokio::test]
     async fn test_check() -> Result<()> {
         test_helper::init();
 
-        let out_dir = &Path::new("out_test");
+        let store: Arc<dyn CardStore> = Arc::new(super::super::files::Files::new(Path::new("out_test")));
         let id = 42;
 
-        let ret = run(Arg { out_dir, id }).await?;
+        let ret = run(Arg { store: store.clone(), id, refetch: false }).await?;
+        assert_eq!(ret, Ret{id: Some(id)});
+
+        store.put(id, &super::super::Fetched::NotFound)?;
+        let ret = run(Arg { store: store.clone(), id, refetch: false }).await?;
+        assert_eq!(ret, Ret{id: None});
+        let ret = run(Arg { store: store.clone(), id, refetch: true }).await?;
         assert_eq!(ret, Ret{id: Some(id)});
+        std::fs::remove_file(super::super::file_spec::get(Path::new("out_test"), id))?;
 
         Ok(())
     }
@@ -60,4 +62,3 @@ mod tests {
 }
 
 
-
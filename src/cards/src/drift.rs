@@ -0,0 +1,124 @@
+
+#[allow(unused_imports)]
+use log::{error, warn, info, debug, trace};
+#[allow(unused_imports)]
+use anyhow::{Result, Error, bail, anyhow, Context};
+
+use serde::{
+    Serialize,
+    Deserialize,
+};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use tokio::fs::{self, File};
+use tokio::prelude::*;
+
+use super::fetched::Extra;
+
+// ============================================================================
+// ============================================================================
+
+// Отчет о расхождении схемы: какие неожиданные ключи встретились в карточках,
+// какого типа были их значения, сколько раз и в каких объявлениях
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Drift (pub BTreeMap<String, DriftItem>);
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct DriftItem {
+    pub value_types: BTreeSet<String>,
+    pub qt: usize,
+    pub example_ids: Vec<u64>,
+}
+
+const EXAMPLE_IDS_MAX: usize = 5;
+
+impl Drift {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    pub fn adopt(&mut self, id: u64, extra: &Extra) {
+        for (path, value) in extra.0.iter() {
+            let item = self.0.entry(path.to_owned()).or_insert_with(|| DriftItem {
+                value_types: BTreeSet::new(),
+                qt: 0,
+                example_ids: Vec::new(),
+            });
+            item.value_types.insert(value_type(value).to_owned());
+            item.qt += 1;
+            if item.example_ids.len() < EXAMPLE_IDS_MAX {
+                item.example_ids.push(id);
+            }
+        }
+    }
+    pub fn report(&self) -> String {
+        let mut lines: Vec<String> = Vec::new();
+        for (path, item) in self.0.iter() {
+            lines.push(format!("{}: {}, qt: {}, ids: {}",
+                path,
+                item.value_types.iter().map(|s| s.as_str()).collect::<Vec<&str>>().join("|"),
+                item.qt,
+                item.example_ids.iter().map(|id| id.to_string()).collect::<Vec<String>>().join(", "),
+            ));
+        }
+        lines.join("\n")
+    }
+    pub async fn to_file(&self, file_path: &Path) -> Result<()> {
+        if let Some(dir_path) = file_path.parent() {
+            fs::create_dir_all(dir_path).await?;
+        }
+        let mut file = File::create(file_path).await?;
+        let json = serde_json::to_string_pretty(&self)?;
+        file.write_all(json.as_bytes()).await?;
+        Ok(())
+    }
+}
+
+fn value_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "bool",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+// ============================================================================
+// ============================================================================
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use log::{error, warn, info, debug, trace};
+    use super::*;
+
+    #[test]
+    fn test_drift() {
+        test_helper::init();
+
+        let mut drift = Drift::new();
+        for id in 1..=7 {
+            let mut extra = Extra::new();
+            extra.0.insert("firebaseParams.kolichestvo_mest".to_owned(), if id % 2 == 0 { serde_json::json!("5") } else { serde_json::json!(5) });
+            drift.adopt(id, &extra);
+        }
+        let mut extra = Extra::new();
+        extra.0.insert("newKey".to_owned(), Value::Null);
+        drift.adopt(42, &extra);
+
+        assert_eq!(drift.0.len(), 2);
+        let item = drift.0.get("firebaseParams.kolichestvo_mest").unwrap();
+        assert_eq!(item.qt, 7);
+        assert_eq!(item.example_ids, vec![1, 2, 3, 4, 5]);
+        assert_eq!(item.value_types.iter().map(|s| s.as_str()).collect::<Vec<&str>>(), vec!["number", "string"]);
+
+        assert_eq!(drift.report(), "firebaseParams.kolichestvo_mest: number|string, qt: 7, ids: 1, 2, 3, 4, 5\nnewKey: null, qt: 1, ids: 42");
+    }
+}
@@ -10,6 +10,7 @@ pub struct Arg {
     pub client: client::Client,
     pub auth: String,
     pub id: u64,
+    pub lenient: bool,
 }
 
 use super::fetched::{Fetched};
@@ -43,7 +44,7 @@ pub async fn run(arg: Arg) -> Result<Ret> {
         },
     }
 
-    let fetched = Fetched::parse(text, url).await?;
+    let fetched = Fetched::parse(text, url, arg.lenient).await?;
 
     Ok(Ret {
         client: arg.client,
@@ -10,8 +10,8 @@ use serde::{
 };
 use serde_json::Value;
 use regex::Regex;
-use std::path::Path;
-use tokio::fs;
+use chrono::{DateTime, Utc, TimeZone};
+use std::collections::BTreeMap;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum Fetched {
@@ -24,31 +24,32 @@ pub enum Fetched {
     Record(Record)
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Record {
-    pub body_type: Option<String>,
+    pub id: Option<u64>,
+    pub body_type: Option<vocab::BodyType>,
     pub brand: Option<String>,
     pub color: Option<String>,
-    pub fuel_type: Option<String>,
+    pub fuel_type: Option<vocab::FuelType>,
     // modelDate - год модели
     pub name: Option<String>,
     pub title: Option<String>, // модель автомобиля по автокаталогу
     pub number_of_doors: Option<u8>,
     pub production_date: Option<u16>,
     // vehicle_configuration
-    pub vehicle_transmission: Option<String>,
+    pub vehicle_transmission: Option<vocab::Transmission>,
     pub engine_displacement: Option<String>,
     pub engine_power: Option<String>,
     pub description: Option<String>,
     pub mileage: Option<u64>,
     // Комлектация
     #[serde(rename = "Привод")]
-    pub drive: Option<String>,
+    pub drive: Option<vocab::Drive>,
     #[serde(rename = "Руль")]
     pub steering_wheel: Option<String>,
     #[serde(rename = "Состояние")]
-    pub condition: Option<String>,
+    pub condition: Option<vocab::Condition>,
     #[serde(rename = "Владельцы")]
     pub owners: Option<String>,
     // ПТС
@@ -85,14 +86,67 @@ pub struct Record {
     pub type_of_trade: Option<String>,
     pub canonical_url: Option<String>,
 
+    pub location_id: Option<u64>,
+    pub lat: Option<f64>,
+    pub lng: Option<f64>,
+
+    pub time: Option<DateTime<Utc>>, // время публикации
+    pub views_total: Option<u64>,
+    pub views_today: Option<u64>,
+    pub images_qt: Option<usize>,
+    pub images: Option<Images>,
+    pub has_video: Option<bool>,
+
+    // Жизненный цикл объявления, заполняется из lifecycle перед выгрузкой
+    pub first_seen: Option<DateTime<Utc>>,
+    pub last_seen: Option<DateTime<Utc>>,
+    pub closed_at: Option<DateTime<Utc>>,
+    pub days_on_market: Option<i64>,
+
+    // Повторы объявления (см. dedup): кластер и объявление, повтором которого является это
+    pub cluster_id: Option<u64>,
+    pub duplicate_of: Option<u64>,
+
+    // Изменение с прошлой выгрузки при инкрементной выгрузке: new, changed, removed (см. crate changes)
+    pub change_type: Option<String>,
+
+    // Числовые характеристики из полей карточки и автокаталога (см. crate units), заполняются перед выгрузкой
+    pub power_hp: Option<f64>,
+    pub power_kw: Option<f64>,
+    pub displacement_l: Option<f64>,
+    pub displacement_cc: Option<f64>,
+    pub torque_nm: Option<f64>,
+    pub max_speed_kmh: Option<f64>,
+    pub acceleration_s: Option<f64>,
+    pub consumption_city_l100km: Option<f64>,
+    pub consumption_highway_l100km: Option<f64>,
+    pub consumption_mixed_l100km: Option<f64>,
+    pub fuel_tank_l: Option<f64>,
+    pub length_mm: Option<f64>,
+    pub width_with_mirrors_mm: Option<f64>,
+    pub height_mm: Option<f64>,
+    pub wheelbase_mm: Option<f64>,
+    pub clearance_mm: Option<f64>,
+    pub front_track_mm: Option<f64>,
+    pub rear_track_mm: Option<f64>,
+
+    // Неизвестные ключи карточки (при нестрогом разборе): путь -> значение
+    pub extra: Option<Extra>,
+
     // #[serde(flatten)]
     // pub autocatalog: Option<super::autocatalog::Record>,
 
     pub autocatalog_id: Option<u64>,
     pub autocatalog_title: Option<String>,
+    // Как найдена модификация автокаталога: url - по ссылке карточки, title - по названию модификации,
+    // specs - по характеристикам, ambiguous - одинаково подходят несколько (их число - candidates);
+    // уверенность (0..1) - доля совпавших характеристик (см. crate enrich)
+    pub autocatalog_match_method: Option<String>,
+    pub autocatalog_match_confidence: Option<f64>,
+    pub autocatalog_match_candidates: Option<u64>,
 
     // #[serde(rename = "Коробка передач")]
-    pub autocatalog_transmission: Option<String>,
+    pub autocatalog_transmission: Option<vocab::Transmission>,
 
     // #[serde(rename = "Объем двигателя, л")]
     pub autocatalog_engine_displacement: Option<String>,
@@ -100,9 +154,9 @@ pub struct Record {
     pub autocatalog_engine_displacement_precise: Option<String>,
 
     // #[serde(rename = "Привод")]
-    pub autocatalog_drive: Option<String>,
+    pub autocatalog_drive: Option<vocab::Drive>,
     // #[serde(rename = "Тип двигателя")]
-    pub autocatalog_fuel_type: Option<String>,
+    pub autocatalog_fuel_type: Option<vocab::FuelType>,
 
     // #[serde(rename = "Мощность, л.с.")]
     pub autocatalog_engine_power: Option<String>,
@@ -192,81 +246,109 @@ pub struct Record {
     pub autocatalog_front_disc_dimension: Option<String>,
 }
 
+// Ссылки на фотографии объявления (по одной, наибольшего размера, на каждое фото).
+// Сериализуются одной строкой через пробел, чтобы оставаться плоским полем и в файле карточки, и в csv
+#[derive(Debug, Clone, PartialEq)]
+pub struct Images(pub Vec<String>);
+
+impl Serialize for Images {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.0.join(" "))
+    }
+}
+
+impl<'de> Deserialize<'de> for Images {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        Ok(Images(s.split_whitespace().map(|s| s.to_owned()).collect()))
+    }
+}
+
+// Неизвестные ключи карточки, собранные при нестрогом разборе: "firebaseParams.some_key" -> значение.
+// Сериализуются компактной json-строкой, чтобы оставаться плоским полем и в файле карточки, и в csv
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Extra(pub BTreeMap<String, Value>);
+
+impl Extra {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Serialize for Extra {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let s = serde_json::to_string(&self.0).map_err(serde::ser::Error::custom)?;
+        serializer.serialize_str(&s)
+    }
+}
+
+impl<'de> Deserialize<'de> for Extra {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        let map = serde_json::from_str(&s).map_err(serde::de::Error::custom)?;
+        Ok(Extra(map))
+    }
+}
+
 use url::Url;
 use json::{Json, By};
-use tokio::prelude::*;
 
 impl Fetched {
-    pub async fn parse<S: AsRef<str>>(text: S, url: Url) -> Result<Self> {
+    pub async fn parse<S: AsRef<str>>(text: S, url: Url, lenient: bool) -> Result<Self> {
         let json = Json::from_str(&text, url)?;
-        match Self::parse_json(&json) {
+        match Self::parse_json(&json, lenient) {
             Ok(record) => Ok(Fetched::Record (record)),
-            Err(err) => {
-                let file_path = Path::new("error.card.json");
-                let mut file = fs::File::create(file_path).await.context(format!("file_path: {:?}", file_path))?;
-                file.write_all(text.as_ref().as_bytes()).await?;
-                Ok(Fetched::WithError {json: json.value, error: format!("{}", err)})
-            },
+            Err(err) => Ok(Fetched::WithError {json: json.value, error: format!("{}", err)}),
         }
     }
-    pub fn parse_json(json: &Json) -> Result<Record> {
-        let mut power_windows: Option<String> = None;
-        let mut name: Option<String> = None;
-        let title = None;
+    // При lenient == true неизвестные ключи не приводят к ошибке, а собираются в Record::extra
+    pub fn parse_json(json: &Json, lenient: bool) -> Result<Record> {
+        let mut id: Option<u64> = None;
         let mut canonical_url: Option<String> = None;
-        let mut item_price: Option<u64> = None;
         let mut market_price: Option<u64> = None;
-        let mut fuel_type: Option<String> = None;
-        let mut power_steering: Option<String> = None;
-        let mut audio_system: Option<String> = None;
-        let mut headlights: Option<String> = None;
-        let mut production_date: Option<u16> = None;
-        let mut climate_control: Option<String> = None;
-        let mut interior: Option<String> = None;
-        let mut vehicle_transmission: Option<String> = None;
-        let mut body_type: Option<String> = None;
-        let mut brand: Option<String> = None;
-        let mut engine_displacement: Option<String> = None;
-        let mut type_of_trade: Option<String> = None;
-        let mut color: Option<String> = None;
-        let mut engine_power: Option<String> = None;
-
-        let mut condition: Option<String> = None;
         let mut description: Option<String> = None;
-        let mut rims: Option<String> = None;
-        let mut number_of_doors: Option<u8> = None;
-        let mut owners: Option<String> = None;
         let mut autocatalog_url: Option<String> = None;
-        let mut drive: Option<String> = None;
-        let mut steering_wheel: Option<String> = None;
-        let mut mileage: Option<u64> = None;
         let mut status: Option<String> = None;
         let mut closing_reason: Option<String> = None;
-        let mut complectation: Option<String> = None;
-        let mut generation: Option<String> = None;
-        let mut modification: Option<String> = None;
+        let mut time: Option<DateTime<Utc>> = None;
+        let mut location_id: Option<u64> = None;
+        let mut lat: Option<f64> = None;
+        let mut lng: Option<f64> = None;
+        let mut views_total: Option<u64> = None;
+        let mut views_today: Option<u64> = None;
+        let mut images_qt: Option<usize> = None;
+        let mut images: Option<Images> = None;
+        let mut has_video = false;
+        let mut extra = Extra::new();
+        let mut firebase_params: Option<Json> = None;
         for (key, val) in json.iter_map()? {
             match key {
                 "address" | 
                 "adjustParams" | 
                 "categoryId" | 
-                "coords" | 
                 "districtId" | 
                 "geoReferences" | 
-                "locationId" | 
                 "metroId" | 
                 "metroType" | 
-                "time" | 
                 "userType" | 
                 "titleGenerated" | 
                 "title" | 
-                "stats" | 
                 "sharing" | 
                 "seller" | 
                 "needToCheckCreditInfo" | 
                 "needToCheckSimilarItems" | 
-                "images" | 
-                "video" | 
                 "icebreakers" | 
                 "contacts" | 
                 "autotekaTeaser" | 
@@ -279,10 +361,44 @@ impl Fetched {
                 "similarAction" | 
                 "shopId" | 
                 "shopType" | 
-                "advertOptions" | 
-                "id"  => {
+                "advertOptions" => {
                     // skip
                 },
+                "id" => {
+                    id = Some(val.as_u64()?);
+                },
+                "locationId" => {
+                    location_id = Some(val.as_u64()?);
+                },
+                "coords" => {
+                    if !val.value.is_null() {
+                        lat = Some(val.get([By::key("lat")])?.as_f64()?);
+                        lng = Some(val.get([By::key("lng")])?.as_f64()?);
+                    }
+                },
+                "time" => {
+                    let secs = val.as_i64()?;
+                    match Utc.timestamp_opt(secs, 0) {
+                        chrono::LocalResult::Single(dt) => time = Some(dt),
+                        _ => bail!("{} expected to be a unix timestamp, but: {}", val.path, secs),
+                    }
+                },
+                "stats" => {
+                    let views = val.get([By::key("views")])?;
+                    views_total = Some(views.get([By::key("total")])?.as_u64()?);
+                    views_today = Some(views.get([By::key("today")])?.as_u64()?);
+                },
+                "images" => {
+                    let mut urls: Vec<String> = Vec::new();
+                    for image in val.iter_vec()? {
+                        urls.push(Self::largest_image_url(&image)?);
+                    }
+                    images_qt = Some(urls.len());
+                    images = Some(Images(urls));
+                },
+                "video" => {
+                    has_video = !val.value.is_null();
+                },
                 "status" => {
                     status = Some(val.as_string()?);
                 },
@@ -321,286 +437,66 @@ impl Fetched {
                     canonical_url = Some(val.as_string()?);
                 },
                 "firebaseParams" => {
-                    for (sub_key, val) in val.iter_map()? {
-                        match sub_key {
-                            "categoryId" | 
-                            "categorySlug" | 
-                            "isASDClient" | 
-                            "isNewAuto" | 
-                            "isPersonalAuto" | 
-                            "isShop" | 
-                            "itemID" | 
-                            "locationId" | 
-                            "microCategoryId" | 
-                            "userAuth" | 
-                            "vertical" | 
-                            "vehicle_type" | 
-                            "withDelivery"  => {
-                                // skip 
-                            },
-                            "body_type" => {
-                                let s = match val.as_str()? {
-                                    "Внедорожник" => "внедорожник",
-                                    "Седан" => "седан",
-                                    "Купе" => "купе",
-                                    "Хетчбэк" => "хэтчбек",
-                                    "Лифтбек" => "лифтбек",
-                                    "Универсал" => "универсал",
-                                    "Минивэн" => "минивэн",
-                                    "Кабриолет" => "кабриолет",
-                                    "Фургон" => "фургон",
-                                    "Пикап" => "пикап",
-                                    "Микроавтобус" => "микроавтобус",
-                                    s @ _ => {
-                                        warn!("{}: {:?}", val.path, s);
-                                        s
-                                    }
-                                };
-                                body_type = Some(s.to_owned());
-                            },
-                            "brand" => {
-                                brand = Some(val.as_string()?);
-                            },
-                            "color" => {
-                                color = Some(val.as_string()?);
-                            },
-                            "mileage" => {
-                                lazy_static! {
-                                    static ref RE_REPLACE: Regex = Regex::new(r"\D").unwrap();
-                                }
-                                mileage = Some(val.parse_as_u64_after(|s| RE_REPLACE.replace_all(s, ""))?);
-                            },
-                            "capacity" => {
-                                engine_power = Some(val.as_string()?);
-                            },
-                            "engine" => {
-                                engine_displacement = Some(val.as_string()?);
-                            }, 
-                            "type_of_trade" => {
-                                type_of_trade = Some(val.as_string()?);
-                            },
-                            "condition" => {
-                                condition = Some(val.as_string()?);
-                            },
-                            "drive" => {
-                                let s = match val.as_str()? {
-                                    "Передний" => "передний",
-                                    "Задний" => "задний",
-                                    "Полный" => "полный",
-                                    s @ _ => {
-                                        warn!("{}: {}", val.path, s);
-                                        s
-                                    }
-                                };
-                                drive = Some(s.to_owned());
-                            },
-                            "engine_type" => {
-                                fuel_type = Some(val.as_string()?);
-                            },
-                            "model" => {
-                                name = Some(val.as_string()?);
-                            },
-                            "transmission" => {
-                                let val = match val.as_str()? {
-                                    "Механика" => "механическая",
-                                    "Автомат" => "автоматическая",
-                                    "Вариатор" => "вариатор",
-                                    "Робот" => "робот",
-                                    s @ _ => {
-                                        warn!("{}: {}", val.path, s);
-                                        s
-                                    }
-                                };
-                                vehicle_transmission = Some(val.to_owned());
-                            },
-                            "vladeltsev_po_pts" => {
-                                owners = Some(val.as_string()?);
-                            },
-                            "audiosistema" => {
-                                audio_system = Some(val.as_string()?);
-                            },
-                            "wheel" => {
-                                steering_wheel = Some(val.as_string()?);
-                            },
-                            "elektrosteklopodemniki" => {
-                                power_windows = Some(val.as_string()?);
-                            },
-                            "usilitel_rulya" => {
-                                power_steering = Some(val.as_string()?);
-                            },
-                            "diski" => {
-                                rims = Some(val.as_string()?);
-                            },
-                            "salon" => {
-                                interior = Some(val.as_string()?);
-                            },
-                            "upravlenie_klimatom" => {
-                                climate_control = Some(val.as_string()?);
-                            },
-                            "fary" => {
-                                headlights = Some(val.as_string()?);
-                            },
-                            "itemPrice" => {
-                                let v = val.parse_as_u64()?;
-                                match item_price {
-                                    None => item_price = Some(v),
-                                    Some(item_price) => {
-                                        if item_price != v {
-                                            warn!("{} expected to be a {}, not {:?}", val.path, item_price, v );
-                                        }
-                                    },
-                                }
-                            },
-                            "year" => {
-                                production_date = Some(val.parse_as_u16()?);
-                            },
-                            "kolichestvo_dverey" => {
-                                number_of_doors = Some(val.parse_as_u8()?);
-                            },
-                            "description" => {
-                                let v = val.as_str()?;
-                                match &description {
-                                    None => {
-                                        description = Some(v.to_owned());
-                                    },
-                                    Some(description) => {
-                                        if description != v {
-                                            warn!("{} expected to be a {}, not {:?}", val.path, description, v );
-                                        }
-                                    },
-                                }
-                            },
-                            "price" => {
-                                let v = val.parse_as_u64()?;
-                                match item_price {
-                                    None => item_price = Some(v),
-                                    Some(item_price) => {
-                                        if item_price != v {
-
-                                            warn!("{} expected to be a {}, not {:?}", val.path, item_price, v );
-
-                                        }
-                                    },
-                                }
-                            },
-                            "complectation" => {
-                                complectation = Some(val.as_string()?);
-                            },
-                            "generation" => {
-                                generation = Some(val.as_string()?);
-                            },
-                            "modification" => {
-                                modification = Some(val.as_string()?);
-                            },
-                            _ => {
-                                bail!("unexpected {}: {}", val.path, val.value);
-                            },
-                        }
-                    }
+                    firebase_params = Some(val);
                 },
                 _ => {
-                    bail!("unexpected {}: {}", val.path, val.value);
+                    if !lenient {
+                        bail!("unexpected {}: {}", val.path, val.value);
+                    }
+                    extra.0.insert(key.to_owned(), val.value.clone());
                 },
             }
         }
-        Ok(Record {
-            power_windows,
-            name,
-            title,
+        // остальные поля - из firebaseParams (см. mapping), перед выгрузкой - обогащением (см. crate enrich)
+        let mut ret = Record {
+            id,
             canonical_url,
-            item_price,
             market_price,
-            fuel_type,
-            power_steering,
-            audio_system,
-            headlights,
-            production_date,
-            climate_control,
-            interior,
-            vehicle_transmission,
-            body_type,
-            brand,
-            engine_displacement,
-            type_of_trade,
-            color,
-            engine_power,
-            condition,
+            location_id,
+            lat,
+            lng,
             description,
-            rims,
-            number_of_doors,
-            owners,
             autocatalog_url,
-            drive,
-            steering_wheel,
-            mileage,
             status,
             closing_reason,
-            complectation,
-            generation,
-            modification,
-
-            autocatalog_id: None,
-            autocatalog_title: None,
-
-            autocatalog_transmission: None,
-
-            autocatalog_engine_displacement: None,
-            autocatalog_engine_displacement_precise: None,
-
-            autocatalog_drive: None,
-            autocatalog_fuel_type: None,
-
-            autocatalog_engine_power: None,
-            autocatalog_maximum_speed: None,
-            autocatalog_acceleration: None,
-
-            autocatalog_brand_country: None,
-            autocatalog_assembly_country: None,
-
-            autocatalog_number_of_seats: None,
-
-            autocatalog_rating: None,
-
-            autocatalog_number_of_cylinders: None,
-            autocatalog_configuration: None,
-
-            autocatalog_torque: None,
-            autocatalog_torque_max: None,
-            autocatalog_max_power_speed: None,
-
-            autocatalog_height: None,
-            autocatalog_length: None,
-            autocatalog_turning_diameter: None,
-            autocatalog_clearance: None,
-            autocatalog_wheelbase: None,
-            autocatalog_rear_track: None,
-            autocatalog_front_track: None,
-
-            autocatalog_trunk_volume: None,
-
-            autocatalog_fuel_tank_capacity: None,
-
-            autocatalog_fuel_consumption_city: None,
-            autocatalog_fuel_consumption_highway: None,
-            autocatalog_fuel_consumption_mixed: None,
-
-            autocatalog_environmental_class: None,
-
-            autocatalog_rear_breaks: None,
-            autocatalog_front_breaks: None,
-
-            autocatalog_rear_tire_dimension: None,
-            autocatalog_front_tire_dimension: None,
-
-            autocatalog_rear_suspension: None,
-            autocatalog_front_suspension: None,
-
-            autocatalog_world_premier: None,
-            autocatalog_pending_update: None,
-            autocatalog_width_with_mirrors: None,
-            autocatalog_rear_disc_dimension: None,
-            autocatalog_front_disc_dimension: None,
-        })
+            time,
+            views_total,
+            views_today,
+            images_qt,
+            images,
+            has_video: Some(has_video),
+            extra: if extra.0.is_empty() { None } else { Some(extra) },
+            ..Default::default()
+        };
+        if let Some(params) = firebase_params {
+            super::mapping::current().apply(&params, &mut ret, lenient)?;
+        }
+        Ok(ret)
+    }
+    // Элемент массива images - это объект вида { "640x480": url, "1280x960": url, .. }
+    // Выбираем ссылку на изображение наибольшего размера
+    fn largest_image_url(image: &Json) -> Result<String> {
+        lazy_static! {
+            static ref RE: Regex = Regex::new(r"^(\d+)x(\d+)$").unwrap();
+        }
+        let mut ret: Option<(u64, String)> = None;
+        for (key, val) in image.iter_map()? {
+            let area = match RE.captures(key) {
+                Some(caps) => caps[1].parse::<u64>()? * caps[2].parse::<u64>()?,
+                None => bail!("unexpected {}: {}", val.path, val.value),
+            };
+            let is_larger = match &ret {
+                None => true,
+                Some((max_area, _)) => area > *max_area,
+            };
+            if is_larger {
+                ret = Some((area, val.as_string()?));
+            }
+        }
+        match ret {
+            Some((_, url)) => Ok(url),
+            None => bail!("{} expected to be a non-empty Object", image.path),
+        }
     }
 }
 
@@ -611,6 +507,7 @@ mod tests {
     use super::*;
 
     use tokio::fs::File;
+    use tokio::prelude::*;
 
     use std::path::Path;
     #[tokio::test]
@@ -624,9 +521,69 @@ mod tests {
         let mut contents = vec![];
         file.read_to_end(&mut contents).await?;
         let s = std::str::from_utf8(&contents)?;
-        let _ret = Fetched::parse(s.to_owned(), Url::parse("https://sob.ru")?).await?;
+        let _ret = Fetched::parse(s.to_owned(), Url::parse("https://sob.ru")?, false).await?;
         // info!("ret: {:#?}", ret);
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_parse_json_time_stats_images() -> Result<()> {
+        test_helper::init();
+
+        let json = Json::from_file("test_data/card.json").await?;
+        let record = Fetched::parse_json(&json, false)?;
+
+        assert_eq!(record.id, Some(1767797249));
+        assert_eq!(record.time, Some(Utc.timestamp_opt(1595158549, 0).unwrap()));
+        assert_eq!(record.views_total, Some(4153));
+        assert_eq!(record.views_today, Some(30));
+        assert_eq!(record.images_qt, Some(14));
+        let images = record.images.unwrap();
+        assert_eq!(images.0.len(), 14);
+        assert_eq!(images.0[0], "https://50.img.avito.st/1280x960/5871171150.jpg");
+        assert_eq!(images.0[13], "https://69.img.avito.st/1280x960/5871153269.jpg");
+        assert_eq!(record.has_video, Some(false));
+        assert_eq!(record.location_id, Some(637640));
+        assert_eq!((record.lat, record.lng), (Some(55.836197), Some(37.381365)));
+        assert_eq!(record.body_type, Some(vocab::BodyType::Suv));
+        assert_eq!(record.vehicle_transmission, Some(vocab::Transmission::Automatic));
+        assert_eq!(record.fuel_type, Some(vocab::FuelType::Petrol));
+        assert_eq!(record.condition, Some(vocab::Condition::NotDamaged));
+
+        let json = serde_json::to_string(&images)?;
+        let images_restore: Images = serde_json::from_str(&json)?;
+        assert_eq!(images_restore, images);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_parse_json_lenient() -> Result<()> {
+        test_helper::init();
+
+        let mut json = Json::from_file("test_data/card.json").await?;
+        if let Value::Object(map) = &mut json.value {
+            map.insert("newKey".to_owned(), serde_json::json!([1, 2]));
+            if let Some(Value::Object(map)) = map.get_mut("firebaseParams") {
+                map.insert("kolichestvo_mest".to_owned(), serde_json::json!("5"));
+            }
+        }
+
+        let err = Fetched::parse_json(&json, false).unwrap_err();
+        assert!(err.to_string().starts_with("unexpected "));
+
+        let record = Fetched::parse_json(&json, true)?;
+        assert_eq!(record.brand, Some("KIA".to_owned()));
+        let extra = record.extra.unwrap();
+        assert_eq!(extra.0.len(), 2);
+        assert_eq!(extra.0.get("newKey"), Some(&serde_json::json!([1, 2])));
+        assert_eq!(extra.0.get("firebaseParams.kolichestvo_mest"), Some(&serde_json::json!("5")));
+
+        let s = serde_json::to_string(&extra)?;
+        let extra_restore: Extra = serde_json::from_str(&s)?;
+        assert_eq!(extra_restore, extra);
+
+        Ok(())
+    }
 }
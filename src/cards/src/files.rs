@@ -0,0 +1,122 @@
+
+#[allow(unused_imports)]
+use log::{error, warn, info, debug, trace};
+#[allow(unused_imports)]
+use anyhow::{Result, Error, bail, anyhow, Context};
+
+use chrono::{DateTime, Utc};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::fetched::Fetched;
+use super::store::{CardStore, Version, Migrated};
+
+// ============================================================================
+// ============================================================================
+
+// Файл на карточку: <out_dir>/cards/<9 hex>/<7 hex>.json (см. file_spec). Истории не ведет
+pub struct Files {
+    out_dir: PathBuf,
+}
+
+impl Files {
+    pub fn new(out_dir: &Path) -> Self {
+        Self { out_dir: out_dir.to_owned() }
+    }
+    fn read(file_path: &Path) -> Result<Fetched> {
+        let contents = fs::read(file_path)?;
+        super::schema::decode(&contents).context(format!("{:?}", file_path))
+    }
+    fn for_each_file<F>(&self, mut f: F) -> Result<()>
+    where
+        F: FnMut(u64, PathBuf) -> Result<()>,
+    {
+        let cards_dir = self.out_dir.join("cards");
+        if !cards_dir.exists() {
+            return Ok(());
+        }
+        for entry in fs::read_dir(&cards_dir)? {
+            let dir_path = entry?.path();
+            let prefix = match dir_path.file_name().map(|s| s.to_string_lossy().to_string()) {
+                Some(s) if dir_path.is_dir() && s.len() == 9 => s,
+                _ => continue,
+            };
+            for entry in fs::read_dir(&dir_path)? {
+                let file_path = entry?.path();
+                if file_path.extension().map(|ext| ext == "json") != Some(true) {
+                    continue;
+                }
+                let stem = file_path.file_stem().unwrap().to_string_lossy().to_string();
+                match u64::from_str_radix(&format!("{}{}", prefix, stem), 16) {
+                    Ok(id) if stem.len() == 7 => f(id, file_path)?,
+                    _ => warn!("skipped file: {:?}", file_path),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl CardStore for Files {
+    fn exists(&self, id: u64) -> Result<bool> {
+        Ok(super::file_spec::get(&self.out_dir, id).exists())
+    }
+    fn get(&self, id: u64) -> Result<Option<Fetched>> {
+        let file_path = super::file_spec::get(&self.out_dir, id);
+        if !file_path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(Self::read(&file_path)?))
+    }
+    fn put(&self, id: u64, fetched: &Fetched) -> Result<()> {
+        let file_path = super::file_spec::get(&self.out_dir, id);
+        if let Some(dir_path) = file_path.parent() {
+            fs::create_dir_all(dir_path)?;
+        }
+        let json = super::schema::to_string_pretty(fetched)?;
+        fs::write(&file_path, json.as_bytes()).context(format!("{:?}", file_path))?;
+        Ok(())
+    }
+    fn iterate(&self, f: &mut dyn FnMut(u64, Fetched) -> Result<()>) -> Result<()> {
+        self.for_each_file(|id, file_path| f(id, Self::read(&file_path)?))
+    }
+    fn versions(&self, id: u64) -> Result<Vec<Version>> {
+        let file_path = super::file_spec::get(&self.out_dir, id);
+        if !file_path.exists() {
+            return Ok(vec![]);
+        }
+        let saved_at = fs::metadata(&file_path)?.modified().ok().map(DateTime::<Utc>::from);
+        Ok(vec![Version { saved_at, fetched: Self::read(&file_path)? }])
+    }
+    fn ids(&self) -> Result<Vec<u64>> {
+        let mut ids = Vec::new();
+        self.for_each_file(|id, _file_path| {
+            ids.push(id);
+            Ok(())
+        })?;
+        ids.sort();
+        Ok(ids)
+    }
+    fn len(&self) -> Result<usize> {
+        let mut qt = 0;
+        self.for_each_file(|_id, _file_path| {
+            qt += 1;
+            Ok(())
+        })?;
+        Ok(qt)
+    }
+    fn migrate(&self) -> Result<Migrated> {
+        let mut ret = Migrated::default();
+        self.for_each_file(|_id, file_path| {
+            ret.total += 1;
+            let contents = fs::read(&file_path)?;
+            if super::schema::version_of(&contents).context(format!("{:?}", file_path))? < super::schema::VERSION {
+                let fetched = super::schema::decode(&contents).context(format!("{:?}", file_path))?;
+                fs::write(&file_path, super::schema::to_string_pretty(&fetched)?.as_bytes()).context(format!("{:?}", file_path))?;
+                ret.migrated += 1;
+            }
+            Ok(())
+        })?;
+        Ok(ret)
+    }
+}
@@ -8,6 +8,7 @@ use anyhow::{Result, Error, bail, anyhow, Context};
 
 use std::time::Instant;
 use std::path::Path;
+use std::sync::Arc;
 
 #[macro_use] extern crate lazy_static;
 
@@ -19,13 +20,23 @@ use futures::{
     },
 };
 
-mod file_spec;
+pub mod file_spec;
 mod check;
 mod save;
 mod fetch;
 mod fetched;
-
-pub use fetched::{Fetched, Record};
+pub mod mapping;
+pub mod schema;
+mod drift;
+pub mod quarantine;
+pub mod store;
+pub mod files;
+pub mod pack;
+pub mod sqlite;
+
+pub use fetched::{Fetched, Record, Images, Extra};
+pub use store::CardStore;
+pub use drift::{Drift, DriftItem};
 
 // ============================================================================
 // ============================================================================
@@ -36,6 +47,10 @@ pub struct Arg<'a> {
     pub thread_limit_network: usize,
     pub thread_limit_file: usize,
     pub client_provider: client::Provider,
+    pub lenient: bool,
+    pub store: Arc<dyn CardStore>,
+    // true - карточки запрашиваются, даже если уже есть в хранилище (новая версия заменяет прежнюю)
+    pub refetch: bool,
     // pub retry_count: usize,
 }
 
@@ -46,6 +61,7 @@ macro_rules! push_fut_fetch {
             client: $client,
             auth: $auth.key().await?,
             id: id,
+            lenient: $arg.lenient,
             // retry_count: $arg.retry_count,
         });
         let fut = op(arg);
@@ -55,11 +71,12 @@ macro_rules! push_fut_fetch {
 }
 
 macro_rules! push_fut_save {
-    ($fut_queue: expr, $fetched: expr, $id: expr, $out_dir: expr) => {
+    ($fut_queue: expr, $fetched: expr, $id: expr, $out_dir: expr, $store: expr) => {
         let arg = OpArg::Save (save::Arg {
             id: $id,
             fetched: $fetched,
-            out_dir: $out_dir
+            out_dir: $out_dir,
+            store: $store.clone(),
         });
         let fut = op(arg);
         $fut_queue.push(fut);
@@ -67,10 +84,11 @@ macro_rules! push_fut_save {
 }
 
 macro_rules! push_fut_check {
-    ($fut_queue: expr, $id: expr, $out_dir: expr) => {
+    ($fut_queue: expr, $id: expr, $store: expr, $refetch: expr) => {
         let arg = OpArg::Check (check::Arg {
             id: $id,
-            out_dir: $out_dir
+            store: $store.clone(),
+            refetch: $refetch,
         });
         let fut = op(arg);
         $fut_queue.push(fut);
@@ -102,6 +120,8 @@ pub struct CallbackArg {
 
 pub struct Ret {
     pub received_qt: usize,
+    pub drift: Drift,
+    pub not_found: Vec<u64>, // карточки, на запрос которых получен 404
 }
 
 const CALLBACK_THROTTLE: u128 = 100;
@@ -123,12 +143,14 @@ where
     let mut fut_queue = FuturesUnordered::new();
     while id_i < arg.thread_limit_file && id_i < ids_len {
         let id = *ids[id_i];
-        push_fut_check!(fut_queue, id, arg.out_dir);
+        push_fut_check!(fut_queue, id, arg.store, arg.refetch);
         id_i += 1;
     }
     let mut used_network_threads = 0;
 
     let mut received_qt = 0;
+    let mut drift = Drift::new();
+    let mut not_found = Vec::new();
     let mut elapsed_qt = 0;
     let mut remained_qt = 0;
     let mut last_callback = Instant::now();
@@ -173,7 +195,7 @@ where
                                 }
                                 if id_i < ids_len {
                                     let id = *ids[id_i];
-                                    push_fut_check!(fut_queue, id, arg.out_dir);
+                                    push_fut_check!(fut_queue, id, arg.store, arg.refetch);
                                     id_i += 1;
                                 }
                             },
@@ -195,7 +217,14 @@ where
                                     None
                                 };
                                 received_qt += 1;
-                                push_fut_save!(fut_queue, ret.fetched, ret.id, arg.out_dir);
+                                match &ret.fetched {
+                                    Fetched::Record(record) => if let Some(extra) = &record.extra {
+                                        drift.adopt(ret.id, extra);
+                                    },
+                                    Fetched::NotFound => not_found.push(ret.id),
+                                    _ => {},
+                                }
+                                push_fut_save!(fut_queue, ret.fetched, ret.id, arg.out_dir, arg.store);
                                 if ids_non_existent_i < ids_non_existent.len() {
                                     let client = ret.client;
                                     push_fut_fetch!(fut_queue, client, auth, arg, ids_non_existent, ids_non_existent_i);
@@ -218,13 +247,13 @@ where
         }
     }
     
-    Ok(Ret{received_qt})
+    Ok(Ret{received_qt, drift, not_found})
 }
 
 enum OpArg<'a> {
     Fetch(fetch::Arg),
     Save(save::Arg<'a>),
-    Check(check::Arg<'a>),
+    Check(check::Arg),
 }
 
 enum OpRet {
@@ -301,6 +330,7 @@ mod tests {
             ids.insert(id);
         }
         let out_dir = &Path::new("out_test");
+        let store = Arc::new(files::Files::new(out_dir));
         let settings_rmq = rmq::Settings::new(std::path::Path::new("../../cnf/rmq/bikuzin18.toml"))?;
         let pool = rmq::get_pool(settings_rmq)?;
         let arg = Arg {
@@ -309,6 +339,9 @@ mod tests {
             thread_limit_network: 1,
             thread_limit_file: 12,
             client_provider: client::Provider::new(client::Kind::ViaProxy(pool, "cards".to_owned())),
+            lenient: false,
+            store,
+            refetch: false,
             // retry_count: 3,
         };
         let mut auth = auth::Lazy::new(auth::Arg::new_ready("af0deccbgcgidddjgnvljitntccdduijhdinfgjgfjir".to_owned()));
@@ -0,0 +1,351 @@
+#[allow(unused_imports)]
+use log::{error, warn, info, debug, trace};
+#[allow(unused_imports)]
+use anyhow::{Result, Error, bail, anyhow, Context};
+
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+use tokio::fs::File;
+use tokio::prelude::*;
+
+use json::Json;
+use super::fetched::Record;
+
+// ============================================================================
+// ============================================================================
+
+// Разбор firebaseParams карточки по описанию (см. firebase_params.toml): ключ источника -> поле записи,
+// вид значения, таблица замен, обязательность. Описание загружается при запуске (set),
+// так что новый или переименованный ключ Avito не требует пересборки
+
+const DEFAULT: &str = include_str!("../firebase_params.toml");
+
+// Вид значения в описании
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Type {
+    #[default]
+    String,
+    Int,
+    Digits,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Param {
+    pub field: String,
+    #[serde(default, rename = "type")]
+    pub type_: Type,
+    #[serde(default)]
+    pub normalize: BTreeMap<String, String>,
+    #[serde(default)]
+    pub required: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Mapping {
+    #[serde(default)]
+    pub skip: BTreeSet<String>,
+    #[serde(default)]
+    pub params: BTreeMap<String, Param>,
+}
+
+// ============================================================================
+
+// Вид поля записи: какие виды значений описания к нему применимы
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Str,
+    Int,
+    Vocab,
+}
+
+// Значение поля: если поле уже заполнено (например, description - и из карточки, и из firebaseParams;
+// item_price - из itemPrice и price) и значение другое, остается прежнее, с предупреждением
+trait Adopt {
+    fn adopt(&mut self, s: &str, path: &str) -> Result<()>;
+}
+
+fn adopt<T: PartialEq + std::fmt::Debug>(field: &mut Option<T>, val: T, path: &str) {
+    match field {
+        None => *field = Some(val),
+        Some(prev) => {
+            if *prev != val {
+                warn!("{} expected to be a {:?}, not {:?}", path, prev, val);
+            }
+        },
+    }
+}
+
+impl Adopt for Option<String> {
+    fn adopt(&mut self, s: &str, path: &str) -> Result<()> {
+        adopt(self, s.to_owned(), path);
+        Ok(())
+    }
+}
+
+macro_rules! adopt_int {
+    ($($t: ty),*) => {
+        $(
+            impl Adopt for Option<$t> {
+                fn adopt(&mut self, s: &str, path: &str) -> Result<()> {
+                    let val = s.parse::<$t>()
+                        .context(format!("{} expected to be a String parseable to {}, but: {:?}", path, stringify!($t), s))?;
+                    adopt(self, val, path);
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+adopt_int!(u8, u16, u64);
+
+// Неизвестное справочное значение сохраняется как есть, с предупреждением
+macro_rules! adopt_vocab {
+    ($($t: ty),*) => {
+        $(
+            impl Adopt for Option<$t> {
+                fn adopt(&mut self, s: &str, path: &str) -> Result<()> {
+                    let val = <$t>::parse(s);
+                    if val.is_other() {
+                        warn!("{}: {:?}", path, s);
+                    }
+                    adopt(self, val, path);
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+adopt_vocab!(vocab::BodyType, vocab::Drive, vocab::Transmission, vocab::FuelType, vocab::Condition);
+
+// Поля записи, которые можно заполнить из firebaseParams: имя (как в cards::Record) и вид
+macro_rules! targets {
+    ($($name: ident: $kind: ident),* $(,)?) => {
+        const TARGETS: &[(&str, Kind)] = &[
+            $((stringify!($name), Kind::$kind)),*
+        ];
+        fn set_field(record: &mut Record, field: &str, s: &str, path: &str) -> Result<()> {
+            match field {
+                $(stringify!($name) => record.$name.adopt(s, path),)*
+                _ => bail!("{}: no field {:?}", path, field),
+            }
+        }
+    };
+}
+
+targets! {
+    body_type: Vocab,
+    brand: Str,
+    color: Str,
+    fuel_type: Vocab,
+    name: Str,
+    number_of_doors: Int,
+    production_date: Int,
+    vehicle_transmission: Vocab,
+    engine_displacement: Str,
+    engine_power: Str,
+    description: Str,
+    mileage: Int,
+    drive: Vocab,
+    steering_wheel: Str,
+    condition: Vocab,
+    owners: Str,
+    power_windows: Str,
+    power_steering: Str,
+    audio_system: Str,
+    headlights: Str,
+    climate_control: Str,
+    interior: Str,
+    rims: Str,
+    item_price: Int,
+    complectation: Str,
+    modification: Str,
+    generation: Str,
+    type_of_trade: Str,
+}
+
+// Значение в Record::extra вместо поля
+const EXTRA: &str = "extra";
+
+// ============================================================================
+
+impl Mapping {
+    pub async fn from_file(file_path: &Path) -> Result<Self> {
+        let mut file = File::open(file_path).await.context(format!("{:?}", file_path))?;
+        let mut content = vec![];
+        file.read_to_end(&mut content).await?;
+        let content = std::str::from_utf8(&content)?;
+        Self::from_str(content).context(format!("{:?}", file_path))
+    }
+    fn check(&self) -> Result<()> {
+        for (key, param) in self.params.iter() {
+            if self.skip.contains(key) {
+                bail!("{:?} is both in skip and params", key);
+            }
+            if param.field == EXTRA {
+                continue;
+            }
+            let kind = match TARGETS.iter().find(|(name, _)| *name == param.field) {
+                Some((_, kind)) => *kind,
+                None => bail!("params.{}: unknown field {:?}, expected one of: {}, {}", key, param.field, EXTRA,
+                    TARGETS.iter().map(|(name, _)| *name).collect::<Vec<&str>>().join(", ")),
+            };
+            let is_int = param.type_ == Type::Int || param.type_ == Type::Digits;
+            if is_int != (kind == Kind::Int) {
+                bail!("params.{}: type {:?} is not applicable to field {:?}", key, param.type_, param.field);
+            }
+        }
+        Ok(())
+    }
+    // Заполняет record из firebaseParams карточки
+    pub fn apply(&self, params: &Json, record: &mut Record, lenient: bool) -> Result<()> {
+        let mut extra = record.extra.take().unwrap_or_default();
+        let mut found = BTreeSet::new();
+        for (key, val) in params.iter_map()? {
+            if self.skip.contains(key) {
+                continue;
+            }
+            let path = val.path.to_string();
+            let param = match self.params.get(key) {
+                Some(param) => param,
+                None => {
+                    if !lenient {
+                        bail!("unexpected {}: {}", path, val.value);
+                    }
+                    extra.0.insert(format!("firebaseParams.{}", key), val.value.clone());
+                    continue;
+                },
+            };
+            found.insert(key);
+            let s = match &val.value {
+                Value::String(s) => s.to_owned(),
+                Value::Number(n) => n.to_string(),
+                _ => bail!("{} expected to be a String or Number, but: {}", path, val.value),
+            };
+            let s = param.normalize.get(&s).cloned().unwrap_or(s);
+            let s = match param.type_ {
+                Type::Digits => s.chars().filter(|c| c.is_ascii_digit()).collect(),
+                _ => s,
+            };
+            if param.field == EXTRA {
+                extra.0.insert(format!("firebaseParams.{}", key), Value::String(s));
+            } else {
+                set_field(record, &param.field, &s, &path)?;
+            }
+        }
+        for (key, param) in self.params.iter() {
+            if param.required && !found.contains(key.as_str()) {
+                bail!("{} expected to have key {:?}", params.path, key);
+            }
+        }
+        record.extra = if extra.0.is_empty() { None } else { Some(extra) };
+        Ok(())
+    }
+}
+
+impl FromStr for Mapping {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let ret: Self = toml::from_str(s)?;
+        ret.check()?;
+        Ok(ret)
+    }
+}
+
+impl Default for Mapping {
+    fn default() -> Self {
+        Self::from_str(DEFAULT).expect("firebase_params.toml")
+    }
+}
+
+lazy_static! {
+    static ref CURRENT: RwLock<Arc<Mapping>> = RwLock::new(Arc::new(Mapping::default()));
+}
+
+// Описание для всех последующих разборов карточек
+pub fn set(mapping: Mapping) {
+    *CURRENT.write().unwrap() = Arc::new(mapping);
+}
+
+pub fn current() -> Arc<Mapping> {
+    CURRENT.read().unwrap().clone()
+}
+
+// ============================================================================
+// ============================================================================
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use log::{error, warn, info, debug, trace};
+    use super::*;
+
+    #[tokio::test]
+    async fn test_mapping() -> Result<()> {
+        test_helper::init();
+
+        let mapping = Mapping::default();
+        assert_eq!(mapping.params["mileage"].type_, Type::Digits);
+
+        let json = Json::from_file("test_data/card.json").await?;
+        let params = json.get([json::By::key("firebaseParams")])?;
+
+        let mapping = Mapping::from_str(r#"
+            skip = ["itemID", "withDelivery", "vehicle_type", "type_of_trade", "capacity", "color", "wheel", "engine",
+                "year", "engine_type", "drive", "transmission", "modification", "complectation", "generation",
+                "condition", "vladeltsev_po_pts", "model", "isPersonalAuto", "isNewAuto", "userAuth", "isShop",
+                "isASDClient", "vertical", "categoryId", "categorySlug", "microCategoryId", "locationId"]
+            [params.brand]
+            field = "brand"
+            normalize = { "KIA" = "Kia" }
+            [params.body_type]
+            field = "body_type"
+            [params.mileage]
+            field = "mileage"
+            type = "digits"
+            [params.itemPrice]
+            field = "item_price"
+            type = "int"
+            required = true
+            [params.kolichestvo_dverey]
+            field = "extra"
+        "#)?;
+        let mut record = Record::default();
+        mapping.apply(&params, &mut record, false)?;
+        assert_eq!(record.brand.as_deref(), Some("Kia"));
+        assert_eq!(record.body_type, Some(vocab::BodyType::Suv));
+        assert_eq!(record.mileage, Some(72_000));
+        assert_eq!(record.item_price, Some(1_180_000));
+        assert_eq!(record.number_of_doors, None);
+        assert_eq!(record.extra.unwrap().0["firebaseParams.kolichestvo_dverey"], Value::String("5".to_owned()));
+
+        let mapping = Mapping::from_str(r#"
+            [params.itemPrice]
+            field = "item_price"
+            type = "int"
+        "#)?;
+        assert!(mapping.apply(&params, &mut Record::default(), false).unwrap_err().to_string().starts_with("unexpected"));
+        let mut record = Record::default();
+        mapping.apply(&params, &mut record, true)?;
+        assert_eq!(record.item_price, Some(1_180_000));
+        assert_eq!(record.extra.unwrap().0["firebaseParams.brand"], Value::String("KIA".to_owned()));
+
+        let err = |s: &str| Mapping::from_str(s).unwrap_err().to_string();
+        assert!(err("[params.year]\nfield = \"production_date\"").contains("is not applicable"));
+        assert!(err("[params.year]\nfield = \"year\"").contains("unknown field"));
+        assert!(err("[params.brand]\nfield = \"brand\"\nrequred = true").contains("unknown field"));
+        let mapping = Mapping::from_str("[params.doors]\nfield = \"number_of_doors\"\ntype = \"int\"\nrequired = true")?;
+        assert!(mapping.apply(&params, &mut Record::default(), true).unwrap_err().to_string().contains("\"doors\""));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,336 @@
+
+#[allow(unused_imports)]
+use log::{error, warn, info, debug, trace};
+#[allow(unused_imports)]
+use anyhow::{Result, Error, bail, anyhow, Context};
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use chrono::{Utc, TimeZone};
+
+use super::fetched::Fetched;
+use super::store::{CardStore, Version, Migrated};
+
+// ============================================================================
+// ============================================================================
+
+// Упакованное хранилище карточек: <out_dir>/pack/
+//   segment.NNNNNN.zst - сегменты, в которые дописываются сжатые zstd карточки (по кадру на карточку)
+//   index              - дописываемый индекс, строка на запись: "<id> <segment> <offset> <len> <unix time>"
+// Повторная запись карточки дописывает новую копию: действует последняя, предыдущие остаются версиями
+
+const SEGMENT_SIZE_MAX: u64 = 64 * 1024 * 1024;
+const COMPRESSION_LEVEL: i32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Entry {
+    segment: u32,
+    offset: u64,
+    len: u64,
+    saved_at: Option<i64>, // в индексах, записанных до появления версий, времени нет
+}
+
+pub struct Pack {
+    dir: PathBuf,
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    index: HashMap<u64, Vec<Entry>>,
+    index_file: File,
+    segment: u32,
+    segment_file: File,
+    segment_len: u64,
+}
+
+pub fn dir(out_dir: &Path) -> PathBuf {
+    out_dir.join("pack")
+}
+
+fn segment_file_spec(dir: &Path, segment: u32) -> PathBuf {
+    dir.join(format!("segment.{:06}.zst", segment))
+}
+
+fn open_append(file_path: &Path) -> Result<File> {
+    OpenOptions::new().create(true).append(true).open(file_path).context(format!("{:?}", file_path))
+}
+
+impl Pack {
+    pub fn open(out_dir: &Path) -> Result<Self> {
+        Self::open_dir(dir(out_dir))
+    }
+
+    fn open_dir(dir: PathBuf) -> Result<Self> {
+        fs::create_dir_all(&dir).context(format!("{:?}", dir))?;
+
+        let index_file_spec = dir.join("index");
+        let mut index = HashMap::new();
+        let mut segment = 0;
+        let mut tail_broken = false;
+        if index_file_spec.exists() {
+            let contents = fs::read_to_string(&index_file_spec)?;
+            // строка без перевода строки в конце не дописана при аварийном завершении
+            tail_broken = !contents.is_empty() && !contents.ends_with('\n');
+            let complete = if tail_broken {
+                let len = contents.rfind('\n').map(|i| i + 1).unwrap_or(0);
+                warn!("{:?}: skipped incomplete line: {:?}", index_file_spec, &contents[len..]);
+                &contents[..len]
+            } else {
+                &contents[..]
+            };
+            for (i, line) in complete.lines().enumerate() {
+                let parsed = (|| -> Option<(u64, Entry)> {
+                    let mut parts = line.split(' ');
+                    let id = parts.next()?.parse().ok()?;
+                    let segment = parts.next()?.parse().ok()?;
+                    let offset = parts.next()?.parse().ok()?;
+                    let len = parts.next()?.parse().ok()?;
+                    let saved_at = match parts.next() {
+                        None => None,
+                        Some(s) => Some(s.parse().ok()?),
+                    };
+                    Some((id, Entry { segment, offset, len, saved_at }))
+                })();
+                match parsed {
+                    Some((id, entry)) => {
+                        if entry.segment > segment {
+                            segment = entry.segment;
+                        }
+                        index.entry(id).or_insert_with(Vec::new).push(entry);
+                    },
+                    None => warn!("{:?}:{}: skipped malformed line: {:?}", index_file_spec, i + 1, line),
+                }
+            }
+        }
+        let mut index_file = open_append(&index_file_spec)?;
+        if tail_broken {
+            // чтобы следующая запись начиналась с новой строки
+            writeln!(index_file)?;
+        }
+        let segment_file = open_append(&segment_file_spec(&dir, segment))?;
+        let segment_len = segment_file.metadata()?.len();
+
+        Ok(Self {
+            dir,
+            inner: Mutex::new(Inner { index, index_file, segment, segment_file, segment_len }),
+        })
+    }
+
+    fn last(&self, id: u64) -> Option<Entry> {
+        self.inner.lock().unwrap().index.get(&id).and_then(|entries| entries.last().cloned())
+    }
+
+    fn read(&self, id: u64, entry: Entry) -> Result<Fetched> {
+        let file_path = segment_file_spec(&self.dir, entry.segment);
+        let mut file = File::open(&file_path).context(format!("{:?}", file_path))?;
+        file.seek(SeekFrom::Start(entry.offset))?;
+        let mut compressed = vec![0u8; entry.len as usize];
+        file.read_exact(&mut compressed)?;
+        decode(&compressed).context(format!("{:?}: {}", file_path, id))
+    }
+}
+
+impl CardStore for Pack {
+    fn exists(&self, id: u64) -> Result<bool> {
+        Ok(self.inner.lock().unwrap().index.contains_key(&id))
+    }
+
+    fn get(&self, id: u64) -> Result<Option<Fetched>> {
+        match self.last(id) {
+            None => Ok(None),
+            Some(entry) => Ok(Some(self.read(id, entry)?)),
+        }
+    }
+
+    fn put(&self, id: u64, fetched: &Fetched) -> Result<()> {
+        let compressed = encode(fetched)?;
+        self.inner.lock().unwrap().append(&self.dir, id, &compressed, Some(Utc::now().timestamp()))
+    }
+
+    // Каждый сегмент читается целиком один раз
+    fn iterate(&self, f: &mut dyn FnMut(u64, Fetched) -> Result<()>) -> Result<()> {
+        let mut entries: Vec<(u64, Entry)> = self.inner.lock().unwrap().index.iter()
+            .filter_map(|(id, entries)| entries.last().map(|entry| (*id, *entry)))
+            .collect();
+        entries.sort_by_key(|(_, entry)| (entry.segment, entry.offset));
+        let mut segment: Option<(u32, Vec<u8>)> = None;
+        for (id, entry) in entries {
+            if segment.as_ref().map(|(n, _)| *n) != Some(entry.segment) {
+                let file_path = segment_file_spec(&self.dir, entry.segment);
+                let contents = fs::read(&file_path).context(format!("{:?}", file_path))?;
+                segment = Some((entry.segment, contents));
+            }
+            let contents = &segment.as_ref().unwrap().1;
+            let compressed = &contents[entry.offset as usize..(entry.offset + entry.len) as usize];
+            f(id, decode(compressed).context(format!("segment {}: {}", entry.segment, id))?)?;
+        }
+        Ok(())
+    }
+
+    fn versions(&self, id: u64) -> Result<Vec<Version>> {
+        let entries = self.inner.lock().unwrap().index.get(&id).cloned().unwrap_or_default();
+        let mut ret = Vec::new();
+        for entry in entries {
+            ret.push(Version {
+                saved_at: entry.saved_at.and_then(|secs| Utc.timestamp_opt(secs, 0).single()),
+                fetched: self.read(id, entry)?,
+            });
+        }
+        Ok(ret)
+    }
+
+    fn ids(&self) -> Result<Vec<u64>> {
+        let mut ids: Vec<u64> = self.inner.lock().unwrap().index.keys().cloned().collect();
+        ids.sort();
+        Ok(ids)
+    }
+
+    fn len(&self) -> Result<usize> {
+        Ok(self.inner.lock().unwrap().index.len())
+    }
+
+    // Все версии переписываются в соседний каталог в прежнем порядке и с прежним временем записи,
+    // затем каталоги меняются местами
+    fn migrate(&self) -> Result<Migrated> {
+        let mut inner = self.inner.lock().unwrap();
+        let mut entries: Vec<(u64, Entry)> = inner.index.iter()
+            .flat_map(|(id, entries)| entries.iter().map(move |entry| (*id, *entry)))
+            .collect();
+        entries.sort_by_key(|(_, entry)| (entry.segment, entry.offset));
+        let mut ret = Migrated { total: entries.len(), migrated: 0 };
+
+        let migrate_dir = self.dir.with_extension("migrate");
+        if migrate_dir.exists() {
+            fs::remove_dir_all(&migrate_dir)?;
+        }
+        let target = Self::open_dir(migrate_dir.clone())?;
+        let mut target_inner = target.inner.lock().unwrap();
+        let mut segment: Option<(u32, Vec<u8>)> = None;
+        for (id, entry) in entries {
+            if segment.as_ref().map(|(n, _)| *n) != Some(entry.segment) {
+                let file_path = segment_file_spec(&self.dir, entry.segment);
+                let contents = fs::read(&file_path).context(format!("{:?}", file_path))?;
+                segment = Some((entry.segment, contents));
+            }
+            let contents = &segment.as_ref().unwrap().1;
+            let compressed = &contents[entry.offset as usize..(entry.offset + entry.len) as usize];
+            let json = zstd::decode_all(compressed)?;
+            if super::schema::version_of(&json).context(format!("segment {}: {}", entry.segment, id))? < super::schema::VERSION {
+                let fetched = super::schema::decode(&json).context(format!("segment {}: {}", entry.segment, id))?;
+                target_inner.append(&migrate_dir, id, &encode(&fetched)?, entry.saved_at)?;
+                ret.migrated += 1;
+            } else {
+                target_inner.append(&migrate_dir, id, compressed, entry.saved_at)?;
+            }
+        }
+        drop(target_inner);
+        drop(target);
+        if ret.migrated == 0 {
+            fs::remove_dir_all(&migrate_dir)?;
+            return Ok(ret);
+        }
+
+        let old_dir = self.dir.with_extension("old");
+        if old_dir.exists() {
+            fs::remove_dir_all(&old_dir)?;
+        }
+        fs::rename(&self.dir, &old_dir).context(format!("{:?}", self.dir))?;
+        fs::rename(&migrate_dir, &self.dir).context(format!("{:?}", migrate_dir))?;
+        fs::remove_dir_all(&old_dir)?;
+        *inner = Self::open_dir(self.dir.clone())?.inner.into_inner().unwrap();
+        Ok(ret)
+    }
+}
+
+impl Inner {
+    fn append(&mut self, dir: &Path, id: u64, compressed: &[u8], saved_at: Option<i64>) -> Result<()> {
+        if self.segment_len > 0 && self.segment_len + compressed.len() as u64 > SEGMENT_SIZE_MAX {
+            self.segment += 1;
+            self.segment_file = open_append(&segment_file_spec(dir, self.segment))?;
+            self.segment_len = 0;
+        }
+        let entry = Entry {
+            segment: self.segment,
+            offset: self.segment_len,
+            len: compressed.len() as u64,
+            saved_at,
+        };
+        self.segment_file.write_all(compressed)?;
+        self.segment_len += entry.len;
+        match entry.saved_at {
+            Some(saved_at) => writeln!(self.index_file, "{} {} {} {} {}", id, entry.segment, entry.offset, entry.len, saved_at)?,
+            None => writeln!(self.index_file, "{} {} {} {}", id, entry.segment, entry.offset, entry.len)?,
+        }
+        self.index.entry(id).or_default().push(entry);
+        Ok(())
+    }
+}
+
+fn encode(fetched: &Fetched) -> Result<Vec<u8>> {
+    let json = super::schema::to_vec(fetched)?;
+    Ok(zstd::encode_all(&json[..], COMPRESSION_LEVEL)?)
+}
+
+fn decode(compressed: &[u8]) -> Result<Fetched> {
+    let json = zstd::decode_all(compressed)?;
+    super::schema::decode(&json)
+}
+
+// ============================================================================
+// ============================================================================
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use log::{error, warn, info, debug, trace};
+    use super::*;
+
+    #[test]
+    fn test_pack_index() -> Result<()> {
+        test_helper::init();
+
+        let out_dir = Path::new("out_test/pack_test");
+        if out_dir.exists() {
+            fs::remove_dir_all(out_dir)?;
+        }
+        {
+            let pack = Pack::open(out_dir)?;
+            pack.put(42, &Fetched::NotFound)?;
+        }
+        // строка индекса без времени (до появления версий) и недописанная строка
+        let mut index_file = open_append(&dir(out_dir).join("index"))?;
+        let segment_len = fs::metadata(segment_file_spec(&dir(out_dir), 0))?.len();
+        writeln!(index_file, "43 0 0 {}", segment_len)?;
+        write!(index_file, "44 0")?;
+
+        let pack = Pack::open(out_dir)?;
+        assert_eq!(pack.len()?, 2);
+        assert!(matches!(pack.get(43)?, Some(Fetched::NotFound)));
+        assert_eq!(pack.versions(43)?[0].saved_at, None);
+        assert!(pack.versions(42)?[0].saved_at.is_some());
+        assert!(!pack.exists(44)?);
+        pack.put(45, &Fetched::NoText)?;
+
+        // карточка, записанная до появления версий схемы
+        let legacy = zstd::encode_all(r#"{"Record": {"id": 46, "bodyType": "Седан"}}"#.as_bytes(), COMPRESSION_LEVEL)?;
+        pack.inner.lock().unwrap().append(&pack.dir, 46, &legacy, None)?;
+        assert_eq!(pack.migrate()?, Migrated { total: 4, migrated: 1 });
+        assert!(!dir(out_dir).with_extension("migrate").exists());
+
+        let pack = Pack::open(out_dir)?;
+        assert!(pack.exists(45)?);
+        assert_eq!(pack.versions(43)?[0].saved_at, None);
+        match pack.get(46)? {
+            Some(Fetched::Record(record)) => assert_eq!(record.body_type, Some(vocab::BodyType::Sedan)),
+            _ => unreachable!(),
+        }
+        assert_eq!(pack.migrate()?, Migrated { total: 4, migrated: 0 });
+
+        fs::remove_dir_all(out_dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,206 @@
+
+#[allow(unused_imports)]
+use log::{error, warn, info, debug, trace};
+#[allow(unused_imports)]
+use anyhow::{Result, Error, bail, anyhow, Context};
+
+use serde::{
+    Serialize,
+    Deserialize,
+};
+use serde_json::Value;
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use tokio::fs::{self, File};
+use tokio::prelude::*;
+
+use super::fetched::Fetched;
+use super::store::CardStore;
+
+// ============================================================================
+// ============================================================================
+
+// Карточка, которую не удалось разобрать: хранится в <out_dir>/quarantine/<id>.json
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Quarantined {
+    pub id: u64,
+    pub error: String,
+    pub path: Option<String>, // json-путь, на котором споткнулся разбор
+    pub json: Value,
+}
+
+pub fn dir(out_dir: &Path) -> PathBuf {
+    out_dir.join("quarantine")
+}
+
+pub fn file_spec(out_dir: &Path, id: u64) -> PathBuf {
+    dir(out_dir).join(format!("{}.json", id))
+}
+
+// Ошибки разбора начинаются с json-пути вида "<источник>"."firebaseParams"."key"[0];
+// источник (url карточки) отбрасываем, чтобы путь был общим для всех карточек
+pub fn json_path_of(error: &str) -> Option<String> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r#"^(?:unexpected )?"(?:[^"\\]|\\.)*"((?:\."(?:[^"\\]|\\.)*"|\[\d+\])+)"#).unwrap();
+    }
+    RE.captures(error).map(|caps| caps[1].to_owned())
+}
+
+pub async fn put(out_dir: &Path, id: u64, json: &Value, error: &str) -> Result<()> {
+    let file_path = file_spec(out_dir, id);
+    if let Some(dir_path) = file_path.parent() {
+        fs::create_dir_all(dir_path).await?;
+    }
+    let quarantined = Quarantined {
+        id,
+        error: error.to_owned(),
+        path: json_path_of(error),
+        json: json.clone(),
+    };
+    let mut file = File::create(&file_path).await.context(format!("file_path: {:?}", file_path))?;
+    let s = serde_json::to_string_pretty(&quarantined)?;
+    file.write_all(s.as_bytes()).await?;
+    Ok(())
+}
+
+pub async fn list(out_dir: &Path) -> Result<Vec<Quarantined>> {
+    let mut ret = Vec::new();
+    let dir_path = dir(out_dir);
+    let mut read_dir = match fs::read_dir(&dir_path).await {
+        Ok(read_dir) => read_dir,
+        Err(err) => match err.kind() {
+            std::io::ErrorKind::NotFound => return Ok(ret),
+            _ => return Err(Error::new(err).context(format!("{:?}", dir_path))),
+        },
+    };
+    while let Some(entry) = read_dir.next_entry().await? {
+        let file_path = entry.path();
+        if file_path.extension().map(|ext| ext == "json") != Some(true) {
+            continue;
+        }
+        let mut file = File::open(&file_path).await?;
+        let mut contents = vec![];
+        file.read_to_end(&mut contents).await?;
+        let s = std::str::from_utf8(&contents)?;
+        let quarantined: Quarantined = serde_json::from_str(s).context(format!("{:?}", file_path))?;
+        ret.push(quarantined);
+    }
+    ret.sort_by_key(|item| item.id);
+    Ok(ret)
+}
+
+// Группировка по json-пути ошибки: путь -> id карточек
+pub fn group(items: &[Quarantined]) -> BTreeMap<String, Vec<u64>> {
+    let mut ret: BTreeMap<String, Vec<u64>> = BTreeMap::new();
+    for item in items {
+        let key = match &item.path {
+            Some(path) => path.to_owned(),
+            None => item.error.to_owned(),
+        };
+        ret.entry(key).or_default().push(item.id);
+    }
+    ret
+}
+
+pub struct ReplayRet {
+    pub fixed: Vec<u64>,
+    pub failed: Vec<Quarantined>,
+}
+
+// Повторный разбор карточек из карантина текущим парсером.
+// Разобранные записываются на место карточки и удаляются из карантина,
+// у оставшихся обновляется текст ошибки
+pub async fn replay(out_dir: &Path, lenient: bool, store: &Arc<dyn CardStore>) -> Result<ReplayRet> {
+    let mut fixed = Vec::new();
+    let mut failed = Vec::new();
+    for item in list(out_dir).await? {
+        let json = json::Json::new(item.json, json::JsonSource::Name(item.id.to_string()));
+        match Fetched::parse_json(&json, lenient) {
+            Ok(record) => {
+                let store = store.clone();
+                let id = item.id;
+                tokio::task::spawn_blocking(move || store.put(id, &Fetched::Record(record))).await??;
+                fs::remove_file(file_spec(out_dir, item.id)).await?;
+                fixed.push(item.id);
+            },
+            Err(err) => {
+                let error = err.to_string();
+                put(out_dir, item.id, &json.value, &error).await?;
+                failed.push(Quarantined {
+                    id: item.id,
+                    path: json_path_of(&error),
+                    error,
+                    json: json.value,
+                });
+            },
+        }
+    }
+    Ok(ReplayRet { fixed, failed })
+}
+
+// ============================================================================
+// ============================================================================
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use log::{error, warn, info, debug, trace};
+    use super::*;
+
+    #[test]
+    fn test_json_path_of() {
+        test_helper::init();
+
+        let error = r#"unexpected "https://avito.ru/api/14/items/42?key=abc"."firebaseParams"."kolichestvo_mest": "5""#;
+        assert_eq!(json_path_of(error), Some(r#"."firebaseParams"."kolichestvo_mest""#.to_owned()));
+
+        let error = r#""42"."stats"."views" expected to be an Object, but: 1"#;
+        assert_eq!(json_path_of(error), Some(r#"."stats"."views""#.to_owned()));
+
+        let error = r#""42"."images"[3]: not found ."1280x960" at {}"#;
+        assert_eq!(json_path_of(error), Some(r#"."images"[3]"#.to_owned()));
+
+        assert_eq!(json_path_of("expected value at line 1 column 1"), None);
+    }
+
+    #[tokio::test]
+    async fn test_quarantine() -> Result<()> {
+        test_helper::init();
+
+        let out_dir = Path::new("out_test/quarantine_test");
+        if fs::metadata(out_dir).await.is_ok() {
+            fs::remove_dir_all(out_dir).await?;
+        }
+
+        let json = json::Json::from_file("test_data/card.json").await?;
+        let mut broken = json.value.clone();
+        if let Value::Object(map) = &mut broken {
+            map.insert("newKey".to_owned(), Value::Null);
+        }
+        let error = r#"unexpected "1"."newKey": null"#;
+        put(out_dir, 1, &broken, error).await?;
+        put(out_dir, 2, &broken, error).await?;
+
+        let items = list(out_dir).await?;
+        assert_eq!(items.iter().map(|item| item.id).collect::<Vec<u64>>(), vec![1, 2]);
+        let groups = group(&items);
+        assert_eq!(groups.get(r#"."newKey""#), Some(&vec![1, 2]));
+
+        let store: Arc<dyn CardStore> = Arc::new(super::super::files::Files::new(out_dir));
+        let ret = replay(out_dir, false, &store).await?;
+        assert_eq!(ret.fixed.len(), 0);
+        assert_eq!(ret.failed.len(), 2);
+
+        let ret = replay(out_dir, true, &store).await?;
+        assert_eq!(ret.fixed, vec![1, 2]);
+        assert_eq!(list(out_dir).await?.len(), 0);
+        assert!(store.exists(1)?);
+
+        fs::remove_dir_all(out_dir).await?;
+        Ok(())
+    }
+}
@@ -4,18 +4,18 @@ use log::{error, warn, info, debug, trace};
 #[allow(unused_imports)]
 use anyhow::{Result, Error, bail, anyhow, Context};
 
+use serde_json::Value;
 use std::path::{Path};
-// use serde_json::{Value};
-
-use tokio::fs::{self, File};
-use tokio::prelude::*;
+use std::sync::Arc;
 
 use super::fetched::Fetched;
+use super::store::CardStore;
 
 pub struct Arg<'a> {
     pub id: u64,
     pub fetched: Fetched,
     pub out_dir: &'a Path,
+    pub store: Arc<dyn CardStore>,
 }
 
 pub struct Ret {
@@ -23,15 +23,58 @@ pub struct Ret {
 }
 
 pub async fn run<'a>(arg: Arg<'a>) -> Result<Ret> {
-    let file_path = super::file_spec::get(arg.out_dir, arg.id);
-    if let Some(dir_path) = file_path.parent() {
-        fs::create_dir_all(dir_path).await?;
-    }
+    // Неразобранная карточка целиком хранится только в карантине; в хранилище пишется
+    // WithError без json - отметка, что карточка получена (check не запрашивает ее повторно,
+    // gaps видит ошибку). quarantine replay заменяет отметку разобранной карточкой
+    let fetched = match arg.fetched {
+        Fetched::WithError {json, error} => {
+            super::quarantine::put(arg.out_dir, arg.id, &json, &error).await?;
+            Fetched::WithError {json: Value::Null, error}
+        },
+        fetched => fetched,
+    };
 
-    let mut file = File::create(file_path).await?;
-    let json = serde_json::to_string_pretty(&arg.fetched)?;
-    file.write_all(json.as_bytes()).await?;
+    let Arg { id, store, .. } = arg;
+    tokio::task::spawn_blocking(move || store.put(id, &fetched)).await??;
 
-    Ok(Ret{id: arg.id})
+    Ok(Ret{id})
 }
 
+// ============================================================================
+// ============================================================================
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use log::{error, warn, info, debug, trace};
+    use super::*;
+
+    #[tokio::test]
+    async fn test_save() -> Result<()> {
+        test_helper::init();
+
+        let out_dir = Path::new("out_test/save_test");
+        if out_dir.exists() {
+            std::fs::remove_dir_all(out_dir)?;
+        }
+        let store: Arc<dyn CardStore> = Arc::new(super::super::files::Files::new(out_dir));
+        let json = serde_json::json!({"id": 42, "newKey": null});
+        let error = r#"unexpected "42"."newKey": null"#.to_owned();
+        run(Arg { id: 42, fetched: Fetched::WithError {json: json.clone(), error: error.clone()}, out_dir, store: store.clone() }).await?;
+
+        match store.get(42)? {
+            Some(Fetched::WithError {json, error: stored}) => {
+                assert_eq!(json, Value::Null);
+                assert_eq!(stored, error);
+            },
+            _ => unreachable!(),
+        }
+        let items = super::super::quarantine::list(out_dir).await?;
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].json, json);
+
+        std::fs::remove_dir_all(out_dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,166 @@
+#[allow(unused_imports)]
+use log::{error, warn, info, debug, trace};
+#[allow(unused_imports)]
+use anyhow::{Result, Error, bail, anyhow, Context};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use super::fetched::Fetched;
+
+// ============================================================================
+// ============================================================================
+
+// Версия схемы сохраненной карточки. Карточка хранится в обертке {"schemaVersion": N, "fetched": <Fetched>};
+// карточка без обертки (записанная до появления версий) - версии 1.
+// При чтении карточка старой версии последовательно проходит миграции до текущей (VERSION),
+// так что изменение cards::Record требует миграции, а не ломает прежние карточки;
+// scan migrate перезаписывает хранилище в текущей версии
+
+// Миграция от версии i + 1 к версии i + 2: правит json карточки (Fetched) на месте
+type Migration = fn(&mut Value) -> Result<()>;
+
+const MIGRATIONS: &[Migration] = &[
+    to_v2,
+    to_v3,
+];
+
+pub const VERSION: u32 = MIGRATIONS.len() as u32 + 1;
+
+const VERSION_KEY: &str = "schemaVersion";
+const FETCHED_KEY: &str = "fetched";
+
+#[derive(Serialize)]
+struct Envelope<'a> {
+    #[serde(rename = "schemaVersion")]
+    schema_version: u32,
+    fetched: &'a Fetched,
+}
+
+fn envelope(fetched: &Fetched) -> Envelope<'_> {
+    Envelope { schema_version: VERSION, fetched }
+}
+
+pub fn to_vec(fetched: &Fetched) -> Result<Vec<u8>> {
+    Ok(serde_json::to_vec(&envelope(fetched))?)
+}
+
+pub fn to_string_pretty(fetched: &Fetched) -> Result<String> {
+    Ok(serde_json::to_string_pretty(&envelope(fetched))?)
+}
+
+// Версия и json карточки
+fn unwrap(value: Value) -> Result<(u32, Value)> {
+    match value {
+        Value::Object(mut map) if map.contains_key(VERSION_KEY) => {
+            let version = match map.get(VERSION_KEY).and_then(|v| v.as_u64()) {
+                Some(version) if version >= 1 => version as u32,
+                _ => bail!("{} expected to be a positive number, but: {}", VERSION_KEY, map[VERSION_KEY]),
+            };
+            let fetched = map.remove(FETCHED_KEY).ok_or_else(|| anyhow!("no {:?}", FETCHED_KEY))?;
+            Ok((version, fetched))
+        },
+        value => Ok((1, value)),
+    }
+}
+
+pub fn version_of(bytes: &[u8]) -> Result<u32> {
+    Ok(unwrap(serde_json::from_slice(bytes)?)?.0)
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Fetched> {
+    let (version, mut fetched) = unwrap(serde_json::from_slice(bytes)?)?;
+    if version > VERSION {
+        bail!("schema version {} is newer than supported {}", version, VERSION);
+    }
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version as usize - 1) {
+        migration(&mut fetched).context(format!("migration to schema version {}", i + 2))?;
+    }
+    Ok(serde_json::from_value(fetched)?)
+}
+
+// ============================================================================
+
+// Разбор справочного значения в код
+type Parse = fn(&str) -> String;
+
+// Справочные поля записи (в написании serde)
+const VOCAB_KEYS: &[(&str, Parse)] = &[
+    ("bodyType", |s| vocab::BodyType::parse(s).code().to_owned()),
+    ("fuelType", |s| vocab::FuelType::parse(s).code().to_owned()),
+    ("vehicleTransmission", |s| vocab::Transmission::parse(s).code().to_owned()),
+    ("Привод", |s| vocab::Drive::parse(s).code().to_owned()),
+    ("Состояние", |s| vocab::Condition::parse(s).code().to_owned()),
+    ("autocatalogTransmission", |s| vocab::Transmission::parse(s).code().to_owned()),
+    ("autocatalogDrive", |s| vocab::Drive::parse(s).code().to_owned()),
+    ("autocatalogFuelType", |s| vocab::FuelType::parse(s).code().to_owned()),
+];
+
+// Справочные поля: русские подписи источника ("Внедорожник", "Автомат") -> коды ("suv", "automatic")
+fn to_v2(fetched: &mut Value) -> Result<()> {
+    if let Some(record) = fetched.get_mut("Record").and_then(|record| record.as_object_mut()) {
+        for (key, parse) in VOCAB_KEYS.iter() {
+            if let Some(Value::String(s)) = record.get_mut(*key) {
+                *s = parse(s);
+            }
+        }
+    }
+    Ok(())
+}
+
+// Ширина по автокаталогу - с зеркалами: widthMm -> widthWithMirrorsMm
+fn to_v3(fetched: &mut Value) -> Result<()> {
+    if let Some(record) = fetched.get_mut("Record").and_then(|record| record.as_object_mut()) {
+        if let Some(value) = record.remove("widthMm") {
+            record.insert("widthWithMirrorsMm".to_owned(), value);
+        }
+    }
+    Ok(())
+}
+
+// ============================================================================
+// ============================================================================
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use log::{error, warn, info, debug, trace};
+    use super::*;
+
+    #[tokio::test]
+    async fn test_schema() -> Result<()> {
+        test_helper::init();
+
+        let legacy = r#"{"Record": {"id": 42, "bodyType": "Внедорожник", "Привод": "Передний", "brand": "KIA"}}"#.as_bytes();
+        assert_eq!(version_of(legacy)?, 1);
+        match decode(legacy)? {
+            Fetched::Record(record) => {
+                assert_eq!(record.body_type, Some(vocab::BodyType::Suv));
+                assert_eq!(record.drive, Some(vocab::Drive::Front));
+                assert_eq!(record.brand.as_deref(), Some("KIA"));
+            },
+            _ => unreachable!(),
+        }
+        let mut value: Value = serde_json::from_slice(legacy)?;
+        to_v2(&mut value)?;
+        assert_eq!(value["Record"]["bodyType"], Value::String("suv".to_owned()));
+        assert!(matches!(decode(br#""NotFound""#)?, Fetched::NotFound));
+        let v2 = r#"{"schemaVersion": 2, "fetched": {"Record": {"id": 42, "widthMm": 1850.0}}}"#.as_bytes();
+        match decode(v2)? {
+            Fetched::Record(record) => assert_eq!(record.width_with_mirrors_mm, Some(1850.0)),
+            _ => unreachable!(),
+        }
+
+        let json = json::Json::from_file("test_data/card.json").await?;
+        let bytes = to_vec(&Fetched::Record(Fetched::parse_json(&json, false)?))?;
+        assert_eq!(version_of(&bytes)?, VERSION);
+        assert!(matches!(decode(&bytes)?, Fetched::Record(_)));
+        assert!(matches!(decode(to_string_pretty(&Fetched::NoText)?.as_bytes())?, Fetched::NoText));
+
+        let newer = format!(r#"{{"schemaVersion": {}, "fetched": "NotFound"}}"#, VERSION + 1);
+        assert!(decode(newer.as_bytes()).unwrap_err().to_string().contains("newer"));
+
+        Ok(())
+    }
+}
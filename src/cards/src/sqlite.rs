@@ -0,0 +1,210 @@
+
+#[allow(unused_imports)]
+use log::{error, warn, info, debug, trace};
+#[allow(unused_imports)]
+use anyhow::{Result, Error, bail, anyhow, Context};
+
+use chrono::{Utc, TimeZone};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::path::Path;
+use std::sync::Mutex;
+
+use super::fetched::Fetched;
+use super::store::{CardStore, Version, Migrated};
+
+// ============================================================================
+// ============================================================================
+
+// Карточки в базе SQLite <out_dir>/cards.sqlite: каждая запись карточки - новая строка (версия)
+pub struct Sqlite {
+    conn: Mutex<Connection>,
+}
+
+pub fn file_spec(out_dir: &Path) -> std::path::PathBuf {
+    out_dir.join("cards.sqlite")
+}
+
+impl Sqlite {
+    pub fn open(out_dir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(out_dir).context(format!("{:?}", out_dir))?;
+        let file_path = file_spec(out_dir);
+        let conn = Connection::open(&file_path).context(format!("{:?}", file_path))?;
+        conn.execute_batch("
+            PRAGMA journal_mode = WAL;
+            PRAGMA synchronous = NORMAL;
+            CREATE TABLE IF NOT EXISTS card (
+                id INTEGER NOT NULL,
+                version INTEGER NOT NULL,
+                saved_at INTEGER NOT NULL,
+                json TEXT NOT NULL,
+                PRIMARY KEY (id, version)
+            );
+        ")?;
+        Ok(Self { conn: Mutex::new(conn) })
+    }
+}
+
+// Обход идет порциями по id, соединение блокируется только на время запроса порции:
+// пока работает f (например, ждет места в канале потока карточек), другие вызовы хранилища не ждут
+const ITERATE_BATCH: i64 = 1_000;
+
+fn decode(id: u64, json: String) -> Result<Fetched> {
+    super::schema::decode(json.as_bytes()).context(format!("card {}", id))
+}
+
+impl CardStore for Sqlite {
+    fn exists(&self, id: u64) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let found: Option<i64> = conn.query_row(
+            "SELECT 1 FROM card WHERE id = ?1 LIMIT 1",
+            params![id as i64],
+            |row| row.get(0),
+        ).optional()?;
+        Ok(found.is_some())
+    }
+    fn get(&self, id: u64) -> Result<Option<Fetched>> {
+        let conn = self.conn.lock().unwrap();
+        let json: Option<String> = conn.query_row(
+            "SELECT json FROM card WHERE id = ?1 ORDER BY version DESC LIMIT 1",
+            params![id as i64],
+            |row| row.get(0),
+        ).optional()?;
+        match json {
+            None => Ok(None),
+            Some(json) => Ok(Some(decode(id, json)?)),
+        }
+    }
+    fn put(&self, id: u64, fetched: &Fetched) -> Result<()> {
+        let json = String::from_utf8(super::schema::to_vec(fetched)?)?;
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO card (id, version, saved_at, json)
+            SELECT ?1, COALESCE(MAX(version), 0) + 1, ?2, ?3 FROM card WHERE id = ?1",
+            params![id as i64, Utc::now().timestamp(), json],
+        )?;
+        Ok(())
+    }
+    fn iterate(&self, f: &mut dyn FnMut(u64, Fetched) -> Result<()>) -> Result<()> {
+        let mut last_id: Option<i64> = None;
+        loop {
+            let batch = {
+                let conn = self.conn.lock().unwrap();
+                let mut stmt = conn.prepare(
+                    "SELECT c.id, c.json FROM card c
+                    JOIN (SELECT id, MAX(version) AS version FROM card
+                        WHERE ?1 IS NULL OR id > ?1 GROUP BY id ORDER BY id LIMIT ?2) l
+                    ON c.id = l.id AND c.version = l.version
+                    ORDER BY c.id"
+                )?;
+                let mut rows = stmt.query(params![last_id, ITERATE_BATCH])?;
+                let mut batch: Vec<(i64, String)> = Vec::new();
+                while let Some(row) = rows.next()? {
+                    batch.push((row.get(0)?, row.get(1)?));
+                }
+                batch
+            };
+            match batch.last() {
+                None => return Ok(()),
+                Some((id, _)) => last_id = Some(*id),
+            }
+            for (id, json) in batch {
+                let id = id as u64;
+                f(id, decode(id, json)?)?;
+            }
+        }
+    }
+    fn versions(&self, id: u64) -> Result<Vec<Version>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT saved_at, json FROM card WHERE id = ?1 ORDER BY version")?;
+        let mut rows = stmt.query(params![id as i64])?;
+        let mut ret = Vec::new();
+        while let Some(row) = rows.next()? {
+            let saved_at: i64 = row.get(0)?;
+            let json: String = row.get(1)?;
+            ret.push(Version {
+                saved_at: Utc.timestamp_opt(saved_at, 0).single(),
+                fetched: decode(id, json)?,
+            });
+        }
+        Ok(ret)
+    }
+    fn ids(&self) -> Result<Vec<u64>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT DISTINCT id FROM card ORDER BY id")?;
+        let mut rows = stmt.query(params![])?;
+        let mut ids = Vec::new();
+        while let Some(row) = rows.next()? {
+            ids.push(row.get::<_, i64>(0)? as u64);
+        }
+        Ok(ids)
+    }
+    fn len(&self) -> Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        let qt: i64 = conn.query_row("SELECT COUNT(DISTINCT id) FROM card", params![], |row| row.get(0))?;
+        Ok(qt as usize)
+    }
+    fn migrate(&self) -> Result<Migrated> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let mut ret = Migrated::default();
+        {
+            let mut stmt = tx.prepare("SELECT id, version, json FROM card")?;
+            let mut update = tx.prepare("UPDATE card SET json = ?3 WHERE id = ?1 AND version = ?2")?;
+            let mut rows = stmt.query(params![])?;
+            while let Some(row) = rows.next()? {
+                let id: i64 = row.get(0)?;
+                let version: i64 = row.get(1)?;
+                let json: String = row.get(2)?;
+                ret.total += 1;
+                if super::schema::version_of(json.as_bytes()).context(format!("card {}", id))? < super::schema::VERSION {
+                    let fetched = decode(id as u64, json)?;
+                    let json = String::from_utf8(super::schema::to_vec(&fetched)?)?;
+                    update.execute(params![id, version, json])?;
+                    ret.migrated += 1;
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(ret)
+    }
+}
+
+// ============================================================================
+// ============================================================================
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use log::{error, warn, info, debug, trace};
+    use super::*;
+
+    #[test]
+    fn test_iterate_batches() -> Result<()> {
+        test_helper::init();
+
+        let out_dir = Path::new("out_test/sqlite_iterate_test");
+        if out_dir.exists() {
+            std::fs::remove_dir_all(out_dir)?;
+        }
+        let store = Sqlite::open(out_dir)?;
+        let qt = ITERATE_BATCH as u64 + 2;
+        for id in 1..=qt {
+            store.put(id, &Fetched::NotFound)?;
+        }
+        store.put(1, &Fetched::NoText)?;
+
+        let mut ids = Vec::new();
+        store.iterate(&mut |id, fetched| {
+            if id == 1 {
+                assert!(matches!(fetched, Fetched::NoText));
+            }
+            ids.push(id);
+            Ok(())
+        })?;
+        assert_eq!(ids, (1..=qt).collect::<Vec<u64>>());
+
+        std::fs::remove_dir_all(out_dir)?;
+        Ok(())
+    }
+}
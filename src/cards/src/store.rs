@@ -0,0 +1,147 @@
+
+#[allow(unused_imports)]
+use log::{error, warn, info, debug, trace};
+#[allow(unused_imports)]
+use anyhow::{Result, Error, bail, anyhow, Context};
+
+use chrono::{DateTime, Utc};
+use std::path::Path;
+use std::sync::Arc;
+
+use super::fetched::Fetched;
+
+// ============================================================================
+// ============================================================================
+
+// Хранилище карточек. Этапы конвейера (check, save, collect, quarantine)
+// работают с карточками только через него, не зная, где они лежат.
+// Методы блокирующие (файлы, rusqlite, zstd): из асинхронного кода они вызываются только через spawn_blocking
+pub trait CardStore: Send + Sync {
+    fn exists(&self, id: u64) -> Result<bool>;
+    // Последняя сохраненная версия карточки
+    fn get(&self, id: u64) -> Result<Option<Fetched>>;
+    fn put(&self, id: u64, fetched: &Fetched) -> Result<()>;
+    // Обход последних версий всех карточек; читать из f можно, писать в это же хранилище нельзя
+    fn iterate(&self, f: &mut dyn FnMut(u64, Fetched) -> Result<()>) -> Result<()>;
+    // Все сохраненные версии карточки, от старой к новой.
+    // Хранилище, не ведущее историю, возвращает только последнюю
+    fn versions(&self, id: u64) -> Result<Vec<Version>>;
+    // Идентификаторы всех карточек, по возрастанию: для постепенного чтения через get
+    fn ids(&self) -> Result<Vec<u64>>;
+    fn len(&self) -> Result<usize>;
+    fn is_empty(&self) -> Result<bool> {
+        Ok(self.len()? == 0)
+    }
+    // Перезапись карточек (всех версий) старой версии схемы в текущей (см. schema)
+    fn migrate(&self) -> Result<Migrated>;
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Migrated {
+    // сохраненных карточек, считая версии
+    pub total: usize,
+    pub migrated: usize,
+}
+
+pub struct Version {
+    pub saved_at: Option<DateTime<Utc>>,
+    pub fetched: Fetched,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Files,
+    Pack,
+    Sqlite,
+}
+
+pub fn open(kind: Kind, out_dir: &Path) -> Result<Arc<dyn CardStore>> {
+    Ok(match kind {
+        Kind::Files => Arc::new(super::files::Files::new(out_dir)),
+        Kind::Pack => Arc::new(super::pack::Pack::open(out_dir)?),
+        Kind::Sqlite => Arc::new(super::sqlite::Sqlite::open(out_dir)?),
+    })
+}
+
+// Перенос карточек из одного хранилища в другое; уже имеющиеся в target пропускаются
+pub fn copy(source: &dyn CardStore, target: &dyn CardStore) -> Result<usize> {
+    let mut copied_qt = 0;
+    source.iterate(&mut |id, fetched| {
+        if !target.exists(id)? {
+            target.put(id, &fetched)?;
+            copied_qt += 1;
+        }
+        Ok(())
+    })?;
+    Ok(copied_qt)
+}
+
+// ============================================================================
+// ============================================================================
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use log::{error, warn, info, debug, trace};
+    use super::*;
+
+    // Одинаковое поведение всех реализаций
+    async fn check_store(kind: Kind, out_dir: &Path) -> Result<()> {
+        if out_dir.exists() {
+            std::fs::remove_dir_all(out_dir)?;
+        }
+        let json = json::Json::from_file("test_data/card.json").await?;
+        let record = Fetched::parse_json(&json, false)?;
+
+        {
+            let store = open(kind, out_dir)?;
+            assert!(!store.exists(42)?);
+            store.put(42, &Fetched::NotFound)?;
+            store.put(43, &Fetched::Record(record))?;
+            store.put(42, &Fetched::NoText)?;
+        }
+
+        let store = open(kind, out_dir)?;
+        assert!(store.exists(42)?);
+        assert_eq!(store.len()?, 2);
+        assert!(matches!(store.get(42)?, Some(Fetched::NoText)));
+        assert!(store.get(44)?.is_none());
+        match store.get(43)? {
+            Some(Fetched::Record(record)) => assert_eq!(record.id, Some(1767797249)),
+            _ => unreachable!(),
+        }
+        let mut ids = Vec::new();
+        store.iterate(&mut |id, _fetched| {
+            // чтение из f не ждет обхода: так поток карточек выгрузки берет версии карточки
+            assert!(!store.versions(id)?.is_empty());
+            ids.push(id);
+            Ok(())
+        })?;
+        ids.sort();
+        assert_eq!(ids, vec![42, 43]);
+        assert_eq!(store.ids()?, vec![42, 43]);
+
+        let versions = store.versions(42)?;
+        assert!(matches!(versions.last(), Some(Version { fetched: Fetched::NoText, .. })));
+        if kind != Kind::Files {
+            assert_eq!(versions.len(), 2);
+            assert!(matches!(versions[0].fetched, Fetched::NotFound));
+        }
+        assert_eq!(store.migrate()?, Migrated { total: versions.len() + 1, migrated: 0 });
+
+        std::fs::remove_dir_all(out_dir)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_store() -> Result<()> {
+        test_helper::init();
+
+        check_store(Kind::Files, Path::new("out_test/store_files_test")).await?;
+        check_store(Kind::Pack, Path::new("out_test/store_pack_test")).await?;
+        check_store(Kind::Sqlite, Path::new("out_test/store_sqlite_test")).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,228 @@
+#[allow(unused_imports)]
+use log::{error, warn, info, debug, trace};
+#[allow(unused_imports)]
+use anyhow::{Result, Error, bail, anyhow, Context};
+
+use serde::{Serialize, Deserialize};
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use tokio::fs::{self, File};
+use tokio::prelude::*;
+
+// ============================================================================
+// ============================================================================
+
+// Изменение записи с прошлой выгрузки
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChangeType {
+    New,
+    Changed,
+    Removed,
+}
+
+impl ChangeType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeType::New => "new",
+            ChangeType::Changed => "changed",
+            ChangeType::Removed => "removed",
+        }
+    }
+}
+
+// Отслеживаемые поля записи: изменение любого из них делает запись changed.
+// Вместо описания хранится его хэш, чтобы файл состояния оставался небольшим
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Tracked {
+    pub item_price: Option<u64>,
+    pub status: Option<String>,
+    pub mileage: Option<u64>,
+    pub description_hash: Option<u64>,
+}
+
+impl Tracked {
+    pub fn new(record: &cards::Record) -> Self {
+        Self {
+            item_price: record.item_price,
+            status: record.status.clone(),
+            mileage: record.mileage,
+            description_hash: record.description.as_deref().map(fnv1a),
+        }
+    }
+}
+
+// FNV-1a: хэш не зависит от версии Rust (в отличие от DefaultHasher), а состояние живет между запусками
+fn fnv1a(s: &str) -> u64 {
+    let mut ret: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in s.bytes() {
+        ret ^= byte as u64;
+        ret = ret.wrapping_mul(0x0100_0000_01b3);
+    }
+    ret
+}
+
+// Состояние последней успешной выгрузки: отслеживаемые поля выгруженных записей по id
+#[derive(Serialize, Deserialize)]
+pub struct State {
+    pub exported_at: DateTime<Utc>,
+    pub items: BTreeMap<u64, Tracked>,
+}
+
+// Файл состояния на каждую выгрузку: records, records_filtered, records_<search>
+pub fn file_spec(out_dir: &Path, file_stem: &str) -> PathBuf {
+    out_dir.join(format!("export_state_{}.json", file_stem))
+}
+
+impl State {
+    // Пишется во временный <file_path>.tmp и переименовывается поверх прежнего: прерванная запись
+    // не портит состояние, иначе все следующие выгрузки --incremental падали бы на from_file
+    pub async fn to_file(&self, file_path: &Path) -> Result<()> {
+        if let Some(dir_path) = file_path.parent() {
+            fs::create_dir_all(dir_path).await?;
+        }
+        let tmp_path = {
+            let mut s = file_path.as_os_str().to_owned();
+            s.push(".tmp");
+            PathBuf::from(s)
+        };
+        let mut file = File::create(&tmp_path).await.context(format!("{:?}", tmp_path))?;
+        let json = serde_json::to_string(&self)?;
+        file.write_all(json.as_bytes()).await?;
+        file.sync_all().await?;
+        fs::rename(&tmp_path, file_path).await.context(format!("{:?}", file_path))?;
+        Ok(())
+    }
+    // None - файла нет (выгрузок еще не было); испорченный или нечитаемый файл - ошибка,
+    // а не пустое состояние: иначе выгрузка молча стала бы полной
+    pub async fn from_file(file_path: &Path) -> Result<Option<Self>> {
+        let mut file = match File::open(file_path).await {
+            Ok(file) => file,
+            Err(err) => match err.kind() {
+                std::io::ErrorKind::NotFound => return Ok(None),
+                _ => return Err(Error::new(err).context(format!("{:?}", file_path))),
+            },
+        };
+        let mut content = vec![];
+        file.read_to_end(&mut content).await?;
+        let content = std::str::from_utf8(&content)?;
+        let ret = Self::from_str(content).context(format!("{:?}", file_path))?;
+        Ok(Some(ret))
+    }
+}
+
+impl FromStr for State {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let ret = serde_json::from_str(s)?;
+        Ok(ret)
+    }
+}
+
+// ============================================================================
+
+// Инкрементная выгрузка: записи сверяются с состоянием прошлой выгрузки (prev),
+// из них же собирается состояние этой (next), которое сохраняется только после ее успешного завершения
+pub struct Changes {
+    prev: BTreeMap<u64, Tracked>,
+    next: BTreeMap<u64, Tracked>,
+}
+
+impl Changes {
+    // Без прошлого состояния все записи - new
+    pub fn new(prev: Option<State>) -> Self {
+        Self {
+            prev: prev.map(|state| state.items).unwrap_or_default(),
+            next: BTreeMap::new(),
+        }
+    }
+    // None - запись не изменилась (или без id) и не выгружается
+    pub fn check(&mut self, record: &cards::Record) -> Option<ChangeType> {
+        let id = record.id?;
+        let tracked = Tracked::new(record);
+        let ret = match self.prev.get(&id) {
+            None => Some(ChangeType::New),
+            Some(prev) if *prev != tracked => Some(ChangeType::Changed),
+            Some(_) => None,
+        };
+        self.next.insert(id, tracked);
+        ret
+    }
+    // Записи прошлой выгрузки, которых нет в этой
+    pub fn removed(&self) -> Vec<u64> {
+        let next: HashSet<&u64> = self.next.keys().collect();
+        self.prev.keys().filter(|id| !next.contains(id)).cloned().collect()
+    }
+    pub fn into_state(self, exported_at: DateTime<Utc>) -> State {
+        State { exported_at, items: self.next }
+    }
+}
+
+// Строка удаленной записи: только id и change_type
+pub fn removed_record(id: u64) -> cards::Record {
+    cards::Record {
+        id: Some(id),
+        change_type: Some(ChangeType::Removed.as_str().to_owned()),
+        ..cards::Record::default()
+    }
+}
+
+// ============================================================================
+// ============================================================================
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use log::{error, warn, info, debug, trace};
+    use super::*;
+
+    #[tokio::test]
+    async fn test_changes() -> Result<()> {
+        test_helper::init();
+
+        let json = json::Json::from_file("../cards/test_data/card.json").await?;
+        let record = |id: u64, item_price: u64| -> Result<cards::Record> {
+            let mut ret = cards::Fetched::parse_json(&json, false)?;
+            ret.id = Some(id);
+            ret.item_price = Some(item_price);
+            Ok(ret)
+        };
+
+        let mut changes = Changes::new(None);
+        assert_eq!(changes.check(&record(1, 900_000)?), Some(ChangeType::New));
+        assert_eq!(changes.check(&record(2, 900_000)?), Some(ChangeType::New));
+        assert_eq!(changes.check(&record(3, 900_000)?), Some(ChangeType::New));
+        let file_path = file_spec(Path::new("out_test"), "records");
+        changes.into_state(Utc::now()).to_file(&file_path).await?;
+        // временный файл переименован на место, а оставшийся от прерванной записи не мешает чтению
+        let tmp_path = file_path.with_extension("json.tmp");
+        assert!(fs::metadata(&tmp_path).await.is_err());
+        fs::write(&tmp_path, "{").await?;
+
+        let mut changes = Changes::new(State::from_file(&file_path).await?);
+        assert_eq!(changes.check(&record(1, 900_000)?), None);
+        assert_eq!(changes.check(&record(2, 850_000)?), Some(ChangeType::Changed));
+        let mut described = record(4, 900_000)?;
+        assert_eq!(changes.check(&described), Some(ChangeType::New));
+        assert_eq!(changes.removed(), vec![3]);
+
+        let mut changes = Changes::new(Some(changes.into_state(Utc::now())));
+        described.description = Some("другое описание".to_owned());
+        assert_eq!(changes.check(&described), Some(ChangeType::Changed));
+
+        let removed = removed_record(3);
+        assert_eq!(removed.change_type.as_deref(), Some("removed"));
+        assert_eq!(removed.brand, None);
+
+        assert!(State::from_file(&file_spec(Path::new("out_test"), "no_such_export")).await?.is_none());
+        let file_path = file_spec(Path::new("out_test"), "broken");
+        fs::write(&file_path, "{").await?;
+        assert!(State::from_file(&file_path).await.is_err());
+
+        Ok(())
+    }
+}
@@ -47,6 +47,12 @@ impl Client {
             Client::ViaProxy(client) => client.get_text_status(url).await?,
         })
     }
+    pub async fn get_bytes_status(&self, url: url::Url) -> Result<(Vec<u8>, http::StatusCode)> {
+        Ok(match self {
+            Client::Reqwest(client) => client.get_bytes_status(url).await?,
+            Client::ViaProxy(client) => client.get_bytes_status(url).await?,
+        })
+    }
 }
 
 pub struct Wrapper {
@@ -114,6 +120,39 @@ impl Wrapper {
         // }.context("ids::fetch")?;
         Ok((text, status))
     }
+    async fn get_bytes_status(&self, url: url::Url) -> Result<(Vec<u8>, http::StatusCode)> {
+        let mut remained = self.retry_count;
+        loop {
+            match self.client.get(url.clone()).send().await {
+                Err(err) => {
+                    if remained > 0 {
+                        remained -= 1;
+                        continue;
+                    } else {
+                        return Err(Error::new(err))
+                    }
+                },
+                Ok(response) => {
+                    let status = response.status();
+                    if status != StatusCode::OK && remained > 0 {
+                        remained -= 1;
+                        continue;
+                    }
+                    match response.bytes().await {
+                        Ok(bytes) => return Ok((bytes.to_vec(), status)),
+                        Err(err) => {
+                            if remained > 0 {
+                                remained -= 1;
+                                continue;
+                            } else {
+                                return Err(Error::new(err))
+                            }
+                        },
+                    }
+                },
+            }
+        }
+    }
 }
 
 
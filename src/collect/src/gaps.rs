@@ -0,0 +1,162 @@
+
+#[allow(unused_imports)]
+use log::{error, warn, info, debug, trace};
+#[allow(unused_imports)]
+use anyhow::{Result, Error, bail, anyhow, Context};
+
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+// ============================================================================
+// ============================================================================
+
+// Пробелы между сбором идентификаторов и выгрузкой: почему объявление не попало в records.csv
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Category {
+    // карточка в хранилище есть, но не читается
+    Unreadable,
+    // карточка получена, но не разобрана (см. cards::quarantine)
+    WithError,
+    // на запрос карточки получен 404
+    NotFound,
+    // карточка получена без текста объявления
+    NoText,
+    // идентификатор получен, а карточки в хранилище нет
+    NotFetched,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Gap {
+    pub category: Category,
+    pub id: Option<u64>,
+    pub file_path: Option<String>,
+    pub error: Option<String>,
+    pub json_path: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct Gaps {
+    pub items: Vec<Gap>,
+}
+
+impl Gaps {
+    pub fn counts(&self) -> BTreeMap<Category, usize> {
+        let mut ret = BTreeMap::new();
+        for item in self.items.iter() {
+            *ret.entry(item.category).or_insert(0) += 1;
+        }
+        ret
+    }
+    // Снятые с публикации (NotFound) запрашивать повторно незачем
+    pub fn ids_to_refetch(&self) -> HashSet<u64> {
+        self.items.iter()
+            .filter(|item| item.category != Category::NotFound)
+            .filter_map(|item| item.id)
+            .collect()
+    }
+}
+
+// files_out_dir - out_dir файлового хранилища: для него в отчет попадает путь к файлу карточки.
+// harvested - все полученные идентификаторы (id_store::IdStore::all_ids)
+pub fn collect(store: &dyn cards::CardStore, files_out_dir: Option<&Path>, harvested: &HashSet<u64>) -> Result<Gaps> {
+    let mut items = Vec::new();
+    let ids = store.ids().context("collect::gaps")?;
+    for id in ids.iter() {
+        let id = *id;
+        let file_path = files_out_dir.map(|out_dir| cards::file_spec::get(out_dir, id).to_string_lossy().to_string());
+        let gap = |category, error: Option<String>, json_path| Gap {
+            category,
+            id: Some(id),
+            file_path: file_path.clone(),
+            error,
+            json_path,
+        };
+        match store.get(id) {
+            Err(err) => items.push(gap(Category::Unreadable, Some(err.root_cause().to_string()), None)),
+            Ok(None) | Ok(Some(cards::Fetched::Record(_))) => {},
+            Ok(Some(cards::Fetched::NotFound)) => items.push(gap(Category::NotFound, None, None)),
+            Ok(Some(cards::Fetched::NoText)) => items.push(gap(Category::NoText, None, None)),
+            Ok(Some(cards::Fetched::WithError { error, .. })) => {
+                let json_path = cards::quarantine::json_path_of(&error);
+                items.push(gap(Category::WithError, Some(error), json_path));
+            },
+        }
+    }
+    let stored: HashSet<u64> = ids.into_iter().collect();
+    let mut not_fetched: Vec<u64> = harvested.difference(&stored).cloned().collect();
+    not_fetched.sort();
+    for id in not_fetched {
+        items.push(Gap {
+            category: Category::NotFetched,
+            id: Some(id),
+            file_path: None,
+            error: None,
+            json_path: None,
+        });
+    }
+    Ok(Gaps { items })
+}
+
+// ============================================================================
+// ============================================================================
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use log::{error, warn, info, debug, trace};
+    use super::*;
+
+    use cards::CardStore;
+
+    #[tokio::test]
+    async fn test_gaps() -> Result<()> {
+        test_helper::init();
+
+        let out_dir = Path::new("out_test/gaps");
+        if out_dir.exists() {
+            std::fs::remove_dir_all(out_dir)?;
+        }
+        let store = cards::files::Files::new(out_dir);
+
+        let json = json::Json::from_file("../cards/test_data/card.json").await?;
+        let record = cards::Fetched::parse_json(&json, false)?;
+        let record_id = record.id.unwrap();
+        store.put(record_id, &cards::Fetched::Record(record))?;
+        store.put(2, &cards::Fetched::NotFound)?;
+        store.put(3, &cards::Fetched::NoText)?;
+        store.put(4, &cards::Fetched::WithError {
+            json: serde_json::Value::Null,
+            error: r#""https://www.avito.ru/4"."firebaseParams"."model": invalid type: null"#.to_owned(),
+        })?;
+        let unreadable = cards::file_spec::get(out_dir, 5);
+        std::fs::create_dir_all(unreadable.parent().unwrap())?;
+        std::fs::write(&unreadable, b"{")?;
+
+        let harvested: HashSet<u64> = vec![record_id, 2, 6].into_iter().collect();
+        let gaps = collect(&store, Some(out_dir), &harvested)?;
+
+        let counts = gaps.counts();
+        assert_eq!(counts.get(&Category::Unreadable), Some(&1));
+        assert_eq!(counts.get(&Category::WithError), Some(&1));
+        assert_eq!(counts.get(&Category::NotFound), Some(&1));
+        assert_eq!(counts.get(&Category::NoText), Some(&1));
+        assert_eq!(counts.get(&Category::NotFetched), Some(&1));
+
+        let with_error = gaps.items.iter().find(|item| item.category == Category::WithError).unwrap();
+        assert_eq!(with_error.id, Some(4));
+        assert_eq!(with_error.json_path.as_deref(), Some(r#"."firebaseParams"."model""#));
+        let unreadable_gap = gaps.items.iter().find(|item| item.category == Category::Unreadable).unwrap();
+        assert_eq!(unreadable_gap.file_path.as_deref(), Some(unreadable.to_string_lossy().as_ref()));
+        assert!(unreadable_gap.error.is_some());
+
+        let mut ids: Vec<u64> = gaps.ids_to_refetch().into_iter().collect();
+        ids.sort();
+        assert_eq!(ids, vec![3, 4, 5, 6]);
+
+        Ok(())
+    }
+}
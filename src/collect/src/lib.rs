@@ -5,21 +5,26 @@ use log::{error, warn, info, debug, trace};
 #[allow(unused_imports)]
 use anyhow::{Result, Error, bail, anyhow, Context};
 
-use std::time::Instant;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 #[macro_use] extern crate lazy_static;
 
 use futures::{
-    select,
+    future,
+    executor,
+    channel::mpsc,
+    sink::SinkExt,
     stream::{
-        FuturesUnordered,
+        self,
+        Stream,
         StreamExt,
     },
 };
 
 mod read_dir;
 mod read_file;
+pub mod gaps;
 
 // ============================================================================
 // ============================================================================
@@ -29,269 +34,64 @@ pub struct Arg<'a> {
     pub thread_limit_file: usize,
 }
 
-macro_rules! push_fut_readdir {
-    ($fut_queue: expr, $vec_dir: expr, $i_dir: expr, $used_file_threads: expr) => {
-        let dir = $vec_dir[$i_dir].as_path();
-        let arg = OpArg::ReadDir (read_dir::Arg {
-            dir: dir.to_owned(),
-        });
-        let fut = op(arg);
-        $fut_queue.push(fut);
-        $i_dir += 1;
-        $used_file_threads += 1;
-    };
-}
-
-macro_rules! push_fut_readfile {
-    ($fut_queue: expr, $vec_file: expr, $i_file: expr, $used_file_threads: expr) => {
-        let file_path = $vec_file[$i_file].as_path();
-        let arg = OpArg::ReadFile (read_file::Arg {
-            file_path: file_path.to_owned(),
-        });
-        let fut = op(arg);
-        $fut_queue.push(fut);
-        $i_file += 1;
-        $used_file_threads += 1;
-    };
-}
-
-pub enum CallbackArg {
-    ReadDir {
-        elapsed_millis: u128,
-        dir_qt: usize,
-        file_qt: usize,
-    },
-    ReadFile {
-        elapsed_qt: usize,
-        remained_qt: usize,
-        elapsed_millis: u128,
-        remained_millis: u128,
-        per100_millis: u128,
-    }
-}
-
-pub trait Ret {
-    fn adopt_record(&mut self, record: cards::Record) -> Result<()>;
-}
-
-pub struct Records (pub Vec::<cards::Record>);
-
-impl Records {
-    pub fn new() -> Self {
-        Self(Vec::new())
-    }
-}
-
-impl Ret for Records {
-    fn adopt_record(&mut self, record: cards::Record) -> Result<()> {
-        self.0.push(record);
-        Ok(())
-    }
-}
-
-// use std::collections::HashSet;
-// pub struct Autocatalog (pub HashSet<String>);
-// impl Autocatalog {
-//     pub fn new() -> Self {
-//         Self(HashSet::new())
-//     }
-// }
-//
-// // use regex::Regex;
-// impl Ret for Autocatalog {
-//     fn adopt_record(&mut self, record: cards::Record) -> Result<()> {
-//         if let Some(url) = record.autocatalog_url {
-//             // lazy_static! {
-//             //     static ref RE: Regex = Regex::new(r"/\d+$").unwrap();
-//             // }
-//             // let url = RE.replace(&url, "").to_string();
-//             self.0.insert(url);
-//         }
-//         Ok(())
-//     }
-// }
-
-use std::path::PathBuf;
-
-macro_rules! callback_dir {
-    ($callback: expr, $start: expr, $vec_dir: expr, $vec_file: expr) => {
-        let elapsed_millis = Instant::now().duration_since($start).as_millis(); 
-        $callback(CallbackArg::ReadDir {
-            elapsed_millis, 
-            dir_qt: $vec_dir.len(),
-            file_qt: $vec_file.len(),
-        })?;
-    };
+// Поток карточек из файлового хранилища: память не зависит от числа карточек.
+// Каталоги обходятся по мере чтения, файлы читаются параллельно, не более thread_limit_file одновременно
+pub fn stream(arg: Arg) -> impl Stream<Item = Result<cards::Record>> {
+    let thread_limit_file = arg.thread_limit_file;
+    file_paths(arg.out_dir.to_owned())
+        .map(|file_path| async move {
+            let file_path = file_path?;
+            let ret = read_file::run(read_file::Arg { file_path: file_path.clone() }).await
+                .context(format!("{:?}", file_path))?;
+            Ok(ret)
+        })
+        .buffer_unordered(thread_limit_file)
+        .filter_map(|ret: Result<cards::Fetched>| future::ready(match ret {
+            Ok(cards::Fetched::Record(record)) => Some(Ok(record)),
+            Ok(_) => None,
+            Err(err) => Some(Err(err.context("collect::stream"))),
+        }))
 }
 
-macro_rules! callback_file {
-    ($callback: expr, $start: expr, $elapsed_qt: expr, $remained_qt: expr) => {
-        let elapsed_micros = Instant::now().duration_since($start).as_micros(); 
-        let per_micros = elapsed_micros / $elapsed_qt as u128;
-        let remained_micros = per_micros * $remained_qt as u128;
-        $callback(CallbackArg::ReadFile {
-            elapsed_qt: $elapsed_qt,
-            remained_qt: $remained_qt,
-            elapsed_millis: elapsed_micros / 1000, 
-            remained_millis: remained_micros / 1000, 
-            per100_millis: per_micros / 100,
-        })?;
-    };
-}
-
-const CALLBACK_THROTTLE: u128 = 500; //ms
-
-pub async fn items<'a, Cb, R>(
-    arg: Arg<'a>, 
-    items: &mut R,
-    mut callback: Option<Cb>,
-) -> Result<()>
-where 
-    Cb: FnMut(CallbackArg) -> Result<()>,
-    R: Ret,
-{
-    let mut vec_dir: Vec<PathBuf> = vec![arg.out_dir.to_owned()];
-    let mut i_dir = 0;
-
-    let mut vec_file = Vec::<PathBuf>::new();
-    let mut i_file: usize = 0;
-
-    let mut used_file_threads = 0;
-
-    let mut fut_queue = FuturesUnordered::new();
-    push_fut_readdir!(fut_queue, vec_dir, i_dir, used_file_threads);
-
-    let mut elapsed_qt: usize = 0;
-    let mut remained_qt: usize = 0;
-    let mut last_callback = Instant::now();
-    let mut start_file: Option<Instant> = None; 
-    let start_dir = Instant::now(); 
-
-    loop {
-        select! {
-            ret = fut_queue.select_next_some() => {
-                match ret {
-                    Err(err) => {
-                        return Err(err.context("collect::cards"));
-                    },
-                    Ok(ret) => {
-                        match ret {
-                            OpRet::ReadFile(ret) => {
-                                used_file_threads -= 1;
-                                if let cards::Fetched::Record(record) = ret {
-                                    items.adopt_record(record)?;
-                                }
-
-                                callback = if let Some(mut callback) = callback {
-                                    elapsed_qt += 1;
-                                    if remained_qt > 0 {
-                                        remained_qt -= 1;
-                                    }
-                                    if let Some(start_file) = start_file {
-                                        if elapsed_qt > 0 && Instant::now().duration_since(last_callback).as_millis() > CALLBACK_THROTTLE {
-                                            callback_file!(callback, start_file, elapsed_qt, remained_qt);
-                                            last_callback = Instant::now();
-                                        }
-                                    }
-                                    Some(callback)
-                                } else {
-                                    None
-                                };
-
-                                while used_file_threads < arg.thread_limit_file && i_file < vec_file.len() {
-                                    push_fut_readfile!(fut_queue, vec_file, i_file, used_file_threads);
-                                }
-                            },
-                            OpRet::ReadDir(read_dir::Ret{dirs, files}) => {
-                                used_file_threads -= 1;
-                                if let Some(dirs) = dirs {
-                                    for dir in dirs {
-                                        vec_dir.push(dir);
-                                    }
-                                }
-                                while used_file_threads < arg.thread_limit_file && i_dir < vec_dir.len() {
-                                    push_fut_readdir!(fut_queue, vec_dir, i_dir, used_file_threads);
-                                }
-                                if let Some(files) = files {
-                                    callback = if let Some(mut callback) = callback {
-                                        remained_qt += files.len();
-                                        if let Some(start_file) = start_file {
-                                            if Instant::now().duration_since(last_callback).as_millis() > CALLBACK_THROTTLE {
-                                                callback_file!(callback, start_file, elapsed_qt, remained_qt);
-                                                last_callback = Instant::now();
-                                            }
-                                        }
-                                        Some(callback)
-                                    } else {
-                                        None
-                                    };
-
-                                    for file in files {
-                                        vec_file.push(file);
-                                    }
-
-                                    callback = if let Some(mut callback) = callback {
-                                        if Instant::now().duration_since(last_callback).as_millis() > CALLBACK_THROTTLE {
-                                            if elapsed_qt == 0 {
-                                                callback_dir!(callback, start_dir, vec_dir, vec_file);
-                                                last_callback = Instant::now();
-                                            }
-                                        }
-                                        Some(callback)
-                                    } else {
-                                        None
-                                    };
-                                }
-
-                                while used_file_threads < arg.thread_limit_file && i_file < vec_file.len() {
-                                    if i_file == 0 {
-                                        start_file = Some(Instant::now());
-                                    }
-                                    push_fut_readfile!(fut_queue, vec_file, i_file, used_file_threads);
-                                }
-                            },
-                        }
-                    },
+fn file_paths(out_dir: PathBuf) -> impl Stream<Item = Result<PathBuf>> {
+    stream::unfold(Some(vec![out_dir]), |vec_dir| async move {
+        // после ошибки поток завершается
+        let mut vec_dir = vec_dir?;
+        let dir = vec_dir.pop()?;
+        match read_dir::run(read_dir::Arg { dir }).await {
+            Err(err) => Some((vec![Err(err)], None)),
+            Ok(read_dir::Ret{dirs, files}) => {
+                if let Some(dirs) = dirs {
+                    vec_dir.extend(dirs);
                 }
-            },
-            complete => {
-                break;
+                let files: Vec<Result<PathBuf>> = files.unwrap_or_default().into_iter().map(Ok).collect();
+                Some((files, Some(vec_dir)))
             },
         }
-    }
+    })
+    .map(stream::iter)
+    .flatten()
+}
 
-    if let Some(mut callback) = callback {
-        if let Some(start_file) = start_file {
-            if elapsed_qt > 0  {
-                callback_file!(callback, start_file, elapsed_qt, remained_qt);
+// Поток карточек из остальных хранилищ. Хранилище обходится за один проход (CardStore::iterate:
+// pack читает каждый сегмент один раз) в spawn_blocking, записи передаются через ограниченный канал,
+// так что в памяти не больше STORED_BUFFER карточек. Если поток перестали читать, обход прекращается
+const STORED_BUFFER: usize = 1_000;
+pub fn stream_stored(store: Arc<dyn cards::CardStore>) -> impl Stream<Item = Result<cards::Record>> {
+    let (mut tx, rx) = mpsc::channel(STORED_BUFFER);
+    tokio::task::spawn_blocking(move || {
+        let mut tx_record = tx.clone();
+        let ret = store.iterate(&mut |_id, fetched| {
+            if let cards::Fetched::Record(record) = fetched {
+                executor::block_on(tx_record.send(Ok(record))).map_err(|_| anyhow!("stream is dropped"))?;
             }
+            Ok(())
+        });
+        if let Err(err) = ret {
+            let _ = executor::block_on(tx.send(Err(err.context("collect::stream_stored"))));
         }
-    }
-
-    Ok(())
-}
-
-enum OpArg {
-    ReadDir(read_dir::Arg),
-    ReadFile(read_file::Arg),
-}
-
-enum OpRet {
-    ReadDir(read_dir::Ret),
-    ReadFile(read_file::Ret),
-}
-
-async fn op(arg: OpArg) -> Result<OpRet> {
-    match arg {
-        OpArg::ReadDir(arg) => {
-            let ret = read_dir::run(arg).await?;
-            Ok(OpRet::ReadDir(ret))
-        },
-        OpArg::ReadFile(arg) => {
-            let ret = read_file::run(arg).await?;
-            Ok(OpRet::ReadFile(ret))
-        },
-    }
+    });
+    rx
 }
 
 // ============================================================================
@@ -305,6 +105,7 @@ mod tests {
     use log::{error, warn, info, debug, trace};
     use super::*;
 
+    use std::time::Instant;
     use term::Term;
 
     #[tokio::test]
@@ -318,42 +119,59 @@ mod tests {
 
         let mut term = Term::init(term::Arg::new().header("Чтение карточек . . ."));
         let start = Instant::now();
-        let mut records = Records::new();
-        items(arg, &mut records, Some(|arg: CallbackArg| -> Result<()> {
-            match arg {
-                CallbackArg::ReadDir {elapsed_millis, dir_qt, file_qt} => {
-                    term.output(format!("time: {}, dirs: {}, files: {}", 
-                        arrange_millis::get(elapsed_millis), 
-                        dir_qt,
-                        file_qt,
-                    ));
-                    Ok(())
-                },
-                CallbackArg::ReadFile {elapsed_millis, remained_millis, per100_millis, elapsed_qt, remained_qt} => {
-                    term.output(format!("time: {}/{}-{}, per100: {}, qt: {}/{}-{}", 
-                        arrange_millis::get(elapsed_millis), 
-                        arrange_millis::get(elapsed_millis + remained_millis), 
-                        arrange_millis::get(remained_millis), 
-                        arrange_millis::get(per100_millis), 
-                        elapsed_qt,
-                        elapsed_qt + remained_qt,
-                        remained_qt,
-                    ));
-                    Ok(())
-                },
-            }
-        })).await?;
-        println!("{}, Карточки прочитаны: {}", arrange_millis::get(Instant::now().duration_since(start).as_millis()), records.0.len());
+        let mut qt = 0;
+        let mut records = Box::pin(stream(arg));
+        while let Some(record) = records.next().await {
+            record?;
+            qt += 1;
+            term.output(format!("time: {}, qt: {}", 
+                arrange_millis::get(Instant::now().duration_since(start).as_millis()), 
+                qt,
+            ));
+        }
+        println!("{}, Карточки прочитаны: {}", arrange_millis::get(Instant::now().duration_since(start).as_millis()), qt);
+
+        Ok(())
+    }
 
-        // let file_path = Path::new("out_test/some.csv");
-        // let arg = to_csv::Arg {
-        //     records: &records,
-        //     file_path: &file_path,
-        // };
-        // to_csv::write(arg).await?;
-        //
-        // println!("{}, Записаны в файл {:?}", arrange_millis::get(Instant::now().duration_since(start).as_millis()), file_path);
+    #[tokio::test]
+    async fn test_stream() -> Result<()> {
+        test_helper::init();
 
+        let out_dir = Path::new("out_test/stream_test");
+        if out_dir.exists() {
+            std::fs::remove_dir_all(out_dir)?;
+        }
+        let json = json::Json::from_file("../cards/test_data/card.json").await?;
+        let store: Arc<dyn cards::CardStore> = Arc::new(cards::files::Files::new(out_dir));
+        store.put(1, &cards::Fetched::Record(cards::Fetched::parse_json(&json, false)?))?;
+        store.put(2, &cards::Fetched::NotFound)?;
+        store.put(0x1_0000_0003, &cards::Fetched::Record(cards::Fetched::parse_json(&json, false)?))?;
+
+        let arg = Arg { 
+            out_dir: &out_dir.join("cards"),
+            thread_limit_file: 2,
+        };
+        let records = stream(arg).collect::<Vec<Result<cards::Record>>>().await;
+        assert_eq!(records.len(), 2);
+        assert!(records.iter().all(|record| record.is_ok()));
+
+        let records = stream_stored(store.clone()).collect::<Vec<Result<cards::Record>>>().await;
+        assert_eq!(records.len(), 2);
+        assert!(records.iter().all(|record| record.is_ok()));
+
+        // поток, который перестали читать, не держит обход
+        let mut records = Box::pin(stream_stored(store.clone()));
+        assert!(records.next().await.is_some());
+        drop(records);
+
+        let file_path = cards::file_spec::get(out_dir, 5);
+        std::fs::create_dir_all(file_path.parent().unwrap())?;
+        std::fs::write(file_path, "{")?;
+        let records = stream_stored(store).collect::<Vec<Result<cards::Record>>>().await;
+        assert!(records.last().unwrap().is_err());
+
+        std::fs::remove_dir_all(out_dir)?;
         Ok(())
     }
 
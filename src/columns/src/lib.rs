@@ -0,0 +1,508 @@
+
+#[allow(unused_imports)]
+use log::{error, warn, info, debug, trace};
+#[allow(unused_imports)]
+use anyhow::{Result, Error, bail, anyhow, Context};
+
+use chrono::{DateTime, SecondsFormat, Utc, format::{Item, StrftimeItems}};
+use serde::{Serialize, Deserialize};
+use std::collections::HashSet;
+use std::fmt;
+
+// ============================================================================
+// ============================================================================
+
+// Вид значения столбца; форматированный столбец (см. Spec) - всегда Str
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Str,
+    Int,
+    Float,
+    Bool,
+    Time,
+    // строка автокаталога с числом ("2.0", "1 995"): значение - Cell::Str, а выгрузки с числовыми
+    // столбцами (to_parquet, to_xlsx) пишут его числом, если оно разбирается (см. parse_num)
+    NumStr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cell {
+    Null,
+    Str(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Time(DateTime<Utc>),
+    // справочное значение: пишется кодом, а где нужны подписи (to_xlsx) - подписью
+    Vocab(vocab::Any),
+}
+
+// Текстом - как пишет to_csv: null - пустая строка, время - RFC3339, справочное значение - кодом
+impl fmt::Display for Cell {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Cell::Null => Ok(()),
+            Cell::Str(val) => write!(f, "{}", val),
+            Cell::Int(val) => write!(f, "{}", val),
+            Cell::Float(val) => write!(f, "{}", val),
+            Cell::Bool(val) => write!(f, "{}", val),
+            Cell::Time(val) => write!(f, "{}", val.to_rfc3339_opts(SecondsFormat::AutoSi, true)),
+            Cell::Vocab(val) => write!(f, "{}", val),
+        }
+    }
+}
+
+trait ToCell {
+    fn to_cell(&self) -> Cell;
+}
+
+impl ToCell for Option<String> {
+    fn to_cell(&self) -> Cell {
+        match self {
+            None => Cell::Null,
+            Some(s) => Cell::Str(s.to_owned()),
+        }
+    }
+}
+
+// Справочные значения (см. crate vocab) - единственное место, где они становятся значением выгрузки
+macro_rules! to_cell_vocab {
+    ($($t: ty),*) => {
+        $(
+            impl ToCell for Option<$t> {
+                fn to_cell(&self) -> Cell {
+                    match self {
+                        None => Cell::Null,
+                        Some(val) => Cell::Vocab(val.clone().into()),
+                    }
+                }
+            }
+        )*
+    };
+}
+to_cell_vocab!(vocab::BodyType, vocab::Drive, vocab::Transmission, vocab::FuelType, vocab::Condition);
+
+macro_rules! to_cell_int {
+    ($($t: ty),*) => {
+        $(
+            impl ToCell for Option<$t> {
+                fn to_cell(&self) -> Cell {
+                    match self {
+                        None => Cell::Null,
+                        Some(val) => Cell::Int(*val as i64),
+                    }
+                }
+            }
+        )*
+    };
+}
+to_cell_int!(u8, u16, u64, usize, i64);
+
+impl ToCell for Option<f64> {
+    fn to_cell(&self) -> Cell {
+        match self {
+            None => Cell::Null,
+            Some(val) => Cell::Float(*val),
+        }
+    }
+}
+
+impl ToCell for Option<bool> {
+    fn to_cell(&self) -> Cell {
+        match self {
+            None => Cell::Null,
+            Some(val) => Cell::Bool(*val),
+        }
+    }
+}
+
+impl ToCell for Option<DateTime<Utc>> {
+    fn to_cell(&self) -> Cell {
+        match self {
+            None => Cell::Null,
+            Some(val) => Cell::Time(*val),
+        }
+    }
+}
+
+// images и extra - строками, как в csv: ссылки через пробел и json-объект
+impl ToCell for Option<cards::Images> {
+    fn to_cell(&self) -> Cell {
+        match self {
+            None => Cell::Null,
+            Some(images) => Cell::Str(images.0.join(" ")),
+        }
+    }
+}
+
+impl ToCell for Option<cards::Extra> {
+    fn to_cell(&self) -> Cell {
+        match self {
+            None => Cell::Null,
+            Some(extra) => Cell::Str(serde_json::to_string(&extra.0).unwrap_or_default()),
+        }
+    }
+}
+
+// Поля записи - общий список для профилей, фильтра (crate filter) и выгрузок: имя (как в cards::Record)
+// и вид значения. Поле - индекс в FIELDS, значение достается функцией get
+macro_rules! fields {
+    ($($name: ident: $kind: ident),* $(,)?) => {
+        pub const FIELDS: &[(&str, Kind)] = &[
+            $((stringify!($name), Kind::$kind)),*
+        ];
+        pub fn get(record: &cards::Record, field: usize) -> Cell {
+            let mut i = 0;
+            $(
+                if i == field {
+                    return record.$name.to_cell();
+                }
+                i += 1;
+            )*
+            unreachable!("field {} of {}", field, i)
+        }
+    };
+}
+
+fields! {
+    id: Int,
+    body_type: Str,
+    brand: Str,
+    color: Str,
+    fuel_type: Str,
+    name: Str,
+    title: Str,
+    number_of_doors: Int,
+    production_date: Int,
+    vehicle_transmission: Str,
+    engine_displacement: Str,
+    engine_power: Str,
+    description: Str,
+    mileage: Int,
+    drive: Str,
+    steering_wheel: Str,
+    condition: Str,
+    owners: Str,
+    power_windows: Str,
+    power_steering: Str,
+    audio_system: Str,
+    headlights: Str,
+    climate_control: Str,
+    interior: Str,
+    rims: Str,
+    autocatalog_url: Str,
+    item_price: Int,
+    market_price: Int,
+    status: Str,
+    closing_reason: Str,
+    complectation: Str,
+    modification: Str,
+    generation: Str,
+    type_of_trade: Str,
+    canonical_url: Str,
+    location_id: Int,
+    lat: Float,
+    lng: Float,
+    time: Time,
+    views_total: Int,
+    views_today: Int,
+    images_qt: Int,
+    images: Str,
+    has_video: Bool,
+    first_seen: Time,
+    last_seen: Time,
+    closed_at: Time,
+    days_on_market: Int,
+    cluster_id: Int,
+    duplicate_of: Int,
+    change_type: Str,
+    power_hp: Float,
+    power_kw: Float,
+    displacement_l: Float,
+    displacement_cc: Float,
+    torque_nm: Float,
+    max_speed_kmh: Float,
+    acceleration_s: Float,
+    consumption_city_l100km: Float,
+    consumption_highway_l100km: Float,
+    consumption_mixed_l100km: Float,
+    fuel_tank_l: Float,
+    length_mm: Float,
+    width_with_mirrors_mm: Float,
+    height_mm: Float,
+    wheelbase_mm: Float,
+    clearance_mm: Float,
+    front_track_mm: Float,
+    rear_track_mm: Float,
+    extra: Str,
+    autocatalog_id: Int,
+    autocatalog_title: Str,
+    autocatalog_match_method: Str,
+    autocatalog_match_confidence: Float,
+    autocatalog_match_candidates: Int,
+    autocatalog_transmission: Str,
+    autocatalog_engine_displacement: NumStr,
+    autocatalog_engine_displacement_precise: NumStr,
+    autocatalog_drive: Str,
+    autocatalog_fuel_type: Str,
+    autocatalog_engine_power: NumStr,
+    autocatalog_maximum_speed: NumStr,
+    autocatalog_acceleration: NumStr,
+    autocatalog_brand_country: Str,
+    autocatalog_assembly_country: Str,
+    autocatalog_number_of_seats: NumStr,
+    autocatalog_rating: NumStr,
+    autocatalog_number_of_cylinders: NumStr,
+    autocatalog_configuration: Str,
+    autocatalog_torque: NumStr,
+    autocatalog_torque_max: Str,
+    autocatalog_max_power_speed: Str,
+    autocatalog_height: NumStr,
+    autocatalog_length: NumStr,
+    autocatalog_turning_diameter: NumStr,
+    autocatalog_clearance: NumStr,
+    autocatalog_wheelbase: NumStr,
+    autocatalog_rear_track: NumStr,
+    autocatalog_front_track: NumStr,
+    autocatalog_trunk_volume: Str,
+    autocatalog_fuel_tank_capacity: NumStr,
+    autocatalog_fuel_consumption_city: NumStr,
+    autocatalog_fuel_consumption_highway: NumStr,
+    autocatalog_fuel_consumption_mixed: NumStr,
+    autocatalog_environmental_class: Str,
+    autocatalog_rear_breaks: Str,
+    autocatalog_front_breaks: Str,
+    autocatalog_rear_tire_dimension: Str,
+    autocatalog_front_tire_dimension: Str,
+    autocatalog_rear_suspension: Str,
+    autocatalog_front_suspension: Str,
+    autocatalog_world_premier: Str,
+    autocatalog_pending_update: Str,
+    autocatalog_width_with_mirrors: NumStr,
+    autocatalog_rear_disc_dimension: Str,
+    autocatalog_front_disc_dimension: Str,
+}
+
+pub fn find(name: &str) -> Option<(usize, Kind)> {
+    FIELDS.iter().position(|(field, _)| *field == name).map(|i| (i, FIELDS[i].1))
+}
+
+// Вложенные поля: в csv, ndjson и профилях - строкой (ссылки через пробел, json-объект),
+// в постоянные столбцы to_parquet, to_sqlite, to_postgres и to_xlsx не входят
+pub const NESTED: &[&str] = &["images", "extra"];
+
+// Поля без вложенных: индекс в FIELDS, имя, вид
+pub fn flat() -> impl Iterator<Item = (usize, &'static str, Kind)> {
+    FIELDS.iter().enumerate()
+        .filter(|(_, (name, _))| !NESTED.contains(name))
+        .map(|(i, (name, kind))| (i, *name, *kind))
+}
+
+// "2.0", "1 995", "7,9" - число; "-", "1750-2500" - нет
+pub fn parse_num(s: &str) -> Option<f64> {
+    let s: String = s.trim().chars().filter(|c| !c.is_whitespace()).map(|c| if c == ',' { '.' } else { c }).collect();
+    s.parse::<f64>().ok()
+}
+
+// ============================================================================
+
+// Столбец профиля в настройках (export_profiles):
+//   { field = "item_price", label = "Цена", thousands_separator = " " }
+// label - заголовок (по умолчанию - имя поля); thousands_separator, decimals, decimal_separator - для чисел,
+// date_format - для времени (strftime, например "%d.%m.%Y")
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Spec {
+    pub field: String,
+    #[serde(default)]
+    pub label: Option<String>,
+    #[serde(default)]
+    pub thousands_separator: Option<String>,
+    #[serde(default)]
+    pub decimals: Option<usize>,
+    #[serde(default)]
+    pub decimal_separator: Option<String>,
+    #[serde(default)]
+    pub date_format: Option<String>,
+}
+
+impl Spec {
+    // Поле как есть: заголовок - имя поля, без форматирования
+    pub fn new(field: &str) -> Self {
+        Self {
+            field: field.to_owned(),
+            label: None,
+            thousands_separator: None,
+            decimals: None,
+            decimal_separator: None,
+            date_format: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Column {
+    pub label: String,
+    pub kind: Kind,
+    field: usize,
+    spec: Spec,
+}
+
+impl Column {
+    fn new(spec: &Spec) -> Result<Self> {
+        let (field, kind) = find(&spec.field)
+            .ok_or_else(|| anyhow!("unknown field {:?}, expected one of {}", spec.field, names()))?;
+        let is_num_format = spec.thousands_separator.is_some() || spec.decimals.is_some() || spec.decimal_separator.is_some();
+        if is_num_format && kind != Kind::Int && kind != Kind::Float {
+            bail!("field {:?} is not a number, number format is not applicable", spec.field);
+        }
+        if spec.decimals.is_some() && kind != Kind::Float {
+            bail!("field {:?} is integer, decimals is not applicable", spec.field);
+        }
+        if let Some(date_format) = &spec.date_format {
+            if kind != Kind::Time {
+                bail!("field {:?} is not a time, date_format is not applicable", spec.field);
+            }
+            if StrftimeItems::new(date_format).any(|item| item == Item::Error) {
+                bail!("invalid date_format {:?} for field {:?}", date_format, spec.field);
+            }
+        }
+        Ok(Self {
+            label: spec.label.clone().unwrap_or_else(|| spec.field.clone()),
+            kind: if is_num_format || spec.date_format.is_some() { Kind::Str } else { kind },
+            field,
+            spec: spec.clone(),
+        })
+    }
+    fn cell(&self, record: &cards::Record) -> Cell {
+        let spec = &self.spec;
+        match get(record, self.field) {
+            Cell::Int(val) if self.kind == Kind::Str => Cell::Str(format_num(&val.to_string(), spec)),
+            Cell::Float(val) if self.kind == Kind::Str => {
+                let s = match spec.decimals {
+                    Some(decimals) => format!("{:.*}", decimals, val),
+                    None => val.to_string(),
+                };
+                Cell::Str(format_num(&s, spec))
+            },
+            Cell::Time(val) if spec.date_format.is_some() => Cell::Str(val.format(spec.date_format.as_ref().unwrap()).to_string()),
+            cell => cell,
+        }
+    }
+}
+
+fn names() -> String {
+    FIELDS.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(", ")
+}
+
+// "-1234567.5" -> "-1 234 567,5" (thousands_separator = " ", decimal_separator = ",")
+fn format_num(s: &str, spec: &Spec) -> String {
+    let (sign, s) = if let Some(s) = s.strip_prefix('-') { ("-", s) } else { ("", s) };
+    let (int, frac) = match s.find('.') {
+        Some(i) => (&s[..i], Some(&s[i + 1..])),
+        None => (s, None),
+    };
+    let mut ret = sign.to_owned();
+    for (i, c) in int.chars().enumerate() {
+        if i > 0 && (int.len() - i) % 3 == 0 {
+            if let Some(separator) = &spec.thousands_separator {
+                ret.push_str(separator);
+            }
+        }
+        ret.push(c);
+    }
+    if let Some(frac) = frac {
+        ret.push_str(spec.decimal_separator.as_deref().unwrap_or("."));
+        ret.push_str(frac);
+    }
+    ret
+}
+
+// Набор столбцов выгрузки: какие поля, в каком порядке, с какими заголовками и форматом.
+// Применяется ко всем форматам выгрузки (см. to_csv, to_parquet, to_sqlite, to_ndjson, to_xlsx)
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub columns: Vec<Column>,
+}
+
+impl Profile {
+    pub fn new(specs: &[Spec]) -> Result<Self> {
+        if specs.is_empty() {
+            bail!("profile has no columns");
+        }
+        let mut columns = Vec::new();
+        let mut labels = HashSet::new();
+        for spec in specs.iter() {
+            let column = Column::new(spec)?;
+            if !labels.insert(column.label.clone()) {
+                bail!("duplicate column label {:?}", column.label);
+            }
+            columns.push(column);
+        }
+        Ok(Self { columns })
+    }
+    pub fn labels(&self) -> Vec<&str> {
+        self.columns.iter().map(|column| column.label.as_str()).collect()
+    }
+    pub fn row(&self, record: &cards::Record) -> Vec<Cell> {
+        self.columns.iter().map(|column| column.cell(record)).collect()
+    }
+}
+
+// ============================================================================
+// ============================================================================
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use log::{error, warn, info, debug, trace};
+    use super::*;
+
+    #[tokio::test]
+    async fn test_columns() -> Result<()> {
+        test_helper::init();
+
+        let json = json::Json::from_file("../cards/test_data/card.json").await?;
+        let mut record = cards::Fetched::parse_json(&json, false)?;
+        record.item_price = Some(1_234_567);
+        record.lat = Some(-55.5);
+        record.time = Some(DateTime::parse_from_rfc3339("2020-07-01T12:00:00Z")?.with_timezone(&Utc));
+
+        let profile = Profile::new(&[
+            Spec { label: Some("Цена".to_owned()), thousands_separator: Some(" ".to_owned()), ..Spec::new("item_price") },
+            Spec::new("brand"),
+            Spec { decimals: Some(2), decimal_separator: Some(",".to_owned()), ..Spec::new("lat") },
+            Spec { date_format: Some("%d.%m.%Y".to_owned()), ..Spec::new("time") },
+            Spec::new("id"),
+        ])?;
+        assert_eq!(profile.labels(), vec!["Цена", "brand", "lat", "time", "id"]);
+        let kinds: Vec<Kind> = profile.columns.iter().map(|column| column.kind).collect();
+        assert_eq!(kinds, vec![Kind::Str, Kind::Str, Kind::Str, Kind::Str, Kind::Int]);
+        assert_eq!(profile.row(&record), vec![
+            Cell::Str("1 234 567".to_owned()),
+            Cell::Str("KIA".to_owned()),
+            Cell::Str("-55,50".to_owned()),
+            Cell::Str("01.07.2020".to_owned()),
+            Cell::Int(1767797249),
+        ]);
+
+        // формат числа делает столбец строковым, даже если для целого он ничего не меняет
+        let profile = Profile::new(&[Spec { decimal_separator: Some(",".to_owned()), ..Spec::new("item_price") }])?;
+        assert_eq!(profile.columns[0].kind, Kind::Str);
+        assert_eq!(profile.row(&record), vec![Cell::Str("1234567".to_owned())]);
+
+        assert!(Profile::new(&[Spec::new("no_such_field")]).is_err());
+        assert!(Profile::new(&[Spec::new("id"), Spec::new("id")]).is_err());
+        assert!(Profile::new(&[Spec { date_format: Some("%d".to_owned()), ..Spec::new("brand") }]).is_err());
+        assert!(Profile::new(&[Spec { decimals: Some(2), ..Spec::new("item_price") }]).is_err());
+        assert!(Profile::new(&[]).is_err());
+
+        assert_eq!(find("autocatalog_engine_power").map(|(_, kind)| kind), Some(Kind::NumStr));
+        assert!(flat().all(|(_, name, _)| name != "images" && name != "extra"));
+        assert_eq!(parse_num("1 995"), Some(1995.0));
+        assert_eq!(parse_num("7,9"), Some(7.9));
+        assert_eq!(parse_num("1750-2500"), None);
+
+        Ok(())
+    }
+}
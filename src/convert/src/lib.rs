@@ -372,6 +372,19 @@ pub fn convert_file_path(file_path: PathBuf) -> Result<PathBuf> {
     }
 }
 
+// id карточки по пути в старой раскладке: /out/00/00/00/00/25/3f/9e/88.json -> 0x00000000253f9e88
+pub fn convert_file_path_to_id(file_path: &Path) -> Result<u64> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"((?:/[\da-f]{2}){7})/([\da-f]{2})\.json$").unwrap();
+    }
+    if let Some(caps) = RE.captures(&file_path.to_string_lossy()) {
+        let s = format!("{}{}", caps[1].replace("/", ""), &caps[2]);
+        Ok(u64::from_str_radix(&s, 16)?)
+    } else {
+        bail!("no match for {:?}", file_path);
+    }
+}
+
 // ============================================================================
 // ============================================================================
 // ============================================================================
@@ -393,6 +406,9 @@ mod tests {
         let file_path = convert_file_path(file_path)?;
         assert_eq!(file_path.to_string_lossy(), "/out/cards/000000002/53f9e88.json");
 
+        let id = convert_file_path_to_id(Path::new("/out/00/00/00/00/25/3f/9e/88.json"))?;
+        assert_eq!(id, 0x253f9e88);
+
         Ok(())
     }
 
@@ -437,7 +453,7 @@ mod tests {
         let mut errors = Vec::<ErrorCard>::new();
         for ErrorCard {file_path, error: _, json} in records.errors.into_iter() {
             let json = json::Json::new(json, json::JsonSource::FilePath(file_path.to_owned()));
-            match cards::Fetched::parse_json(&json) {
+            match cards::Fetched::parse_json(&json, false) {
                 Ok(record) => {
                     records.cards.push(Card {file_path, record});
                 },
@@ -456,53 +472,32 @@ mod tests {
 
         // let mut term = Term::init(term::Arg::new().header("write cards . . ."))?;
 
+        let store = cards::files::Files::new(Path::new("/out"));
+
         info!("write cards . . .");
         let start = std::time::Instant::now();
         for Card {file_path, record} in records.cards.into_iter() {
-            let file_path = convert_file_path(file_path)?;
-            if let Some(dir_path) = file_path.parent() {
-                fs::create_dir_all(dir_path).await?;
-            }
-            let mut file = fs::File::create(file_path).await?;
-            let fetched = cards::Fetched::Record(record);
-            let json = serde_json::to_string_pretty(&fetched)?;
-            file.write_all(json.as_bytes()).await?;
+            store.put(convert_file_path_to_id(&file_path)?, &cards::Fetched::Record(record))?;
         }
         info!("{}, cards written", arrange_millis::get(Instant::now().duration_since(start).as_millis()));
 
         info!("write errors . . .");
         let start = std::time::Instant::now();
         for ErrorCard {file_path, error, json} in records.errors.into_iter() {
-            let file_path = convert_file_path(file_path)?;
-            if let Some(dir_path) = file_path.parent() {
-                fs::create_dir_all(dir_path).await?;
-            }
-            let mut file = fs::File::create(file_path).await?;
-            let fetched = cards::Fetched::WithError{error, json};
-            let json = serde_json::to_string_pretty(&fetched)?;
-            file.write_all(json.as_bytes()).await?;
+            store.put(convert_file_path_to_id(&file_path)?, &cards::Fetched::WithError{error, json})?;
         }
         info!("{}, errors written", arrange_millis::get(Instant::now().duration_since(start).as_millis()));
 
         info!("write not_found . . .");
         let start = std::time::Instant::now();
         for  file_path in records.not_found.into_iter() {
-            let file_path = convert_file_path(file_path)?;
-            if let Some(dir_path) = file_path.parent() {
-                fs::create_dir_all(dir_path).await?;
-            }
-            let mut file = fs::File::create(file_path).await?;
-            let fetched = cards::Fetched::NotFound;
-            let json = serde_json::to_string_pretty(&fetched)?;
-            file.write_all(json.as_bytes()).await?;
+            store.put(convert_file_path_to_id(&file_path)?, &cards::Fetched::NotFound)?;
         }
         info!("{}, not_found written", arrange_millis::get(Instant::now().duration_since(start).as_millis()));
 
         Ok(())
     }
 
-    use tokio::fs;
-    use tokio::prelude::*;
+    use cards::CardStore;
 
 }
-
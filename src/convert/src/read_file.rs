@@ -15,8 +15,6 @@ pub struct Arg {
     pub file_path: PathBuf,
 }
 
-use std::str;
-
 pub struct Ret {
     pub file_path: PathBuf,
     pub fetched: cards::Fetched
@@ -26,10 +24,9 @@ pub async fn run(arg: Arg) -> Result<Ret> {
     let mut file = File::open(&arg.file_path).await?;
     let mut contents = vec![];
     file.read_to_end(&mut contents).await?;
-    let s = str::from_utf8(&contents)?;
     let ret = Ret {
         file_path: arg.file_path,
-        fetched: serde_json::from_str(s)?,
+        fetched: cards::schema::decode(&contents)?,
     };
     Ok(ret)
 }
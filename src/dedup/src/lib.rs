@@ -0,0 +1,270 @@
+#[allow(unused_imports)]
+use log::{error, warn, info, debug, trace};
+#[allow(unused_imports)]
+use anyhow::{Result, Error, bail, anyhow, Context};
+
+use std::collections::HashMap;
+
+mod minhash;
+
+use minhash::Signature;
+
+// ============================================================================
+// ============================================================================
+
+// Поиск повторов: перевыставленных под новым id и многократно выставленных автомобилей.
+// Кандидаты - записи с одинаковыми маркой, моделью и годом; пара признается повтором,
+// если взвешенная оценка сходства пробега, цвета, описания и места не ниже порога.
+// Повторы объединяются в кластеры (транзитивно)
+
+// Веса признаков; признак, отсутствующий хотя бы у одной записи пары, не учитывается
+const WEIGHT_MILEAGE: f64 = 0.3;
+const WEIGHT_COLOR: f64 = 0.15;
+const WEIGHT_DESCRIPTION: f64 = 0.35;
+const WEIGHT_LOCATION: f64 = 0.2;
+// Оценка по слишком малому числу признаков ненадежна
+const WEIGHT_MIN: f64 = 0.5;
+
+pub const THRESHOLD_DEFAULT: f64 = 0.8;
+
+struct Item {
+    id: u64,
+    time: Option<i64>,
+    mileage: Option<u64>,
+    color: Option<String>,
+    description: Option<Signature>,
+    location_id: Option<u64>,
+    coords: Option<(f64, f64)>,
+}
+
+// Сведения о записях, нужные для оценки пар: по записи в память попадает только это
+pub struct Dedup {
+    threshold: f64,
+    blocks: HashMap<(String, String, u16), Vec<Item>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cluster {
+    // id самого раннего объявления кластера
+    pub cluster_id: u64,
+    // None для самого раннего объявления
+    pub duplicate_of: Option<u64>,
+}
+
+// Кластеры по id записи; запись без повторов образует кластер из себя одной
+pub struct Clusters(HashMap<u64, Cluster>);
+
+impl Dedup {
+    pub fn new(threshold: f64) -> Self {
+        Self {
+            threshold,
+            blocks: HashMap::new(),
+        }
+    }
+    pub fn adopt_record(&mut self, record: &cards::Record) {
+        let (id, brand, model, year) = match (record.id, &record.brand, &record.name, record.production_date) {
+            (Some(id), Some(brand), Some(model), Some(year)) => (id, brand, model, year),
+            _ => return,
+        };
+        let item = Item {
+            id,
+            time: record.time.map(|time| time.timestamp()),
+            mileage: record.mileage,
+            color: record.color.as_ref().map(|color| color.to_lowercase()),
+            description: record.description.as_ref().and_then(|description| Signature::new(description)),
+            location_id: record.location_id,
+            coords: match (record.lat, record.lng) {
+                (Some(lat), Some(lng)) => Some((lat, lng)),
+                _ => None,
+            },
+        };
+        self.blocks
+            .entry((brand.to_lowercase(), model.to_lowercase(), year))
+            .or_default()
+            .push(item);
+    }
+    pub fn clusters(self) -> Clusters {
+        let threshold = self.threshold;
+        let mut ret = HashMap::new();
+        for (_key, items) in self.blocks.into_iter() {
+            let mut parent: Vec<usize> = (0..items.len()).collect();
+            for i in 0..items.len() {
+                for j in i + 1..items.len() {
+                    if score(&items[i], &items[j]).map(|score| score >= threshold) == Some(true) {
+                        let (a, b) = (root(&mut parent, i), root(&mut parent, j));
+                        if a != b {
+                            parent[b] = a;
+                        }
+                    }
+                }
+            }
+            let mut members: HashMap<usize, Vec<usize>> = HashMap::new();
+            for i in 0..items.len() {
+                let root = root(&mut parent, i);
+                members.entry(root).or_default().push(i);
+            }
+            for (_root, mut members) in members.into_iter() {
+                // раньше опубликованное - первоисточник; без времени публикации - меньший id
+                members.sort_by_key(|i| (items[*i].time.unwrap_or(i64::MAX), items[*i].id));
+                let cluster_id = items[members[0]].id;
+                for (n, i) in members.iter().enumerate() {
+                    ret.insert(items[*i].id, Cluster {
+                        cluster_id,
+                        duplicate_of: if n == 0 { None } else { Some(cluster_id) },
+                    });
+                }
+            }
+        }
+        Clusters(ret)
+    }
+}
+
+impl Clusters {
+    pub fn get(&self, id: u64) -> Option<&Cluster> {
+        self.0.get(&id)
+    }
+    pub fn duplicate_qt(&self) -> usize {
+        self.0.values().filter(|cluster| cluster.duplicate_of.is_some()).count()
+    }
+}
+
+fn root(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
+// Оценка сходства пары от 0 до 1; None, если признаков для оценки недостаточно
+fn score(a: &Item, b: &Item) -> Option<f64> {
+    let mut total = 0.0;
+    let mut weight = 0.0;
+    let mut adopt = |score: Option<f64>, w: f64| {
+        if let Some(score) = score {
+            total += score * w;
+            weight += w;
+        }
+    };
+    adopt(match (a.mileage, b.mileage) {
+        (Some(a), Some(b)) => Some(mileage_score(a, b)),
+        _ => None,
+    }, WEIGHT_MILEAGE);
+    adopt(match (&a.color, &b.color) {
+        (Some(a), Some(b)) => Some(if a == b { 1.0 } else { 0.0 }),
+        _ => None,
+    }, WEIGHT_COLOR);
+    adopt(match (&a.description, &b.description) {
+        (Some(a), Some(b)) => Some(a.similarity(b)),
+        _ => None,
+    }, WEIGHT_DESCRIPTION);
+    adopt(match (a.coords, b.coords, a.location_id, b.location_id) {
+        (Some(a), Some(b), _, _) => Some(location_score(distance_km(a, b))),
+        (_, _, Some(a), Some(b)) => Some(if a == b { 0.5 } else { 0.0 }),
+        _ => None,
+    }, WEIGHT_LOCATION);
+    if weight < WEIGHT_MIN {
+        None
+    } else {
+        Some(total / weight)
+    }
+}
+
+// Пробег перевыставленного автомобиля мог немного вырасти
+fn mileage_score(a: u64, b: u64) -> f64 {
+    let diff = a.abs_diff(b);
+    if diff <= 1000 {
+        return 1.0;
+    }
+    let ratio = diff as f64 / a.max(b) as f64;
+    (1.0 - ratio / 0.2).max(0.0)
+}
+
+fn location_score(km: f64) -> f64 {
+    if km <= 1.0 {
+        1.0
+    } else {
+        (1.0 - (km - 1.0) / 29.0).max(0.0)
+    }
+}
+
+fn distance_km((lat1, lng1): (f64, f64), (lat2, lng2): (f64, f64)) -> f64 {
+    const EARTH_RADIUS_KM: f64 = 6371.0;
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let dlat = lat2 - lat1;
+    let dlng = (lng2 - lng1).to_radians();
+    let h = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlng / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * h.sqrt().asin()
+}
+
+// ============================================================================
+// ============================================================================
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use log::{error, warn, info, debug, trace};
+    use super::*;
+
+    #[tokio::test]
+    async fn test_clusters() -> Result<()> {
+        test_helper::init();
+
+        let json = json::Json::from_file("../cards/test_data/card.json").await?;
+        let record = || -> Result<cards::Record> {
+            let mut record = cards::Fetched::parse_json(&json, false)?;
+            record.name = Some("Rio".to_owned());
+            record.production_date = Some(2015);
+            record.mileage = Some(100_000);
+            record.color = Some("белый".to_owned());
+            Ok(record)
+        };
+
+        let mut dedup = Dedup::new(THRESHOLD_DEFAULT);
+
+        let original = record()?;
+        dedup.adopt_record(&original);
+
+        // перевыставлен позже: пробег чуть больше, точка на карте рядом
+        let mut relisted = record()?;
+        relisted.id = Some(2);
+        relisted.time = original.time.map(|time| time + chrono::Duration::days(30));
+        relisted.mileage = Some(101_500);
+        relisted.lat = original.lat.map(|lat| lat + 0.005);
+        dedup.adopt_record(&relisted);
+
+        // та же модель, но другой автомобиль
+        let mut other = record()?;
+        other.id = Some(3);
+        other.mileage = Some(160_000);
+        other.color = Some("черный".to_owned());
+        other.description = Some("Машина на ходу, требует вложений, торг".to_owned());
+        other.lat = original.lat.map(|lat| lat + 0.3);
+        dedup.adopt_record(&other);
+
+        // другой год - не кандидат
+        let mut other_year = record()?;
+        other_year.id = Some(4);
+        other_year.production_date = Some(2016);
+        dedup.adopt_record(&other_year);
+
+        let clusters = dedup.clusters();
+        let original_id = original.id.unwrap();
+        assert_eq!(clusters.get(original_id), Some(&Cluster { cluster_id: original_id, duplicate_of: None }));
+        assert_eq!(clusters.get(2), Some(&Cluster { cluster_id: original_id, duplicate_of: Some(original_id) }));
+        assert_eq!(clusters.get(3), Some(&Cluster { cluster_id: 3, duplicate_of: None }));
+        assert_eq!(clusters.get(4), Some(&Cluster { cluster_id: 4, duplicate_of: None }));
+        assert_eq!(clusters.duplicate_qt(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_distance_km() {
+        test_helper::init();
+
+        let km = distance_km((55.7558, 37.6173), (59.9343, 30.3351));
+        assert!((km - 634.0).abs() < 5.0, "{}", km);
+    }
+}
@@ -0,0 +1,74 @@
+
+#[allow(unused_imports)]
+use log::{error, warn, info, debug, trace};
+#[allow(unused_imports)]
+use anyhow::{Result, Error, bail, anyhow, Context};
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+// ============================================================================
+// ============================================================================
+
+// Сигнатура MinHash описания: доля совпадающих позиций двух сигнатур
+// оценивает сходство Жаккара множеств шинглов (по 3 слова подряд).
+// Хранится вместо самого описания, чтобы не держать описания в памяти
+
+const SIGNATURE_LEN: usize = 16;
+const SHINGLE_LEN: usize = 3;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature([u64; SIGNATURE_LEN]);
+
+impl Signature {
+    pub fn new(text: &str) -> Option<Self> {
+        let words: Vec<String> = text
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(|word| word.to_lowercase())
+            .collect();
+        if words.is_empty() {
+            return None;
+        }
+        let mut ret = [u64::MAX; SIGNATURE_LEN];
+        for shingle in words.windows(SHINGLE_LEN.min(words.len())) {
+            for (seed, min) in ret.iter_mut().enumerate() {
+                let mut hasher = DefaultHasher::new();
+                seed.hash(&mut hasher);
+                shingle.hash(&mut hasher);
+                let hash = hasher.finish();
+                if hash < *min {
+                    *min = hash;
+                }
+            }
+        }
+        Some(Self(ret))
+    }
+    pub fn similarity(&self, other: &Self) -> f64 {
+        let equal_qt = self.0.iter().zip(other.0.iter()).filter(|(a, b)| a == b).count();
+        equal_qt as f64 / SIGNATURE_LEN as f64
+    }
+}
+
+// ============================================================================
+// ============================================================================
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use log::{error, warn, info, debug, trace};
+    use super::*;
+
+    #[test]
+    fn test_signature() {
+        test_helper::init();
+
+        let a = Signature::new("Продаю автомобиль в отличном состоянии, один владелец, сервисная книжка").unwrap();
+        let b = Signature::new("ПРОДАЮ автомобиль в отличном состоянии. Один владелец; сервисная книжка!").unwrap();
+        let c = Signature::new("Срочно, торг у капота, обмен не интересует, звонить вечером").unwrap();
+        assert_eq!(a.similarity(&b), 1.0);
+        assert!(a.similarity(&c) < 0.2);
+        assert!(Signature::new(" ,. ").is_none());
+    }
+}
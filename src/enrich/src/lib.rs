@@ -0,0 +1,374 @@
+#[allow(unused_imports)]
+use log::{error, warn, info, debug, trace};
+#[allow(unused_imports)]
+use anyhow::{Result, Error, bail, anyhow, Context};
+
+use chrono::{DateTime, Utc};
+use futures::future::{self, FutureExt, LocalBoxFuture};
+use std::path::{Path, PathBuf};
+
+pub mod matcher;
+use matcher::Catalog;
+
+// ============================================================================
+// ============================================================================
+
+// Дополнение записи перед выгрузкой данными других источников. Каждый источник - Enricher,
+// заполняющий объявленные им поля записи; конвейер (Pipeline) прогоняет запись через источники
+// в порядке настройки enrichers (scan), так что следующий видит поля, заполненные предыдущими
+
+pub trait Enricher {
+    // Имя в настройке enrichers
+    fn name(&self) -> &'static str;
+    // Поля записи (как в cards::Record), которые заполняет источник
+    fn fields(&self) -> &'static [&'static str];
+    fn enrich<'a>(&'a mut self, record: &'a mut cards::Record) -> LocalBoxFuture<'a, Result<()>>;
+}
+
+pub struct Pipeline<'a> {
+    enrichers: Vec<Box<dyn Enricher + 'a>>,
+}
+
+impl<'a> Pipeline<'a> {
+    pub fn new() -> Self {
+        Self { enrichers: Vec::new() }
+    }
+    // Поле заполняет только один источник
+    pub fn push(&mut self, enricher: Box<dyn Enricher + 'a>) -> Result<()> {
+        for field in enricher.fields() {
+            if let Some(other) = self.enrichers.iter().find(|other| other.fields().contains(field)) {
+                bail!("enricher {:?}: field {:?} is already filled by {:?}", enricher.name(), field, other.name());
+            }
+        }
+        self.enrichers.push(enricher);
+        Ok(())
+    }
+    // Ошибка источника не мешает остальным: запись выгружается без его полей
+    pub async fn run(&mut self, record: &mut cards::Record) {
+        for enricher in self.enrichers.iter_mut() {
+            if let Err(err) = enricher.enrich(record).await {
+                error!("{}: {}: {:#}", enricher.name(), record.id.unwrap_or_default(), err);
+            }
+        }
+    }
+    pub fn names(&self) -> Vec<&'static str> {
+        self.enrichers.iter().map(|enricher| enricher.name()).collect()
+    }
+}
+
+impl<'a> Default for Pipeline<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// ============================================================================
+
+// Поля автокаталога: поле autocatalog::Record => поле записи
+macro_rules! autocatalog_fields {
+    ($($from: ident => $to: ident),* $(,)?) => {
+        const AUTOCATALOG_FIELDS: &[&str] = &[
+            "autocatalog_id",
+            "autocatalog_title",
+            "autocatalog_transmission",
+            "autocatalog_drive",
+            "autocatalog_fuel_type",
+            "autocatalog_match_method",
+            "autocatalog_match_confidence",
+            "autocatalog_match_candidates",
+            $(stringify!($to)),*
+        ];
+        fn adopt_autocatalog(record: &mut cards::Record, autocatalog_record: autocatalog::Record) {
+            record.autocatalog_id = Some(autocatalog_record.id);
+            record.autocatalog_title = Some(autocatalog_record.title);
+            record.autocatalog_transmission = autocatalog_record.transmission.as_deref().map(vocab::Transmission::parse);
+            record.autocatalog_drive = autocatalog_record.drive.as_deref().map(vocab::Drive::parse);
+            record.autocatalog_fuel_type = autocatalog_record.fuel_type.as_deref().map(vocab::FuelType::parse);
+            $(record.$to = autocatalog_record.$from;)*
+        }
+    };
+}
+
+autocatalog_fields! {
+    engine_displacement => autocatalog_engine_displacement,
+    engine_displacement_precise => autocatalog_engine_displacement_precise,
+    engine_power => autocatalog_engine_power,
+    maximum_speed => autocatalog_maximum_speed,
+    acceleration => autocatalog_acceleration,
+    brand_country => autocatalog_brand_country,
+    assembly_country => autocatalog_assembly_country,
+    number_of_seats => autocatalog_number_of_seats,
+    rating => autocatalog_rating,
+    number_of_cylinders => autocatalog_number_of_cylinders,
+    configuration => autocatalog_configuration,
+    torque => autocatalog_torque,
+    torque_max => autocatalog_torque_max,
+    max_power_speed => autocatalog_max_power_speed,
+    height => autocatalog_height,
+    length => autocatalog_length,
+    turning_diameter => autocatalog_turning_diameter,
+    clearance => autocatalog_clearance,
+    wheelbase => autocatalog_wheelbase,
+    rear_track => autocatalog_rear_track,
+    front_track => autocatalog_front_track,
+    trunk_volume => autocatalog_trunk_volume,
+    fuel_tank_capacity => autocatalog_fuel_tank_capacity,
+    fuel_consumption_city => autocatalog_fuel_consumption_city,
+    fuel_consumption_highway => autocatalog_fuel_consumption_highway,
+    fuel_consumption_mixed => autocatalog_fuel_consumption_mixed,
+    environmental_class => autocatalog_environmental_class,
+    rear_breaks => autocatalog_rear_breaks,
+    front_breaks => autocatalog_front_breaks,
+    rear_tire_dimension => autocatalog_rear_tire_dimension,
+    front_tire_dimension => autocatalog_front_tire_dimension,
+    rear_suspension => autocatalog_rear_suspension,
+    front_suspension => autocatalog_front_suspension,
+    world_premier => autocatalog_world_premier,
+    pending_update => autocatalog_pending_update,
+    width_with_mirrors => autocatalog_width_with_mirrors,
+    rear_disc_dimension => autocatalog_rear_disc_dimension,
+    front_disc_dimension => autocatalog_front_disc_dimension,
+}
+
+// Модификация из автокаталога, сохраненная этапом autocatalog в out_dir (см. matcher): для карточки со ссылкой
+// (autocatalog_url) - самая уверенная на странице ссылки, для карточки без ссылки - самая уверенная
+// из модификаций марки и модели, если уверенность не ниже min_confidence.
+// catalog загружается до выгрузки (Catalog::load), а не на первой записи
+pub struct Autocatalog<'c> {
+    out_dir: PathBuf,
+    catalog: &'c Catalog,
+    min_confidence: f64,
+}
+
+impl<'c> Autocatalog<'c> {
+    pub fn new(out_dir: &Path, catalog: &'c Catalog, min_confidence: f64) -> Self {
+        Self { out_dir: out_dir.to_owned(), catalog, min_confidence }
+    }
+    fn adopt(record: &mut cards::Record, found: matcher::Found) {
+        adopt_autocatalog(record, found.modification.record.clone());
+        record.autocatalog_match_method = Some(found.matched.method.to_owned());
+        record.autocatalog_match_confidence = Some(found.matched.confidence);
+        record.autocatalog_match_candidates = Some(found.candidates as u64);
+    }
+}
+
+impl<'c> Enricher for Autocatalog<'c> {
+    fn name(&self) -> &'static str {
+        "autocatalog"
+    }
+    fn fields(&self) -> &'static [&'static str] {
+        AUTOCATALOG_FIELDS
+    }
+    fn enrich<'a>(&'a mut self, record: &'a mut cards::Record) -> LocalBoxFuture<'a, Result<()>> {
+        async move {
+            match record.autocatalog_url.clone() {
+                Some(autocatalog_url) => {
+                    if let Some(found) = self.catalog.find_by_url(record, &autocatalog_url) {
+                        if found.candidates > 1 {
+                            debug!("{:?}: {} modifications match equally", autocatalog_url, found.candidates);
+                        }
+                        Self::adopt(record, found);
+                        return Ok(());
+                    }
+                    // страницы нет среди загруженных: модификация по ссылке без сверки
+                    let autocatalog_record = autocatalog::get(&self.out_dir, &autocatalog_url).await
+                        .context("autocatalog_url not found")?;
+                    adopt_autocatalog(record, autocatalog_record);
+                    record.autocatalog_match_method = Some(matcher::METHOD_URL.to_owned());
+                    record.autocatalog_match_confidence = None;
+                    record.autocatalog_match_candidates = None;
+                },
+                None => {
+                    if let Some(found) = self.catalog.find(record, self.min_confidence) {
+                        Self::adopt(record, found);
+                    }
+                },
+            }
+            Ok(())
+        }.boxed_local()
+    }
+}
+
+// ============================================================================
+
+// Числовые характеристики из строковых полей карточки и автокаталога (см. crate units)
+pub struct Units<'r> {
+    report: &'r mut units::Report,
+}
+
+impl<'r> Units<'r> {
+    pub fn new(report: &'r mut units::Report) -> Self {
+        Self { report }
+    }
+}
+
+impl<'r> Enricher for Units<'r> {
+    fn name(&self) -> &'static str {
+        "units"
+    }
+    fn fields(&self) -> &'static [&'static str] {
+        &[
+            "power_hp", "power_kw", "displacement_l", "displacement_cc",
+            "torque_nm", "max_speed_kmh", "acceleration_s",
+            "consumption_city_l100km", "consumption_highway_l100km", "consumption_mixed_l100km", "fuel_tank_l",
+            "length_mm", "width_with_mirrors_mm", "height_mm", "wheelbase_mm", "clearance_mm", "front_track_mm", "rear_track_mm",
+        ]
+    }
+    fn enrich<'a>(&'a mut self, record: &'a mut cards::Record) -> LocalBoxFuture<'a, Result<()>> {
+        self.report.adopt(record);
+        future::ok(()).boxed_local()
+    }
+}
+
+// ============================================================================
+
+// Жизненный цикл объявления (см. crate lifecycle)
+pub struct Lifecycle<'l> {
+    lifecycle: &'l mut lifecycle::Lifecycle,
+    now: DateTime<Utc>,
+}
+
+impl<'l> Lifecycle<'l> {
+    pub fn new(lifecycle: &'l mut lifecycle::Lifecycle, now: DateTime<Utc>) -> Self {
+        Self { lifecycle, now }
+    }
+}
+
+impl<'l> Enricher for Lifecycle<'l> {
+    fn name(&self) -> &'static str {
+        "lifecycle"
+    }
+    fn fields(&self) -> &'static [&'static str] {
+        &["first_seen", "last_seen", "closed_at", "days_on_market"]
+    }
+    fn enrich<'a>(&'a mut self, record: &'a mut cards::Record) -> LocalBoxFuture<'a, Result<()>> {
+        if let Some(id) = record.id {
+            self.lifecycle.adopt_card(id, record.status.as_deref(), record.closing_reason.as_deref(), self.now);
+            if let Some(item) = self.lifecycle.get(id) {
+                record.first_seen = Some(item.first_seen);
+                record.last_seen = Some(item.last_seen);
+                record.closed_at = item.closed_at;
+                record.days_on_market = Some(item.days_on_market());
+            }
+        }
+        future::ok(()).boxed_local()
+    }
+}
+
+// ============================================================================
+
+// Повторы объявлений (см. crate dedup)
+pub struct Duplicates<'c> {
+    clusters: &'c dedup::Clusters,
+}
+
+impl<'c> Duplicates<'c> {
+    pub fn new(clusters: &'c dedup::Clusters) -> Self {
+        Self { clusters }
+    }
+}
+
+impl<'c> Enricher for Duplicates<'c> {
+    fn name(&self) -> &'static str {
+        "duplicates"
+    }
+    fn fields(&self) -> &'static [&'static str] {
+        &["cluster_id", "duplicate_of"]
+    }
+    fn enrich<'a>(&'a mut self, record: &'a mut cards::Record) -> LocalBoxFuture<'a, Result<()>> {
+        if let Some(cluster) = record.id.and_then(|id| self.clusters.get(id)) {
+            record.cluster_id = Some(cluster.cluster_id);
+            record.duplicate_of = cluster.duplicate_of;
+        }
+        future::ok(()).boxed_local()
+    }
+}
+
+// ============================================================================
+// ============================================================================
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use log::{error, warn, info, debug, trace};
+    use super::*;
+
+    #[tokio::test]
+    async fn test_pipeline() -> Result<()> {
+        test_helper::init();
+
+        let out_dir = Path::new("out_test/enrich_test");
+        if out_dir.exists() {
+            std::fs::remove_dir_all(out_dir)?;
+        }
+        let json = json::Json::from_file("../cards/test_data/card.json").await?;
+        let mut record = cards::Fetched::parse_json(&json, false)?;
+
+        // модификация, сохраненная этапом autocatalog
+        let mut autocatalog_record = autocatalog::Record::new(349628, "2.0 AT (150 л.с.)".to_owned());
+        autocatalog_record.drive = Some("Передний".to_owned());
+        autocatalog_record.transmission = Some("Автомат".to_owned());
+        autocatalog_record.engine_power = Some("150".to_owned());
+        autocatalog_record.engine_displacement = Some("2.0".to_owned());
+        autocatalog_record.clearance = Some("182".to_owned());
+        let file_path = PathBuf::from(format!("{}{}.json", out_dir.to_string_lossy(), record.autocatalog_url.as_ref().unwrap()));
+        std::fs::create_dir_all(file_path.parent().unwrap())?;
+        std::fs::write(&file_path, serde_json::to_string(&autocatalog_record)?)?;
+
+        let mut dedup = dedup::Dedup::new(0.8);
+        dedup.adopt_record(&record);
+        let clusters = dedup.clusters();
+        let mut lifecycle = lifecycle::Lifecycle::new();
+        lifecycle.adopt_ids("params", &[1767797249].iter().cloned().collect(), Utc::now());
+        let mut report = units::Report::new();
+        let catalog = Catalog::load(out_dir).await?;
+        {
+            let mut pipeline = Pipeline::new();
+            pipeline.push(Box::new(Autocatalog::new(out_dir, &catalog, 0.7)))?;
+            pipeline.push(Box::new(Units::new(&mut report)))?;
+            pipeline.push(Box::new(Lifecycle::new(&mut lifecycle, Utc::now())))?;
+            pipeline.push(Box::new(Duplicates::new(&clusters)))?;
+            assert_eq!(pipeline.names(), vec!["autocatalog", "units", "lifecycle", "duplicates"]);
+            assert!(pipeline.push(Box::new(Duplicates::new(&clusters))).unwrap_err().to_string().contains("already filled"));
+            for enricher in pipeline.enrichers.iter() {
+                for field in enricher.fields() {
+                    assert!(columns::find(field).is_some(), "{}: {}", enricher.name(), field);
+                }
+            }
+            pipeline.run(&mut record).await;
+        }
+        assert_eq!(record.autocatalog_id, Some(349628));
+        assert_eq!(record.autocatalog_match_method.as_deref(), Some(matcher::METHOD_URL));
+        assert_eq!((record.autocatalog_match_confidence, record.autocatalog_match_candidates), (Some(1.0), Some(1)));
+        assert_eq!(record.autocatalog_drive, Some(vocab::Drive::Front));
+        assert_eq!(record.autocatalog_clearance.as_deref(), Some("182"));
+        assert_eq!(record.clearance_mm, Some(182.0));
+        assert_eq!(record.power_hp, Some(150.0));
+        assert_eq!(record.cluster_id, record.id);
+        assert!(record.first_seen.is_some());
+        assert_eq!(record.days_on_market, Some(0));
+
+        // без сохраненной модификации запись дополняется остальными источниками
+        record.autocatalog_url = Some("/autocatalog/none".to_owned());
+        record.power_hp = None;
+        let mut pipeline = Pipeline::new();
+        pipeline.push(Box::new(Autocatalog::new(out_dir, &catalog, 0.7)))?;
+        pipeline.push(Box::new(Units::new(&mut report)))?;
+        pipeline.run(&mut record).await;
+        assert_eq!(record.power_hp, Some(150.0));
+
+        // карточка без ссылки сопоставляется с сохраненной модификацией
+        record.autocatalog_url = None;
+        record.autocatalog_id = None;
+        record.autocatalog_match_method = None;
+        let mut autocatalog = Autocatalog::new(out_dir, &catalog, 0.7);
+        autocatalog.enrich(&mut record).await?;
+        assert_eq!(record.autocatalog_id, Some(349628));
+        assert_eq!(record.autocatalog_match_method.as_deref(), Some(matcher::METHOD_TITLE));
+        assert_eq!(record.autocatalog_match_confidence, Some(1.0));
+
+        std::fs::remove_dir_all(out_dir)?;
+        Ok(())
+    }
+}
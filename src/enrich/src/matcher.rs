@@ -0,0 +1,391 @@
+#[allow(unused_imports)]
+use log::{error, warn, info, debug, trace};
+#[allow(unused_imports)]
+use anyhow::{Result, Error, bail, anyhow, Context};
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// ============================================================================
+// ============================================================================
+
+// Сопоставление карточки без ссылки на автокаталог с модификацией автокаталога.
+// Кандидаты - модификации, уже сохраненные этапом autocatalog: <out_dir>/autocatalog/<марка>/<модель>/<поколение>/<кузов>/<id>.json;
+// марка и модель карточки должны совпасть с каталогами, остальные характеристики дают уверенность:
+// долю (по весу) совпавших из тех, что указаны в карточке.
+// Карточка со ссылкой сверяется со всеми модификациями страницы автокаталога (поколение и кузов),
+// а не только с той, на которую указывает ссылка: ссылка бывает на модификацию по умолчанию
+
+pub const METHOD_URL: &str = "url";
+pub const METHOD_TITLE: &str = "title";
+pub const METHOD_SPECS: &str = "specs";
+pub const METHOD_AMBIGUOUS: &str = "ambiguous";
+
+// Вес характеристики в уверенности
+const WEIGHT_GENERATION: f64 = 2.0;
+const WEIGHT_YEAR: f64 = 2.0;
+const WEIGHT_BODY: f64 = 1.0;
+const WEIGHT_TITLE: f64 = 3.0;
+const WEIGHT_POWER: f64 = 2.0;
+const WEIGHT_DISPLACEMENT: f64 = 2.0;
+const WEIGHT_TRANSMISSION: f64 = 1.0;
+const WEIGHT_DRIVE: f64 = 1.0;
+
+// Допустимое расхождение: л.с. и литры
+const POWER_TOLERANCE: f64 = 2.0;
+const DISPLACEMENT_TOLERANCE: f64 = 0.05;
+
+pub struct Modification {
+    pub url: String,
+    pub generation: Option<String>,
+    pub body: Option<String>,
+    pub record: autocatalog::Record,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Match {
+    pub method: &'static str,
+    pub confidence: f64,
+}
+
+pub struct Found<'a> {
+    pub modification: &'a Modification,
+    pub matched: Match,
+    // модификаций с той же (наибольшей) уверенностью, включая выбранную
+    pub candidates: usize,
+}
+
+impl Modification {
+    pub fn id(&self) -> u64 {
+        self.record.id
+    }
+}
+
+// Сохраненные модификации по (марка, модель) в виде slug
+#[derive(Default)]
+pub struct Catalog {
+    models: HashMap<(String, String), Vec<Modification>>,
+}
+
+impl Catalog {
+    // Обход сохраненных модификаций блокирующий (std::fs): выполняется в пуле блокирующих задач
+    pub async fn load(out_dir: &Path) -> Result<Self> {
+        let out_dir = out_dir.to_owned();
+        tokio::task::spawn_blocking(move || Self::read(&out_dir)).await?
+    }
+    fn read(out_dir: &Path) -> Result<Self> {
+        let mut ret = Self::default();
+        let root = out_dir.join("autocatalog");
+        if root.exists() {
+            ret.load_dir(&root, &mut vec![])?;
+        }
+        for modifications in ret.models.values_mut() {
+            modifications.sort_by_key(|modification| modification.record.id);
+        }
+        Ok(ret)
+    }
+    fn load_dir(&mut self, dir: &Path, slugs: &mut Vec<String>) -> Result<()> {
+        for entry in fs::read_dir(dir).context(format!("{:?}", dir))? {
+            let path = entry?.path();
+            let name = match path.file_name() {
+                Some(name) => name.to_string_lossy().to_string(),
+                None => continue,
+            };
+            if path.is_dir() {
+                slugs.push(name);
+                self.load_dir(&path, slugs)?;
+                slugs.pop();
+                continue;
+            }
+            // страница без модификаций (NotFound и т.п.) лежит рядом с каталогом модификаций
+            if slugs.len() < 2 || path.extension().map(|ext| ext == "json") != Some(true) {
+                continue;
+            }
+            let record: autocatalog::Record = match fs::read(&path).map_err(Error::new).and_then(|contents| Ok(serde_json::from_slice(&contents)?)) {
+                Ok(record) => record,
+                Err(err) => {
+                    debug!("{:?}: {}", path, err);
+                    continue;
+                },
+            };
+            let url = PathBuf::from("/autocatalog").join(slugs.join("/")).join(path.file_stem().unwrap()).to_string_lossy().to_string();
+            let modification = Modification {
+                url,
+                generation: slugs.get(2).cloned(),
+                body: slugs.get(3).cloned(),
+                record,
+            };
+            self.models.entry((normalize(&slugs[0]), normalize(&slugs[1]))).or_default().push(modification);
+        }
+        Ok(())
+    }
+    pub fn len(&self) -> usize {
+        self.models.values().map(|modifications| modifications.len()).sum()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    fn model(&self, brand: &str, model: &str) -> &[Modification] {
+        self.models.get(&(normalize(brand), normalize(model))).map(|modifications| &modifications[..]).unwrap_or(&[])
+    }
+    // Модификации марки и модели карточки
+    pub fn candidates(&self, record: &cards::Record) -> &[Modification] {
+        match (&record.brand, &record.name) {
+            (Some(brand), Some(name)) => self.model(&slugify(brand), &slugify(name)),
+            _ => &[],
+        }
+    }
+    // Самая уверенная модификация марки и модели карточки не ниже порога
+    pub fn find(&self, record: &cards::Record, min_confidence: f64) -> Option<Found<'_>> {
+        best(record, self.candidates(record).iter(), None).filter(|found| found.matched.confidence >= min_confidence)
+    }
+    // Самая уверенная модификация страницы, на модификацию которой ссылается карточка:
+    // "/autocatalog/kia/sportage/iv-2015n-v_5321/vnedorozhnik/349628" - модификации .../vnedorozhnik/*;
+    // None - страницы нет среди сохраненных
+    pub fn find_by_url(&self, record: &cards::Record, autocatalog_url: &str) -> Option<Found<'_>> {
+        let (page, id) = match autocatalog_url.rfind('/') {
+            Some(i) => (&autocatalog_url[..=i], autocatalog_url[i + 1..].parse::<u64>().ok()),
+            None => return None,
+        };
+        let slugs: Vec<&str> = page.split('/').filter(|s| !s.is_empty()).collect();
+        if slugs.len() < 3 {
+            return None;
+        }
+        let on_page = self.model(slugs[1], slugs[2]).iter()
+            .filter(|modification| modification.url.starts_with(page) && !modification.url[page.len()..].contains('/'));
+        let mut ret = best(record, on_page, id)?;
+        if ret.candidates == 1 && Some(ret.modification.id()) == id {
+            ret.matched.method = METHOD_URL;
+        }
+        Some(ret)
+    }
+}
+
+// Модификации с наибольшей уверенностью: выбирается preferred (по ссылке), если она среди них, иначе - с меньшим id;
+// несколько равных - неоднозначность
+fn best<'a, I>(record: &cards::Record, modifications: I, preferred: Option<u64>) -> Option<Found<'a>>
+where
+    I: Iterator<Item = &'a Modification>,
+{
+    let mut ret: Option<Found> = None;
+    for modification in modifications {
+        let matched = score(record, modification);
+        ret = match ret {
+            None => Some(Found { modification, matched, candidates: 1 }),
+            Some(best) if matched.confidence > best.matched.confidence => Some(Found { modification, matched, candidates: 1 }),
+            Some(best) if (matched.confidence - best.matched.confidence).abs() < f64::EPSILON => {
+                let is_preferred = preferred == Some(modification.id());
+                Some(Found {
+                    modification: if is_preferred { modification } else { best.modification },
+                    matched: if is_preferred { matched } else { best.matched },
+                    candidates: best.candidates + 1,
+                })
+            },
+            best => best,
+        };
+    }
+    ret.map(|mut found| {
+        if found.candidates > 1 {
+            found.matched.method = METHOD_AMBIGUOUS;
+        }
+        found
+    })
+}
+
+// ============================================================================
+
+// Уверенность сопоставления карточки с модификацией
+pub fn score(record: &cards::Record, modification: &Modification) -> Match {
+    let candidate = &modification.record;
+    let mut total = 0.0;
+    let mut matched = 0.0;
+    let mut check = |weight: f64, is_match: Option<bool>| {
+        if let Some(is_match) = is_match {
+            total += weight;
+            if is_match {
+                matched += weight;
+            }
+        }
+    };
+
+    // у характеристики, указанной в карточке, но не в автокаталоге, совпадения нет
+    check(WEIGHT_GENERATION, record.generation.as_ref().map(|generation| {
+        modification.generation.as_deref().map(generation_key) == Some(generation_key(generation))
+    }));
+    check(WEIGHT_YEAR, record.production_date.map(|year| {
+        match modification.generation.as_deref().and_then(years) {
+            Some((from, to)) => from <= year && to.map(|to| year <= to) != Some(false),
+            None => false,
+        }
+    }));
+    check(WEIGHT_BODY, record.body_type.as_ref().map(|body_type| {
+        modification.body.as_deref().map(normalize) == Some(normalize(&slugify(body_type.label_ru())))
+    }));
+    let title_matched = record.modification.as_ref().map(|title| normalize_text(title) == normalize_text(&candidate.title));
+    check(WEIGHT_TITLE, title_matched);
+    check(WEIGHT_POWER, record.engine_power.as_deref().and_then(units::power_hp).map(|hp| {
+        match candidate.engine_power.as_deref().and_then(units::power_hp) {
+            Some(candidate_hp) => (hp - candidate_hp).abs() <= POWER_TOLERANCE,
+            None => false,
+        }
+    }));
+    check(WEIGHT_DISPLACEMENT, record.engine_displacement.as_deref().and_then(units::displacement_l).map(|l| {
+        match candidate.engine_displacement.as_deref().and_then(units::displacement_l) {
+            Some(candidate_l) => (l - candidate_l).abs() <= DISPLACEMENT_TOLERANCE,
+            None => false,
+        }
+    }));
+    check(WEIGHT_TRANSMISSION, record.vehicle_transmission.as_ref().map(|transmission| {
+        candidate.transmission.as_deref().map(vocab::Transmission::parse).as_ref() == Some(transmission)
+    }));
+    check(WEIGHT_DRIVE, record.drive.as_ref().map(|drive| {
+        candidate.drive.as_deref().map(vocab::Drive::parse).as_ref() == Some(drive)
+    }));
+
+    let confidence = if total > 0.0 { (matched / total * 100.0).round() / 100.0 } else { 0.0 };
+    let method = if title_matched == Some(true) { METHOD_TITLE } else { METHOD_SPECS };
+    Match { method, confidence }
+}
+
+// Поколение: римский номер или первое слово: "IV (2016—2018)" -> "iv", "iv-2015n-v_5321" -> "iv"
+fn generation_key(s: &str) -> String {
+    s.split(|c: char| c == '-' || c == '_' || c == '(' || c.is_whitespace())
+        .find(|s| !s.is_empty())
+        .map(|s| s.to_lowercase())
+        .unwrap_or_default()
+}
+
+// Годы выпуска поколения из slug: "iv-2015n-v_5321" -> (2015, None), "iii-2010-2016_1234" -> (2010, Some(2016))
+fn years(slug: &str) -> Option<(u16, Option<u16>)> {
+    let mut numbers = slug.split(|c: char| !c.is_ascii_digit())
+        .filter(|s| s.len() == 4)
+        .filter_map(|s| s.parse::<u16>().ok())
+        .filter(|year| *year >= 1900 && *year <= 2100);
+    let from = numbers.next()?;
+    Some((from, numbers.next()))
+}
+
+// Транслитерация, как в адресах автокаталога: "5 серия" -> "5_seriya", "Внедорожник" -> "vnedorozhnik"
+fn slugify(s: &str) -> String {
+    let mut ret = String::new();
+    for c in s.to_lowercase().chars() {
+        let t = match c {
+            'а' => "a", 'б' => "b", 'в' => "v", 'г' => "g", 'д' => "d", 'е' => "e", 'ё' => "e",
+            'ж' => "zh", 'з' => "z", 'и' => "i", 'й' => "y", 'к' => "k", 'л' => "l", 'м' => "m",
+            'н' => "n", 'о' => "o", 'п' => "p", 'р' => "r", 'с' => "s", 'т' => "t", 'у' => "u",
+            'ф' => "f", 'х' => "h", 'ц' => "ts", 'ч' => "ch", 'ш' => "sh", 'щ' => "sch", 'ъ' => "",
+            'ы' => "y", 'ь' => "", 'э' => "e", 'ю' => "yu", 'я' => "ya",
+            c if c.is_ascii_alphanumeric() => {
+                ret.push(c);
+                continue;
+            },
+            _ => "_",
+        };
+        ret.push_str(t);
+    }
+    ret
+}
+
+// Для сравнения slug: без разделителей
+fn normalize(slug: &str) -> String {
+    slug.chars().filter(|c| c.is_ascii_alphanumeric()).collect::<String>().to_lowercase()
+}
+
+fn normalize_text(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<&str>>().join(" ").to_lowercase()
+}
+
+// ============================================================================
+// ============================================================================
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use log::{error, warn, info, debug, trace};
+    use super::*;
+
+    fn save(out_dir: &Path, url: &str, record: &autocatalog::Record) -> Result<()> {
+        let file_path = PathBuf::from(format!("{}{}.json", out_dir.to_string_lossy(), url));
+        fs::create_dir_all(file_path.parent().unwrap())?;
+        fs::write(&file_path, serde_json::to_string(record)?)?;
+        Ok(())
+    }
+
+    fn modification(id: u64, title: &str, power: &str, displacement: &str, transmission: &str, drive: &str) -> autocatalog::Record {
+        let mut ret = autocatalog::Record::new(id, title.to_owned());
+        ret.engine_power = Some(power.to_owned());
+        ret.engine_displacement = Some(displacement.to_owned());
+        ret.transmission = Some(transmission.to_owned());
+        ret.drive = Some(drive.to_owned());
+        ret
+    }
+
+    #[tokio::test]
+    async fn test_matcher() -> Result<()> {
+        test_helper::init();
+
+        assert_eq!(slugify("5 серия"), "5_seriya");
+        assert_eq!(slugify("Внедорожник"), "vnedorozhnik");
+        assert_eq!(generation_key("IV (2016—2018)"), "iv");
+        assert_eq!(years("iv-2015n-v_5321"), Some((2015, None)));
+        assert_eq!(years("iii-2010-2016_1234"), Some((2010, Some(2016))));
+
+        let out_dir = Path::new("out_test/matcher_test");
+        if out_dir.exists() {
+            fs::remove_dir_all(out_dir)?;
+        }
+        let page = "/autocatalog/kia/sportage/iv-2015n-v_5321/vnedorozhnik";
+        save(out_dir, &format!("{}/349628", page), &modification(349628, "2.0 AT (150 л.с.)", "150", "2.0", "автомат", "передний"))?;
+        save(out_dir, &format!("{}/349630", page), &modification(349630, "2.0 AT 4WD (150 л.с.)", "150", "2.0", "автомат", "полный"))?;
+        save(out_dir, &format!("{}/349640", page), &modification(349640, "1.6 MT (132 л.с.)", "132", "1.6", "механика", "передний"))?;
+        save(out_dir, "/autocatalog/kia/rio/iv-2017n-v_1/sedan/1", &modification(1, "1.6 AT (123 л.с.)", "123", "1.6", "автомат", "передний"))?;
+        // страница без модификаций
+        fs::write(out_dir.join("autocatalog/kia/sportage/iii-2010-2016_1.json"), r#""NotFound""#)?;
+
+        let catalog = Catalog::load(out_dir).await?;
+        assert_eq!(catalog.len(), 4);
+
+        let json = json::Json::from_file("../cards/test_data/card.json").await?;
+        let mut record = cards::Fetched::parse_json(&json, false)?;
+        record.autocatalog_url = None;
+        assert_eq!(catalog.candidates(&record).len(), 3);
+
+        let found = catalog.find(&record, 0.7).unwrap();
+        assert_eq!(found.modification.url, format!("{}/349628", page));
+        assert_eq!((found.matched, found.candidates), (Match { method: METHOD_TITLE, confidence: 1.0 }, 1));
+
+        // по ссылке: выбирается модификация страницы, подходящая по характеристикам, а не та, на которую ссылка
+        let found = catalog.find_by_url(&record, &format!("{}/349628", page)).unwrap();
+        assert_eq!((found.modification.id(), found.matched.method), (349628, METHOD_URL));
+        let found = catalog.find_by_url(&record, &format!("{}/349640", page)).unwrap();
+        assert_eq!((found.modification.id(), found.matched.method), (349628, METHOD_TITLE));
+        assert!(catalog.find_by_url(&record, "/autocatalog/kia/sportage/iii-2010-2016_1/vnedorozhnik/1").is_none());
+
+        // без названия модификации - по характеристикам
+        record.modification = None;
+        let found = catalog.find(&record, 0.7).unwrap();
+        assert_eq!(found.modification.id(), 349628);
+        assert_eq!(found.matched.method, METHOD_SPECS);
+
+        // без привода одинаково подходят обе модификации 2.0 AT: неоднозначность, по ссылке - та, на которую ссылка
+        record.drive = None;
+        let found = catalog.find_by_url(&record, &format!("{}/349630", page)).unwrap();
+        assert_eq!((found.modification.id(), found.matched.method, found.candidates), (349630, METHOD_AMBIGUOUS, 2));
+        let found = catalog.find(&record, 0.7).unwrap();
+        assert_eq!((found.modification.id(), found.candidates), (349628, 2));
+
+        record.drive = Some(vocab::Drive::All);
+        record.production_date = Some(2012);
+        let found = catalog.find(&record, 0.7).unwrap();
+        assert_eq!(found.modification.id(), 349630);
+        assert!(found.matched.confidence < 1.0);
+
+        record.name = Some("Ceed".to_owned());
+        assert!(catalog.find(&record, 0.0).is_none());
+
+        fs::remove_dir_all(out_dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,229 @@
+#[allow(unused_imports)]
+use log::{error, warn, info, debug, trace};
+#[allow(unused_imports)]
+use anyhow::{Result, Error, bail, anyhow, Context};
+
+use chrono::{DateTime, Utc};
+use columns::Cell;
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+pub mod parse;
+
+use parse::{Expr, Op, Literal};
+
+// ============================================================================
+// ============================================================================
+
+// Тип поля для сравнения: целые и дробные - числа, числа автокаталога текстом - строки
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Type {
+    Str,
+    Num,
+    Bool,
+    Time,
+}
+
+impl From<columns::Kind> for Type {
+    fn from(kind: columns::Kind) -> Self {
+        match kind {
+            columns::Kind::Str | columns::Kind::NumStr => Type::Str,
+            columns::Kind::Int | columns::Kind::Float => Type::Num,
+            columns::Kind::Bool => Type::Bool,
+            columns::Kind::Time => Type::Time,
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match self {
+            Type::Str => "string",
+            Type::Num => "number",
+            Type::Bool => "bool",
+            Type::Time => "time",
+        })
+    }
+}
+
+// Отбор записей выражением над полями cards::Record (см. parse и columns::FIELDS):
+//   brand == "BMW" && production_date >= 2015 && item_price < 2000000
+// Поля и типы значений проверяются при разборе, а не при применении
+#[derive(Debug)]
+pub struct Filter {
+    source: String,
+    node: Node,
+}
+
+#[derive(Debug)]
+enum Node {
+    Or(Vec<Node>),
+    And(Vec<Node>),
+    Not(Box<Node>),
+    Cmp {
+        field: usize,
+        op: Op,
+        bound: Bound,
+    },
+}
+
+#[derive(Debug)]
+enum Bound {
+    Null,
+    Bool(bool),
+    Num(f64),
+    Str(String),
+    Time(DateTime<Utc>),
+}
+
+impl Filter {
+    pub fn new(s: &str) -> Result<Self> {
+        let expr = parse::parse(s).context(format!("filter {:?}", s))?;
+        let node = bind(expr).context(format!("filter {:?}", s))?;
+        Ok(Self { source: s.to_owned(), node })
+    }
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+    pub fn matches(&self, record: &cards::Record) -> bool {
+        eval(&self.node, record)
+    }
+}
+
+impl FromStr for Filter {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::new(s)
+    }
+}
+
+// Время задается строкой: "2020-07-01" или "2020-07-01T12:00:00Z"
+fn parse_time(s: &str) -> Option<DateTime<Utc>> {
+    let s = if s.len() == 10 { format!("{}T00:00:00Z", s) } else { s.to_owned() };
+    DateTime::parse_from_rfc3339(&s).ok().map(|val| val.with_timezone(&Utc))
+}
+
+fn bind(expr: Expr) -> Result<Node> {
+    Ok(match expr {
+        Expr::Or(items) => Node::Or(items.into_iter().map(bind).collect::<Result<Vec<Node>>>()?),
+        Expr::And(items) => Node::And(items.into_iter().map(bind).collect::<Result<Vec<Node>>>()?),
+        Expr::Not(expr) => Node::Not(Box::new(bind(*expr)?)),
+        Expr::Cmp { field: name, op, literal, pos } => {
+            let (field, kind) = columns::find(&name)
+                .ok_or_else(|| anyhow!("at {}: unknown field `{}`", pos, name))?;
+            let type_ = Type::from(kind);
+            let mismatch = || anyhow!("at {}: field `{}` is {}, can't be compared with {}", pos, name, type_, literal);
+            let bound = match (type_, &literal) {
+                (_, Literal::Null) => Bound::Null,
+                (Type::Str, Literal::Str(val)) => Bound::Str(val.to_owned()),
+                (Type::Num, Literal::Num(val)) => Bound::Num(*val),
+                (Type::Bool, Literal::Bool(val)) => Bound::Bool(*val),
+                (Type::Time, Literal::Str(val)) => Bound::Time(parse_time(val).ok_or_else(mismatch)?),
+                _ => return Err(mismatch()),
+            };
+            let applicable = match (op, &bound) {
+                (Op::Eq, _) | (Op::Ne, _) => true,
+                (_, Bound::Null) | (_, Bound::Bool(_)) => false,
+                (Op::Contains, Bound::Str(_)) => true,
+                (Op::Contains, _) => false,
+                _ => true,
+            };
+            if !applicable {
+                bail!("at {}: `{}` is not applicable to {} field `{}` and {}", pos, op, type_, name, literal);
+            }
+            Node::Cmp { field, op, bound }
+        },
+    })
+}
+
+fn eval(node: &Node, record: &cards::Record) -> bool {
+    match node {
+        Node::Or(items) => items.iter().any(|node| eval(node, record)),
+        Node::And(items) => items.iter().all(|node| eval(node, record)),
+        Node::Not(node) => !eval(node, record),
+        Node::Cmp { field, op, bound } => {
+            let value = columns::get(record, *field);
+            if let Bound::Null = bound {
+                let is_null = matches!(value, Cell::Null);
+                return match op {
+                    Op::Eq => is_null,
+                    _ => !is_null,
+                };
+            }
+            // справочные значения сравниваются по коду (см. crate vocab)
+            let value = match &value {
+                Cell::Vocab(val) => Cell::Str(val.code().to_owned()),
+                _ => value,
+            };
+            if let (Op::Contains, Cell::Str(val), Bound::Str(bound)) = (op, &value, bound) {
+                return val.to_lowercase().contains(&bound.to_lowercase());
+            }
+            let ordering = match (&value, bound) {
+                (Cell::Str(val), Bound::Str(bound)) => Some(val.as_str().cmp(bound.as_str())),
+                (Cell::Int(val), Bound::Num(bound)) => (*val as f64).partial_cmp(bound),
+                (Cell::Float(val), Bound::Num(bound)) => val.partial_cmp(bound),
+                (Cell::Bool(val), Bound::Bool(bound)) => Some(val.cmp(bound)),
+                (Cell::Time(val), Bound::Time(bound)) => Some(val.cmp(bound)),
+                // значения нет
+                _ => None,
+            };
+            match ordering {
+                None => false,
+                Some(ordering) => match op {
+                    Op::Eq => ordering == Ordering::Equal,
+                    Op::Ne => ordering != Ordering::Equal,
+                    Op::Lt => ordering == Ordering::Less,
+                    Op::Le => ordering != Ordering::Greater,
+                    Op::Gt => ordering == Ordering::Greater,
+                    Op::Ge => ordering != Ordering::Less,
+                    Op::Contains => false,
+                },
+            }
+        },
+    }
+}
+
+// ============================================================================
+// ============================================================================
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use log::{error, warn, info, debug, trace};
+    use super::*;
+
+    #[tokio::test]
+    async fn test_filter() -> Result<()> {
+        test_helper::init();
+
+        let json = json::Json::from_file("../cards/test_data/card.json").await?;
+        let record = cards::Fetched::parse_json(&json, false)?;
+
+        let matches = |s: &str| -> Result<bool> { Ok(Filter::new(s)?.matches(&record)) };
+        assert!(matches(r#"brand == "KIA" && views_total > 4000"#)?);
+        assert!(matches(r#"brand ~ "ki" && !has_video"#)?);
+        assert!(!matches(r#"brand == "BMW" || views_today >= 31"#)?);
+        assert!(matches(r#"time >= "2020-07-19" && time < "2020-07-20T00:00:00Z""#)?);
+        assert!(matches(r#"autocatalog_id == null && id != null"#)?);
+        assert!(matches(r#"body_type == "suv" && drive == "front" && vehicle_transmission == "automatic""#)?);
+        // значения нет - сравнение ложно
+        assert!(!matches(r#"autocatalog_id > 0"#)?);
+        assert!(!matches(r#"autocatalog_id <= 0"#)?);
+
+        let err = |s: &str| Filter::new(s).unwrap_err().root_cause().to_string();
+        assert_eq!(err(r#"brand == "KIA" && colour == "red""#), "at 19: unknown field `colour`");
+        assert_eq!(err(r#"item_price < "2000000""#), "at 1: field `item_price` is number, can't be compared with \"2000000\"");
+        assert_eq!(err(r#"time > "yesterday""#), "at 1: field `time` is time, can't be compared with \"yesterday\"");
+        assert_eq!(err(r#"brand"#), "at 1: field `brand` is string, can't be compared with true");
+        assert_eq!(err(r#"mileage ~ 5"#), "at 1: `~` is not applicable to number field `mileage` and 5");
+        assert_eq!(
+            Filter::new(r#"brand =="#).unwrap_err().to_string(),
+            r#"filter "brand ==""#,
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,320 @@
+
+#[allow(unused_imports)]
+use log::{error, warn, info, debug, trace};
+#[allow(unused_imports)]
+use anyhow::{Result, Error, bail, anyhow, Context};
+
+use std::fmt;
+
+// ============================================================================
+// ============================================================================
+
+// Грамматика:
+//   expr    := and ( "||" and )*
+//   and     := unary ( "&&" unary )*
+//   unary   := "!" unary | "(" expr ")" | field ( op literal )?
+//   op      := "==" | "!=" | "<" | "<=" | ">" | ">=" | "~"
+//   literal := number | "строка" | 'строка' | true | false | null
+// Поле без сравнения допустимо только для логических полей
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Or(Vec<Expr>),
+    And(Vec<Expr>),
+    Not(Box<Expr>),
+    Cmp {
+        field: String,
+        op: Op,
+        literal: Literal,
+        pos: usize,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Null,
+    Bool(bool),
+    Num(f64),
+    Str(String),
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match self {
+            Op::Eq => "==",
+            Op::Ne => "!=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+            Op::Contains => "~",
+        })
+    }
+}
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Literal::Null => write!(f, "null"),
+            Literal::Bool(val) => write!(f, "{}", val),
+            Literal::Num(val) => write!(f, "{}", val),
+            Literal::Str(val) => write!(f, "{:?}", val),
+        }
+    }
+}
+
+// ============================================================================
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Num(f64),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Ident(s) => write!(f, "`{}`", s),
+            Token::Str(s) => write!(f, "{:?}", s),
+            Token::Num(n) => write!(f, "{}", n),
+            Token::Op(op) => write!(f, "`{}`", op),
+            Token::And => write!(f, "`&&`"),
+            Token::Or => write!(f, "`||`"),
+            Token::Not => write!(f, "`!`"),
+            Token::LParen => write!(f, "`(`"),
+            Token::RParen => write!(f, "`)`"),
+        }
+    }
+}
+
+// Позиции - номера символов (не байтов) от 1
+fn tokenize(s: &str) -> Result<Vec<(Token, usize)>> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut ret = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let pos = i + 1;
+        let next = chars.get(i + 1).cloned();
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        let (token, len) = match (c, next) {
+            ('&', Some('&')) => (Token::And, 2),
+            ('|', Some('|')) => (Token::Or, 2),
+            ('=', Some('=')) => (Token::Op(Op::Eq), 2),
+            ('!', Some('=')) => (Token::Op(Op::Ne), 2),
+            ('<', Some('=')) => (Token::Op(Op::Le), 2),
+            ('>', Some('=')) => (Token::Op(Op::Ge), 2),
+            ('<', _) => (Token::Op(Op::Lt), 1),
+            ('>', _) => (Token::Op(Op::Gt), 1),
+            ('~', _) => (Token::Op(Op::Contains), 1),
+            ('!', _) => (Token::Not, 1),
+            ('(', _) => (Token::LParen, 1),
+            (')', _) => (Token::RParen, 1),
+            ('"', _) | ('\'', _) => {
+                let quote = c;
+                let mut val = String::new();
+                let mut j = i + 1;
+                loop {
+                    match chars.get(j) {
+                        None => bail!("at {}: unterminated string", pos),
+                        Some('\\') => {
+                            match chars.get(j + 1) {
+                                Some(c) => val.push(*c),
+                                None => bail!("at {}: unterminated string", pos),
+                            }
+                            j += 2;
+                        },
+                        Some(c) if *c == quote => break,
+                        Some(c) => {
+                            val.push(*c);
+                            j += 1;
+                        },
+                    }
+                }
+                (Token::Str(val), j + 1 - i)
+            },
+            _ if c.is_ascii_digit() || (c == '-' && next.map(|c| c.is_ascii_digit()) == Some(true)) => {
+                let mut j = i + 1;
+                while j < chars.len() && (chars[j].is_ascii_digit() || chars[j] == '.' || chars[j] == '_') {
+                    j += 1;
+                }
+                let s: String = chars[i..j].iter().filter(|c| **c != '_').collect();
+                let val = s.parse::<f64>().map_err(|_| anyhow!("at {}: invalid number {:?}", pos, s))?;
+                (Token::Num(val), j - i)
+            },
+            _ if c.is_alphabetic() || c == '_' => {
+                let mut j = i + 1;
+                while j < chars.len() && (chars[j].is_alphanumeric() || chars[j] == '_') {
+                    j += 1;
+                }
+                (Token::Ident(chars[i..j].iter().collect()), j - i)
+            },
+            _ => bail!("at {}: unexpected {:?}", pos, c),
+        };
+        ret.push((token, pos));
+        i += len;
+    }
+    Ok(ret)
+}
+
+// ============================================================================
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    i: usize,
+    end: usize,
+}
+
+pub fn parse(s: &str) -> Result<Expr> {
+    let tokens = tokenize(s)?;
+    if tokens.is_empty() {
+        bail!("empty filter");
+    }
+    let mut parser = Parser { tokens, i: 0, end: s.chars().count() + 1 };
+    let expr = parser.expr()?;
+    if let Some((token, pos)) = parser.peek() {
+        bail!("at {}: unexpected {}", pos, token);
+    }
+    Ok(expr)
+}
+
+impl Parser {
+    fn peek(&self) -> Option<(Token, usize)> {
+        self.tokens.get(self.i).cloned()
+    }
+    fn next(&mut self, expected: &str) -> Result<(Token, usize)> {
+        match self.tokens.get(self.i).cloned() {
+            None => bail!("at {}: expected {}, but the filter ended", self.end, expected),
+            Some(ret) => {
+                self.i += 1;
+                Ok(ret)
+            },
+        }
+    }
+    fn expr(&mut self) -> Result<Expr> {
+        let mut items = vec![self.and()?];
+        while let Some((Token::Or, _)) = self.peek() {
+            self.i += 1;
+            items.push(self.and()?);
+        }
+        Ok(if items.len() == 1 { items.pop().unwrap() } else { Expr::Or(items) })
+    }
+    fn and(&mut self) -> Result<Expr> {
+        let mut items = vec![self.unary()?];
+        while let Some((Token::And, _)) = self.peek() {
+            self.i += 1;
+            items.push(self.unary()?);
+        }
+        Ok(if items.len() == 1 { items.pop().unwrap() } else { Expr::And(items) })
+    }
+    fn unary(&mut self) -> Result<Expr> {
+        match self.next("field, `!` or `(`")? {
+            (Token::Not, _) => Ok(Expr::Not(Box::new(self.unary()?))),
+            (Token::LParen, _) => {
+                let expr = self.expr()?;
+                match self.next("`)`")? {
+                    (Token::RParen, _) => Ok(expr),
+                    (token, pos) => bail!("at {}: expected `)`, but {}", pos, token),
+                }
+            },
+            (Token::Ident(field), pos) => {
+                match self.peek() {
+                    Some((Token::Op(op), _)) => {
+                        self.i += 1;
+                        let literal = self.literal()?;
+                        Ok(Expr::Cmp { field, op, literal, pos })
+                    },
+                    // логическое поле само по себе: has_video
+                    _ => Ok(Expr::Cmp { field, op: Op::Eq, literal: Literal::Bool(true), pos }),
+                }
+            },
+            (token, pos) => bail!("at {}: expected field, `!` or `(`, but {}", pos, token),
+        }
+    }
+    fn literal(&mut self) -> Result<Literal> {
+        match self.next("value")? {
+            (Token::Num(val), _) => Ok(Literal::Num(val)),
+            (Token::Str(val), _) => Ok(Literal::Str(val)),
+            (Token::Ident(ref s), _) if s == "null" => Ok(Literal::Null),
+            (Token::Ident(ref s), _) if s == "true" => Ok(Literal::Bool(true)),
+            (Token::Ident(ref s), _) if s == "false" => Ok(Literal::Bool(false)),
+            (token, pos) => bail!("at {}: expected value (number, string, true, false or null), but {}", pos, token),
+        }
+    }
+}
+
+// ============================================================================
+// ============================================================================
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use log::{error, warn, info, debug, trace};
+    use super::*;
+
+    fn cmp(field: &str, op: Op, literal: Literal, pos: usize) -> Expr {
+        Expr::Cmp { field: field.to_owned(), op, literal, pos }
+    }
+
+    #[test]
+    fn test_parse() -> Result<()> {
+        test_helper::init();
+
+        let expr = parse(r#"brand == "BMW" && production_date >= 2015 && item_price < 2_000_000"#)?;
+        assert_eq!(expr, Expr::And(vec![
+            cmp("brand", Op::Eq, Literal::Str("BMW".to_owned()), 1),
+            cmp("production_date", Op::Ge, Literal::Num(2015.0), 19),
+            cmp("item_price", Op::Lt, Literal::Num(2000000.0), 46),
+        ]));
+
+        let expr = parse(r#"!(color == 'белый' || color == null) && has_video"#)?;
+        assert_eq!(expr, Expr::And(vec![
+            Expr::Not(Box::new(Expr::Or(vec![
+                cmp("color", Op::Eq, Literal::Str("белый".to_owned()), 3),
+                cmp("color", Op::Eq, Literal::Null, 23),
+            ]))),
+            cmp("has_video", Op::Eq, Literal::Bool(true), 41),
+        ]));
+
+        assert_eq!(
+            parse(r#"brand == "BMW" &&"#).unwrap_err().to_string(),
+            "at 18: expected field, `!` or `(`, but the filter ended",
+        );
+        assert_eq!(
+            parse(r#"brand == BMW"#).unwrap_err().to_string(),
+            "at 10: expected value (number, string, true, false or null), but `BMW`",
+        );
+        assert_eq!(
+            parse(r#"(brand == "BMW""#).unwrap_err().to_string(),
+            "at 16: expected `)`, but the filter ended",
+        );
+        assert_eq!(
+            parse(r#"brand = "BMW""#).unwrap_err().to_string(),
+            "at 7: unexpected '='",
+        );
+
+        Ok(())
+    }
+}
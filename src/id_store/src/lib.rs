@@ -11,7 +11,7 @@ use serde::{
     ser::{Serializer, SerializeStruct},
     de::{self, Deserializer, Visitor, SeqAccess, MapAccess, Unexpected},
 };
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use std::path::Path;
 use std::str::FromStr;
@@ -53,6 +53,15 @@ impl IdStore {
         };
         self.0.insert(key.to_owned(), val);
     }
+    // Идентификаторы последних списков по всем параметрам поиска (по каждым параметрам хранится
+    // только последний список), независимо от свежести
+    pub fn all_ids(&self) -> HashSet<u64> {
+        let mut ret = HashSet::new();
+        for item in self.0.values() {
+            ret.extend(item.ret.iter());
+        }
+        ret
+    }
     pub fn get_ids(&self, key: &str, fresh_duration: chrono::Duration) -> Option<&IdStoreItem> {
         match self.0.get(key) {
             None => None,
@@ -205,6 +214,7 @@ mod tests {
 
         let mut diap_store = IdStore::new();
         diap_store.set_ids(key, ids);
+        assert_eq!(diap_store.all_ids().len(), 6);
 
         let json = serde_json::to_string_pretty(&diap_store)?;
         let file_path = Path::new("out_test/ids.json");
@@ -216,6 +226,21 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_all_ids() {
+        test_helper::init();
+
+        let ids = |ids: &[u64]| ids.iter().cloned().collect::<ids::Ret>();
+        let mut id_store = IdStore::new();
+        id_store.set_ids("a", ids(&[1, 2]));
+        id_store.set_ids("b", ids(&[3]));
+        // более поздний список по "b" не вытесняет список по "a"
+        assert_eq!(id_store.all_ids(), ids(&[1, 2, 3]));
+
+        id_store.set_ids("a", ids(&[2]));
+        assert_eq!(id_store.all_ids(), ids(&[2, 3]));
+    }
+
     #[tokio::test]
     async fn test_from_file() -> Result<()> {
         test_helper::init();
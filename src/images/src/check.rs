@@ -0,0 +1,53 @@
+
+#[allow(unused_imports)]
+use log::{error, warn, info, debug, trace};
+#[allow(unused_imports)]
+use anyhow::{Result, Error, bail, anyhow, Context};
+
+use std::path::{Path};
+
+use tokio::fs;
+
+use super::{Card, ToFetch};
+use super::manifest::Manifest;
+
+pub struct Arg<'a> {
+    pub card: Card,
+    pub out_dir: &'a Path,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Ret {
+    pub item_to_fetch: Option<ToFetch>,
+}
+
+// Изображение считается имеющимся, если оно есть в манифесте карточки и его файл на месте
+pub async fn run<'a>(arg: Arg<'a>) -> Result<Ret> {
+    let Card { id, urls } = arg.card;
+    let manifest = Manifest::from_file(&super::file_spec::manifest(arg.out_dir, id)).await?
+        .unwrap_or_else(|| Manifest::new(id));
+    let mut present = Vec::new();
+    for image in manifest.images {
+        if !urls.contains(&image.url) {
+            continue;
+        }
+        let file_path = super::file_spec::blob(arg.out_dir, &image.hash, &image.ext);
+        match fs::metadata(&file_path).await {
+            Ok(_) => present.push(image),
+            Err(err) => match err.kind() {
+                std::io::ErrorKind::NotFound => {},
+                _ => return Err(Error::new(err).context(format!("{:?}", &file_path))),
+            },
+        }
+    }
+    let missing = urls.iter()
+        .filter(|url| !present.iter().any(|image| &image.url == *url))
+        .cloned()
+        .collect::<Vec<String>>()
+    ;
+    if missing.is_empty() {
+        Ok(Ret{item_to_fetch: None})
+    } else {
+        Ok(Ret{item_to_fetch: Some(ToFetch { id, urls, present, missing })})
+    }
+}
@@ -0,0 +1,45 @@
+
+#[allow(unused_imports)]
+use log::{error, warn, info, debug, trace};
+#[allow(unused_imports)]
+use anyhow::{anyhow, bail, Result, Error, Context};
+
+use url::Url;
+
+use super::ToFetch;
+
+pub struct Arg {
+    pub client: client::Client,
+    pub item: ToFetch,
+}
+
+pub struct Ret {
+    pub client: client::Client,
+    pub item: ToFetch,
+    pub fetched: Vec<(String, Vec<u8>)>, // url -> содержимое
+}
+
+// Изображения, которые не удалось получить, пропускаем: они останутся
+// вне манифеста и будут запрошены при следующем запуске
+pub async fn run(arg: Arg) -> Result<Ret> {
+    let mut fetched = Vec::new();
+    for url in arg.item.missing.iter() {
+        let url_parsed = Url::parse(url).context(format!("images::fetch: {}", url))?;
+        match arg.client.get_bytes_status(url_parsed).await {
+            Err(err) => {
+                warn!("{}: {}", url, err);
+            },
+            Ok((bytes, status)) => {
+                match status {
+                    http::StatusCode::OK => fetched.push((url.to_owned(), bytes)),
+                    code => warn!("{} :: {}", url, code),
+                }
+            },
+        }
+    }
+    Ok(Ret {
+        client: arg.client,
+        item: arg.item,
+        fetched,
+    })
+}
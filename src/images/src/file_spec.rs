@@ -0,0 +1,68 @@
+
+#[allow(unused_imports)]
+use log::{error, warn, info, debug, trace};
+#[allow(unused_imports)]
+use anyhow::{Result, Error, bail, anyhow, Context};
+
+use std::path::{Path, PathBuf};
+
+// Манифест карточки: <out_dir>/images/manifests/<id>, разбитый так же, как файлы карточек
+pub fn manifest(out_dir: &Path, id: u64) -> PathBuf {
+    let s = format!("{:016x}", id);
+    let ss = vec![ "images", "manifests", &s[0..9], &s[9..16] ];
+    let path_vec: Vec<&Path> = ss.into_iter().map(Path::new).collect();
+    let path: PathBuf = [ out_dir ]
+        .iter()
+        .chain(path_vec.iter())
+        .collect()
+    ;
+    path.with_extension("json")
+}
+
+// Само изображение: <out_dir>/images/blobs/<первые 2 символа хеша>/<хеш>.<ext>
+pub fn blob(out_dir: &Path, hash: &str, ext: &str) -> PathBuf {
+    let ss = vec![ "images", "blobs", &hash[0..2], hash ];
+    let path_vec: Vec<&Path> = ss.into_iter().map(Path::new).collect();
+    let path: PathBuf = [ out_dir ]
+        .iter()
+        .chain(path_vec.iter())
+        .collect()
+    ;
+    path.with_extension(ext)
+}
+
+// Временный файл рядом с file_path: пишется целиком и переименовывается на место,
+// чтобы прерванная или параллельная запись не оставила обрезанный файл.
+// id и pid различают одновременных писателей одного и того же файла
+pub fn tmp(file_path: &Path, id: u64) -> PathBuf {
+    let mut s = file_path.as_os_str().to_owned();
+    s.push(format!(".{}-{}.tmp", std::process::id(), id));
+    PathBuf::from(s)
+}
+
+// ============================================================================
+// ============================================================================
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use log::{error, warn, info, debug, trace};
+    use super::*;
+
+    #[test]
+    fn test_file_spec() {
+        test_helper::init();
+
+        let out_dir = &Path::new("out_test");
+
+        let fspec = manifest(out_dir, u64::MAX - 1);
+        assert_eq!(fspec.to_string_lossy(), "out_test/images/manifests/fffffffff/ffffffe.json");
+
+        let fspec = blob(out_dir, "ab12cd", "jpg");
+        assert_eq!(fspec.to_string_lossy(), "out_test/images/blobs/ab/ab12cd.jpg");
+
+        let fspec = tmp(&fspec, 42);
+        assert_eq!(fspec.to_string_lossy(), format!("out_test/images/blobs/ab/ab12cd.jpg.{}-42.tmp", std::process::id()));
+    }
+}
@@ -0,0 +1,283 @@
+#![recursion_limit="512"]
+
+#[allow(unused_imports)]
+use log::{error, warn, info, debug, trace};
+#[allow(unused_imports)]
+use anyhow::{Result, Error, bail, anyhow, Context};
+
+use std::time::Instant;
+use std::path::Path;
+use std::collections::VecDeque;
+
+use futures::{
+    select,
+    stream::{
+        FuturesUnordered,
+        StreamExt,
+    },
+};
+
+mod file_spec;
+mod manifest;
+mod check;
+mod fetch;
+mod save;
+
+pub use manifest::{Manifest, Image};
+
+// ============================================================================
+// ============================================================================
+
+// Карточка и url ее изображений
+#[derive(Debug, Clone, PartialEq)]
+pub struct Card {
+    pub id: u64,
+    pub urls: Vec<String>,
+}
+
+// Карточка, у которой есть еще не скачанные изображения
+#[derive(Debug, PartialEq)]
+pub struct ToFetch {
+    pub id: u64,
+    pub urls: Vec<String>,
+    pub present: Vec<Image>,
+    pub missing: Vec<String>,
+}
+
+macro_rules! fut_check {
+    ($fut_queue: expr, $fut_count: ident, $fut_count_max: expr, $fut_fill_from: expr, $out_dir: expr) => {
+        while $fut_count < $fut_count_max {
+            match $fut_fill_from.pop_front() {
+                None => break,
+                Some(card) => {
+                    let arg = op::Arg::Check (check::Arg {
+                        card,
+                        out_dir: $out_dir
+                    });
+                    $fut_queue.push(op::run(arg));
+                    $fut_count += 1;
+                },
+            }
+        }
+    };
+}
+
+macro_rules! fut_fetch {
+    ($fut_queue: expr, $fut_count: ident, $fut_count_max: expr, $fut_fill_from: expr, $client: block) => {
+        while $fut_count < $fut_count_max {
+            match $fut_fill_from.pop_front() {
+                None => break,
+                Some(item) => {
+                    let client = $client;
+                    fut_fetch!($fut_queue, $fut_count, item, client);
+                },
+            }
+        }
+    };
+    ($fut_queue: expr, $fut_count: ident, $fut_count_max: expr, $fut_fill_from: expr, $client: expr) => {
+        if $fut_count < $fut_count_max {
+            if let Some(item) = $fut_fill_from.pop_front() {
+                fut_fetch!($fut_queue, $fut_count, item, $client);
+            }
+        }
+    };
+    ($fut_queue: expr, $fut_count: ident, $item: expr, $client: expr) => {
+        let arg = op::Arg::Fetch (fetch::Arg { item: $item, client: $client });
+        $fut_queue.push(op::run(arg));
+        $fut_count += 1;
+    }
+}
+
+macro_rules! fut_save {
+    ($fut_queue: expr, $fetched: expr, $item: expr, $out_dir: expr) => {
+        let arg = op::Arg::Save (save::Arg {
+            item: $item,
+            fetched: $fetched,
+            out_dir: $out_dir
+        });
+        $fut_queue.push(op::run(arg));
+    };
+}
+
+macro_rules! callback {
+    ($callback: ident, $start: expr, $last_callback: ident, $elapsed_qt: ident, $remained_qt: ident, $block: block) => {
+        $callback = if let Some(mut callback) = $callback {
+
+            $block;
+
+            if let Some(start) = $start {
+                if $elapsed_qt > 0 && Instant::now().duration_since($last_callback).as_millis() > CALLBACK_THROTTLE {
+                    callback!(callback, start, $elapsed_qt, $remained_qt);
+                    $last_callback = Instant::now();
+                }
+            }
+            Some(callback)
+        } else {
+            None
+        };
+
+    };
+    ($callback: expr, $start: expr, $elapsed_qt: expr, $remained_qt: expr) => {
+        let elapsed_millis = Instant::now().duration_since($start).as_millis();
+        let per_millis = elapsed_millis / $elapsed_qt as u128;
+        let remained_millis = per_millis * $remained_qt as u128;
+        $callback(CallbackArg {
+            elapsed_qt: $elapsed_qt,
+            remained_qt: $remained_qt,
+            elapsed_millis,
+            remained_millis,
+            per_millis,
+        })?;
+    };
+}
+
+pub struct CallbackArg {
+    pub elapsed_qt: usize,
+    pub remained_qt: usize,
+    pub elapsed_millis: u128,
+    pub remained_millis: u128,
+    pub per_millis: u128,
+}
+
+pub struct Arg<'a> {
+    pub cards: Vec<Card>,
+    pub out_dir: &'a Path,
+    pub thread_limit_network: usize,
+    pub thread_limit_file: usize,
+    pub client_provider: client::Provider,
+}
+
+pub struct Ret {
+    pub received_qt: usize, // скачано изображений
+    pub stored_qt: usize, // из них записано новых файлов
+    pub dup_qt: usize, // из них совпали по содержимому с уже сохраненными
+}
+
+// Манифест ранее скачанных изображений карточки
+pub async fn get(out_dir: &Path, id: u64) -> Result<Option<Manifest>> {
+    Manifest::from_file(&file_spec::manifest(out_dir, id)).await
+}
+
+const CALLBACK_THROTTLE: u128 = 100;
+pub async fn fetch_and_save<'a, Cb>(
+    arg: Arg<'a>,
+    mut callback: Option<Cb>,
+) -> Result<Ret>
+where
+    Cb: FnMut(CallbackArg) -> Result<()>,
+{
+    let mut items_to_fetch: VecDeque<ToFetch> = VecDeque::new();
+    let mut items_to_check: VecDeque<Card> = arg.cards.into_iter().collect();
+
+    let mut fut_queue = FuturesUnordered::new();
+    let mut fut_check_count = 0usize;
+    let mut fut_fetch_count = 0usize;
+
+    fut_check!(fut_queue, fut_check_count, arg.thread_limit_file, items_to_check, arg.out_dir);
+
+    let mut received_qt = 0;
+    let mut stored_qt = 0;
+    let mut dup_qt = 0;
+    let mut elapsed_qt = 0;
+    let mut remained_qt = 0;
+    let mut last_callback = Instant::now();
+    let mut start: Option<Instant> = None;
+    loop {
+        select! {
+            ret = fut_queue.select_next_some() => {
+                match ret {
+                    Err(err) => {
+                        return Err(err.context("images::fetch_and_save"));
+                    },
+                    Ok(ret) => {
+                        match ret {
+                            op::Ret::Save(ret) => {
+                                stored_qt += ret.stored_qt;
+                                dup_qt += ret.dup_qt;
+                            },
+                            // Если у карточки есть еще не скачанные изображения, то получаем Some(item_to_fetch)
+                            op::Ret::Check(check::Ret{item_to_fetch}) => {
+                                fut_check_count -= 1;
+                                if let Some(item_to_fetch) = item_to_fetch {
+                                    if start.is_none() {
+                                        start = Some(Instant::now());
+                                    }
+                                    items_to_fetch.push_back(item_to_fetch);
+
+                                    callback!(callback, start, last_callback, elapsed_qt, remained_qt, {
+                                        remained_qt += 1;
+                                    });
+
+                                    fut_fetch!(
+                                        fut_queue,
+                                        fut_fetch_count,
+                                        arg.thread_limit_network,
+                                        items_to_fetch,
+                                        { arg.client_provider.build().await? }
+                                    );
+                                }
+                                fut_check!(fut_queue, fut_check_count, arg.thread_limit_file, items_to_check, arg.out_dir);
+                            },
+                            op::Ret::Fetch(ret) => {
+                                fut_fetch_count -= 1;
+                                fut_fetch!(fut_queue, fut_fetch_count, arg.thread_limit_network, items_to_fetch, ret.client );
+                                callback!(callback, start, last_callback, elapsed_qt, remained_qt, {
+                                    elapsed_qt += 1;
+                                    remained_qt = remained_qt.saturating_sub(1);
+                                });
+
+                                received_qt += ret.fetched.len();
+                                fut_save!(fut_queue, ret.fetched, ret.item, arg.out_dir);
+                            },
+                        }
+                    },
+                }
+            },
+            complete => {
+                break;
+            },
+        }
+    }
+    if let Some(mut callback) = callback {
+        if let Some(start) = start {
+            if elapsed_qt > 0 {
+                callback!(callback, start, elapsed_qt, remained_qt);
+            }
+        }
+    }
+
+    Ok(Ret{received_qt, stored_qt, dup_qt})
+}
+
+mod op {
+    use super::*;
+
+    pub enum Arg<'a> {
+        Fetch(fetch::Arg),
+        Save(save::Arg<'a>),
+        Check(check::Arg<'a>),
+    }
+
+    pub enum Ret {
+        Fetch(fetch::Ret),
+        Save(save::Ret),
+        Check(check::Ret),
+    }
+
+    pub async fn run<'a>(arg: Arg<'a>) -> Result<Ret> {
+        match arg {
+            Arg::Fetch(arg) => {
+                let ret = fetch::run(arg).await?;
+                Ok(Ret::Fetch(ret))
+            },
+            Arg::Save(arg) => {
+                let ret = save::run(arg).await?;
+                Ok(Ret::Save(ret))
+            },
+            Arg::Check(arg) => {
+                let ret = check::run(arg).await?;
+                Ok(Ret::Check(ret))
+            },
+        }
+    }
+}
@@ -0,0 +1,63 @@
+
+#[allow(unused_imports)]
+use log::{error, warn, info, debug, trace};
+#[allow(unused_imports)]
+use anyhow::{Result, Error, bail, anyhow, Context};
+
+use serde::{
+    Serialize,
+    Deserialize,
+};
+use std::path::Path;
+
+use tokio::fs::{self, File};
+use tokio::prelude::*;
+
+// ============================================================================
+// ============================================================================
+
+// Список изображений карточки в порядке их следования в карточке
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub id: u64,
+    pub images: Vec<Image>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Image {
+    pub url: String,
+    pub hash: String, // sha256 содержимого
+    pub ext: String,
+}
+
+impl Manifest {
+    pub fn new(id: u64) -> Self {
+        Self { id, images: Vec::new() }
+    }
+    pub async fn from_file(file_path: &Path) -> Result<Option<Self>> {
+        let mut file = match File::open(file_path).await {
+            Ok(file) => file,
+            Err(err) => match err.kind() {
+                std::io::ErrorKind::NotFound => return Ok(None),
+                _ => return Err(Error::new(err).context(format!("{:?}", file_path))),
+            },
+        };
+        let mut contents = vec![];
+        file.read_to_end(&mut contents).await?;
+        let s = std::str::from_utf8(&contents)?;
+        let manifest = serde_json::from_str(s).context(format!("{:?}", file_path))?;
+        Ok(Some(manifest))
+    }
+    pub async fn to_file(&self, file_path: &Path) -> Result<()> {
+        if let Some(dir_path) = file_path.parent() {
+            fs::create_dir_all(dir_path).await?;
+        }
+        let tmp_path = super::file_spec::tmp(file_path, self.id);
+        let mut file = File::create(&tmp_path).await.context(format!("{:?}", tmp_path))?;
+        let json = serde_json::to_string_pretty(&self)?;
+        file.write_all(json.as_bytes()).await?;
+        file.sync_all().await?;
+        fs::rename(&tmp_path, file_path).await.context(format!("{:?}", file_path))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+
+#[allow(unused_imports)]
+use log::{error, warn, info, debug, trace};
+#[allow(unused_imports)]
+use anyhow::{Result, Error, bail, anyhow, Context};
+
+use std::path::{Path};
+use sha2::{Sha256, Digest};
+
+use tokio::fs::{self, File};
+use tokio::prelude::*;
+
+use super::ToFetch;
+use super::manifest::{Manifest, Image};
+
+pub struct Arg<'a> {
+    pub item: ToFetch,
+    pub fetched: Vec<(String, Vec<u8>)>,
+    pub out_dir: &'a Path,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Ret {
+    pub stored_qt: usize, // новых файлов
+    pub dup_qt: usize, // изображений, содержимое которых уже было сохранено
+}
+
+pub async fn run<'a>(arg: Arg<'a>) -> Result<Ret> {
+    let mut stored_qt = 0;
+    let mut dup_qt = 0;
+    let mut images = arg.item.present;
+    for (url, bytes) in arg.fetched {
+        let hash = format!("{:x}", Sha256::digest(&bytes));
+        let ext = ext_of(&bytes).to_owned();
+        let file_path = super::file_spec::blob(arg.out_dir, &hash, &ext);
+        if fs::metadata(&file_path).await.is_ok() {
+            dup_qt += 1;
+        } else {
+            if let Some(dir_path) = file_path.parent() {
+                fs::create_dir_all(dir_path).await?;
+            }
+            let tmp_path = super::file_spec::tmp(&file_path, arg.item.id);
+            let mut file = File::create(&tmp_path).await.context(format!("{:?}", tmp_path))?;
+            file.write_all(&bytes).await?;
+            file.sync_all().await?;
+            fs::rename(&tmp_path, &file_path).await.context(format!("{:?}", file_path))?;
+            stored_qt += 1;
+        }
+        images.push(Image { url, hash, ext });
+    }
+    let mut manifest = Manifest::new(arg.item.id);
+    for url in arg.item.urls.iter() {
+        if let Some(image) = images.iter().find(|image| &image.url == url) {
+            manifest.images.push(image.clone());
+        }
+    }
+    manifest.to_file(&super::file_spec::manifest(arg.out_dir, arg.item.id)).await?;
+
+    Ok(Ret { stored_qt, dup_qt })
+}
+
+fn ext_of(bytes: &[u8]) -> &'static str {
+    if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        "jpg"
+    } else if bytes.starts_with(b"\x89PNG") {
+        "png"
+    } else if bytes.len() >= 12 && &bytes[0..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        "webp"
+    } else if bytes.starts_with(b"GIF8") {
+        "gif"
+    } else {
+        "bin"
+    }
+}
+
+// ============================================================================
+// ============================================================================
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use log::{error, warn, info, debug, trace};
+    use super::*;
+
+    #[tokio::test]
+    async fn test_save() -> Result<()> {
+        test_helper::init();
+
+        let out_dir = Path::new("out_test/save_test");
+        if fs::metadata(out_dir).await.is_ok() {
+            fs::remove_dir_all(out_dir).await?;
+        }
+
+        let jpg = vec![0xFF, 0xD8, 0xFF, 0xE0, 1, 2, 3];
+        let urls = vec![
+            "https://1.img.avito.st/1280x960/1.jpg".to_owned(),
+            "https://2.img.avito.st/1280x960/2.jpg".to_owned(),
+        ];
+        let item = ToFetch { id: 42, urls: urls.clone(), present: vec![], missing: urls.clone() };
+        let fetched = vec![
+            (urls[1].to_owned(), jpg.clone()),
+            (urls[0].to_owned(), jpg.clone()),
+        ];
+        let ret = run(Arg { item, fetched, out_dir }).await?;
+        assert_eq!(ret, Ret { stored_qt: 1, dup_qt: 1 });
+
+        let manifest = Manifest::from_file(&super::super::file_spec::manifest(out_dir, 42)).await?.unwrap();
+        assert_eq!(manifest.images.iter().map(|image| image.url.as_str()).collect::<Vec<&str>>(), urls);
+        assert_eq!(manifest.images[0].hash, manifest.images[1].hash);
+        assert_eq!(manifest.images[0].ext, "jpg");
+        let blob_dir = super::super::file_spec::blob(out_dir, &manifest.images[0].hash, "jpg").parent().unwrap().to_owned();
+        assert_eq!(std::fs::read_dir(blob_dir)?.count(), 1);
+
+        let ret = super::super::check::run(super::super::check::Arg {
+            card: super::super::Card { id: 42, urls },
+            out_dir,
+        }).await?;
+        assert_eq!(ret.item_to_fetch, None);
+
+        fs::remove_dir_all(out_dir).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,221 @@
+#[allow(unused_imports)]
+use log::{error, warn, info, debug, trace};
+#[allow(unused_imports)]
+use anyhow::{Result, Error, bail, anyhow, Context};
+
+use serde::{Serialize, Deserialize};
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use tokio::fs::{self, File};
+use tokio::prelude::*;
+
+// ============================================================================
+// ============================================================================
+
+// Жизненный цикл объявлений по id, накапливается от запуска к запуску в <out_dir>/lifecycle.json
+#[derive(Default, Serialize, Deserialize)]
+pub struct Lifecycle (BTreeMap<u64, LifecycleItem>);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LifecycleItem {
+    pub first_seen: DateTime<Utc>, // первый список идентификаторов, в котором встретилось объявление
+    pub last_seen: DateTime<Utc>, // последний такой список
+    pub closed_at: Option<DateTime<Utc>>, // когда объявление оказалось закрытым или не найденным
+    pub status: Option<String>,
+    pub closing_reason: Option<String>,
+    // параметры поиска (settings.params) последнего списка, в котором встретилось объявление;
+    // None - у записей lifecycle.json, сделанных до появления этого поля
+    #[serde(default)]
+    pub params: Option<String>,
+}
+
+pub const STATUS_ACTIVE: &str = "active";
+pub const STATUS_NOT_FOUND: &str = "not_found";
+
+pub fn file_spec(out_dir: &Path) -> PathBuf {
+    out_dir.join("lifecycle.json")
+}
+
+impl Lifecycle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub async fn to_file(&self, file_path: &Path) -> Result<()> {
+        if let Some(dir_path) = file_path.parent() {
+            fs::create_dir_all(dir_path).await?;
+        }
+        let mut file = File::create(file_path).await?;
+        let json = serde_json::to_string_pretty(&self)?;
+        file.write_all(json.as_bytes()).await?;
+        Ok(())
+    }
+    pub async fn from_file(file_path: &Path) -> Result<Self> {
+        let mut file = File::open(file_path).await?;
+        let mut content = vec![];
+        file.read_to_end(&mut content).await?;
+        let content = std::str::from_utf8(&content)?;
+        let ret = Self::from_str(content)?;
+        Ok(ret)
+    }
+    pub fn get(&self, id: u64) -> Option<&LifecycleItem> {
+        self.0.get(&id)
+    }
+    // Список идентификаторов по параметрам поиска params, полученный в момент seen_at: встреченные
+    // объявления продлеваются (и открываются снова, если были закрыты), а пропавшие из списка считаются
+    // закрытыми, если в последний раз встретились в списке по этим же параметрам: у нескольких поисков
+    // с общим out_dir каждый закрывает только свои объявления
+    pub fn adopt_ids(&mut self, params: &str, ids: &HashSet<u64>, seen_at: DateTime<Utc>) {
+        for id in ids.iter() {
+            let item = self.0.entry(*id).or_insert_with(|| LifecycleItem {
+                first_seen: seen_at,
+                last_seen: seen_at,
+                closed_at: None,
+                status: None,
+                closing_reason: None,
+                params: None,
+            });
+            if seen_at < item.first_seen {
+                item.first_seen = seen_at;
+            }
+            if seen_at > item.last_seen {
+                item.last_seen = seen_at;
+                item.closed_at = None;
+            }
+            if seen_at >= item.last_seen {
+                item.params = Some(params.to_owned());
+            }
+        }
+        for (id, item) in self.0.iter_mut() {
+            if !ids.contains(id) && item.closed_at.is_none() && item.last_seen < seen_at && item.params.as_deref() == Some(params) {
+                item.closed_at = Some(seen_at);
+            }
+        }
+    }
+    // Состояние карточки: статус, отличный от active, закрывает объявление
+    pub fn adopt_card(&mut self, id: u64, status: Option<&str>, closing_reason: Option<&str>, now: DateTime<Utc>) {
+        if let Some(item) = self.0.get_mut(&id) {
+            if let Some(status) = status {
+                item.status = Some(status.to_owned());
+                if status != STATUS_ACTIVE && item.closed_at.is_none() {
+                    item.closed_at = Some(now);
+                }
+            }
+            if let Some(closing_reason) = closing_reason {
+                item.closing_reason = Some(closing_reason.to_owned());
+            }
+        }
+    }
+    pub fn adopt_not_found(&mut self, id: u64, now: DateTime<Utc>) {
+        self.adopt_card(id, Some(STATUS_NOT_FOUND), None, now);
+    }
+}
+
+impl LifecycleItem {
+    // Срок экспозиции: до закрытия, а для открытых объявлений - до последнего списка идентификаторов
+    pub fn days_on_market(&self) -> i64 {
+        let end = self.closed_at.unwrap_or(self.last_seen);
+        end.signed_duration_since(self.first_seen).num_days()
+    }
+}
+
+impl FromStr for Lifecycle {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let ret = serde_json::from_str(s)?;
+        Ok(ret)
+    }
+}
+
+// ============================================================================
+// ============================================================================
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+
+    #[allow(unused_imports)]
+    use log::{error, warn, info, debug, trace};
+    use super::*;
+
+    #[tokio::test]
+    async fn test_lifecycle() -> Result<()> {
+        test_helper::init();
+
+        let day = |d: u32| format!("2020-07-{:02}T12:00:00Z", d).parse::<DateTime<Utc>>().unwrap();
+        let ids = |ids: &[u64]| ids.iter().cloned().collect::<HashSet<u64>>();
+
+        let mut lifecycle = Lifecycle::new();
+        lifecycle.adopt_ids("a", &ids(&[1, 2, 3]), day(1));
+        lifecycle.adopt_ids("a", &ids(&[1, 2, 4]), day(5));
+        lifecycle.adopt_card(2, Some("closed"), Some("sold"), day(6));
+        lifecycle.adopt_not_found(4, day(6));
+        lifecycle.adopt_card(1, Some(STATUS_ACTIVE), None, day(6));
+
+        let file_path = Path::new("out_test/lifecycle.json");
+        lifecycle.to_file(file_path).await?;
+        let lifecycle = Lifecycle::from_file(file_path).await?;
+
+        let item = lifecycle.get(1).unwrap();
+        assert_eq!((item.first_seen, item.last_seen, item.closed_at), (day(1), day(5), None));
+        assert_eq!(item.days_on_market(), 4);
+
+        let item = lifecycle.get(2).unwrap();
+        assert_eq!(item.closed_at, Some(day(6)));
+        assert_eq!(item.closing_reason.as_deref(), Some("sold"));
+        assert_eq!(item.days_on_market(), 5);
+
+        // пропал из списка идентификаторов
+        let item = lifecycle.get(3).unwrap();
+        assert_eq!(item.closed_at, Some(day(5)));
+        assert_eq!(item.days_on_market(), 4);
+
+        let item = lifecycle.get(4).unwrap();
+        assert_eq!(item.status.as_deref(), Some(STATUS_NOT_FOUND));
+        assert_eq!(item.days_on_market(), 1);
+
+        // снова в списке - снова открыто
+        let mut lifecycle = lifecycle;
+        lifecycle.adopt_ids("a", &ids(&[3]), day(7));
+        let item = lifecycle.get(3).unwrap();
+        assert_eq!((item.last_seen, item.closed_at), (day(7), None));
+        assert_eq!(item.days_on_market(), 6);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lifecycle_params() {
+        test_helper::init();
+
+        let day = |d: u32| format!("2020-07-{:02}T12:00:00Z", d).parse::<DateTime<Utc>>().unwrap();
+        let ids = |ids: &[u64]| ids.iter().cloned().collect::<HashSet<u64>>();
+
+        let mut lifecycle = Lifecycle::new();
+        lifecycle.adopt_ids("a", &ids(&[1, 2]), day(1));
+        lifecycle.adopt_ids("b", &ids(&[3, 2]), day(2));
+
+        // список по другим параметрам не закрывает объявления поиска "a"
+        assert_eq!(lifecycle.get(1).unwrap().closed_at, None);
+        assert_eq!(lifecycle.get(1).unwrap().params.as_deref(), Some("a"));
+        // объявление из обоих списков принадлежит последнему
+        assert_eq!(lifecycle.get(2).unwrap().params.as_deref(), Some("b"));
+
+        lifecycle.adopt_ids("a", &ids(&[2]), day(3));
+        assert_eq!(lifecycle.get(1).unwrap().closed_at, Some(day(3)));
+        assert_eq!(lifecycle.get(3).unwrap().closed_at, None);
+
+        lifecycle.adopt_ids("b", &ids(&[2]), day(4));
+        assert_eq!(lifecycle.get(3).unwrap().closed_at, Some(day(4)));
+        assert_eq!(lifecycle.get(2).unwrap().closed_at, None);
+        assert_eq!(lifecycle.get(2).unwrap().days_on_market(), 3);
+
+        // записи без params (lifecycle.json прежнего формата) не закрываются, пока не встретятся снова
+        let mut lifecycle = Lifecycle::from_str(r#"{"5": {"first_seen": "2020-07-01T12:00:00Z", "last_seen": "2020-07-01T12:00:00Z", "closed_at": null, "status": null, "closing_reason": null}}"#).unwrap();
+        lifecycle.adopt_ids("a", &ids(&[6]), day(2));
+        assert_eq!(lifecycle.get(5).unwrap().closed_at, None);
+    }
+}
@@ -41,7 +41,12 @@ pub async fn run(arg: Arg) -> Ret {
         Ok(response) => {
             let url = response.url().clone();
             let status = response.status();
-            match response.text().await {
+            let text = if opt.req.binary == Some(true) {
+                response.bytes().await.map(|bytes| base64::encode(&bytes))
+            } else {
+                response.text().await
+            };
+            match text {
                 Err(err) => Ret { 
                     result: Err(err),
                     opt,
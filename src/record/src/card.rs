@@ -7,6 +7,7 @@ use serde::{
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Record {
+    pub id: Option<u64>,
     pub body_type: Option<String>,
     pub brand: Option<String>,
     pub color: Option<String>,
@@ -62,11 +63,55 @@ pub struct Record {
     pub type_of_trade: Option<String>,
     pub canonical_url: Option<String>,
 
+    pub location_id: Option<u64>,
+    pub lat: Option<f64>,
+    pub lng: Option<f64>,
+
+    pub time: Option<chrono::DateTime<chrono::Utc>>, // время публикации
+    pub views_total: Option<u64>,
+    pub views_today: Option<u64>,
+    pub images_qt: Option<usize>,
+    pub images: Option<String>, // ссылки на фотографии через пробел
+    pub has_video: Option<bool>,
+
+    pub first_seen: Option<chrono::DateTime<chrono::Utc>>,
+    pub last_seen: Option<chrono::DateTime<chrono::Utc>>,
+    pub closed_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub days_on_market: Option<i64>,
+
+    pub cluster_id: Option<u64>,
+    pub duplicate_of: Option<u64>,
+    pub change_type: Option<String>,
+
+    pub power_hp: Option<f64>,
+    pub power_kw: Option<f64>,
+    pub displacement_l: Option<f64>,
+    pub displacement_cc: Option<f64>,
+    pub torque_nm: Option<f64>,
+    pub max_speed_kmh: Option<f64>,
+    pub acceleration_s: Option<f64>,
+    pub consumption_city_l100km: Option<f64>,
+    pub consumption_highway_l100km: Option<f64>,
+    pub consumption_mixed_l100km: Option<f64>,
+    pub fuel_tank_l: Option<f64>,
+    pub length_mm: Option<f64>,
+    pub width_with_mirrors_mm: Option<f64>,
+    pub height_mm: Option<f64>,
+    pub wheelbase_mm: Option<f64>,
+    pub clearance_mm: Option<f64>,
+    pub front_track_mm: Option<f64>,
+    pub rear_track_mm: Option<f64>,
+
+    pub extra: Option<String>, // неизвестные ключи карточки, json-строкой
+
     // #[serde(flatten)]
     // pub autocatalog: Option<super::autocatalog::Record>,
 
     pub autocatalog_id: Option<u64>,
     pub autocatalog_title: Option<String>,
+    pub autocatalog_match_method: Option<String>,
+    pub autocatalog_match_confidence: Option<f64>,
+    pub autocatalog_match_candidates: Option<u64>,
 
     // #[serde(rename = "Коробка передач")]
     pub autocatalog_transmission: Option<String>,
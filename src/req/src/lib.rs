@@ -17,6 +17,8 @@ pub struct Req {
     pub method: reqwest::Method,
     pub url: reqwest::Url,
     pub timeout: Option<Duration>,
+    // Ответ нужен как есть (картинки и т.п.): прокси вернет его в text в base64
+    pub binary: Option<bool>,
     // pub no_proxy: Option<bool>,
 }
 
@@ -28,10 +30,11 @@ enum Field {
     Method, 
     Url, 
     Timeout,
+    Binary,
     // NoProxy,
 }
 
-const FIELDS: &'static [&'static str] = &["correlation_id", "reply_to", "method", "url", "timeout"
+const FIELDS: &'static [&'static str] = &["correlation_id", "reply_to", "method", "url", "timeout", "binary"
 // , "no_proxy"
 ];
 
@@ -73,6 +76,11 @@ impl Serialize for Req {
                         state.serialize_field("timeout", &timeout.as_secs())?;
                     }
                 },
+                "binary" => {
+                    if let Some(binary) = &self.binary {
+                        state.serialize_field("binary", &binary)?;
+                    }
+                },
                 // "no_proxy" => {
                 //     if let Some(no_proxy) = &self.no_proxy {
                 //         state.serialize_field("no_proxy", &no_proxy)?;
@@ -114,6 +122,7 @@ impl<'de> Visitor<'de> for ReqVisitor {
         let mut method = None;
         let mut url = None;
         let mut timeout = None;
+        let mut binary = None;
         while let Some(key) = map.next_key()? {
             match key {
                 Field::CorrelationId => {
@@ -166,6 +175,12 @@ impl<'de> Visitor<'de> for ReqVisitor {
                     }
                     timeout = Some( Duration::from_secs(map.next_value()? ));
                 }
+                Field::Binary => {
+                    if binary.is_some() {
+                        return Err(de::Error::duplicate_field("binary"));
+                    }
+                    binary = Some(map.next_value()?);
+                }
                 // Field::NoProxy => {
                 //     if no_proxy.is_some() {
                 //         return Err(de::Error::duplicate_field("no_proxy"));
@@ -178,7 +193,7 @@ impl<'de> Visitor<'de> for ReqVisitor {
         let reply_to = reply_to.ok_or_else(|| de::Error::missing_field("reply_to"))?;
         let method = method.ok_or_else(|| de::Error::missing_field("method"))?;
         let url = url.ok_or_else(|| de::Error::missing_field("url"))?;
-        Ok(Req {correlation_id, reply_to, method, url, timeout})
+        Ok(Req {correlation_id, reply_to, method, url, timeout, binary})
     }
 }
 
@@ -239,6 +254,19 @@ mod tests {
         let eta = r#"{"correlation_id":"550e8400-e29b-41d4-a716-446655440000","reply_to":"response","method":"GET","url":"https://bikuzin.baza-winner.ru/echo"}"#;
         assert_eq!(tst, eta);
 
+        let json = r#"{
+            "correlation_id": "550e8400-e29b-41d4-a716-446655440000",
+            "reply_to": "response",
+            "method": "GET",
+            "url": "https://bikuzin.baza-winner.ru/echo",
+            "binary": true
+        }"#;
+        let req: Req = serde_json::from_str(json).unwrap();
+        assert_eq!(req.binary, Some(true));
+        let tst = serde_json::to_string(&req).unwrap();
+        let eta = r#"{"correlation_id":"550e8400-e29b-41d4-a716-446655440000","reply_to":"response","method":"GET","url":"https://bikuzin.baza-winner.ru/echo","binary":true}"#;
+        assert_eq!(tst, eta);
+
         let json = r#"{
             "correlation_id": "550e8400-e29b-41d4-a716-446655440000",
             "reply_to": "response",
@@ -248,7 +276,7 @@ mod tests {
         }"#;
         let err = serde_json::from_str::<Req>(json).unwrap_err();
         let tst = format!("{}", err);
-        let eta = r#"unknown field `proxy`, expected one of `correlation_id`, `reply_to`, `method`, `url`, `timeout` at line 6 column 19"#.to_owned();
+        let eta = r#"unknown field `proxy`, expected one of `correlation_id`, `reply_to`, `method`, `url`, `timeout`, `binary` at line 6 column 19"#.to_owned();
         assert_eq!(tst, eta); 
 
         Ok(())
@@ -1,18 +1,27 @@
 #[allow(unused_imports)]
 use log::{error, warn, info, debug, trace};
 #[allow(unused_imports)]
-use anyhow::{Result, Error, bail, anyhow};
+use anyhow::{Result, Error, bail, anyhow, Context};
 
 use diap_store::{DiapStore};
 use id_store::{IdStore};
+use lifecycle::{Lifecycle};
 use std::path::Path;
 use std::time::Instant;
 
 // use regex::Regex;
 use std::collections::HashSet;
+use std::sync::Arc;
 use autocatalog;
 
 use term::Term;
+use futures::stream::{LocalBoxStream, StreamExt};
+
+const OUTPUT_THROTTLE: u128 = 500; //ms
+
+mod writer;
+
+use writer::{Writer, Search};
 
 use structopt::StructOpt;
 use std::path::PathBuf;
@@ -42,6 +51,10 @@ struct Opt {
     #[structopt(short, long)]
     autocatalog_skip: bool,
 
+    /// download images of cards
+    #[structopt(short, long)]
+    images: bool,
+
     // /// extract bmw
     // #[structopt(short, long)]
     // extract_bmw: bool,
@@ -49,7 +62,65 @@ struct Opt {
     // /// convert
     // #[structopt(long)]
     // conver: bool,
+
+    #[structopt(subcommand)]
+    cmd: Option<Command>,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// cards which failed to parse
+    Quarantine {
+        #[structopt(subcommand)]
+        cmd: QuarantineCommand,
+    },
+    /// copy cards from the files layout into the configured card storage (files are kept)
+    Import,
+    /// rewrite stored cards of older schema versions in the current one, in place
+    Migrate,
+    /// export collected records to csv, parquet, sqlite, ndjson, xlsx or postgres, optionally filtered
+    Export {
+        /// filter expression, e.g. 'brand == "BMW" && production_date >= 2015 && item_price < 2000000'
+        #[structopt(long)]
+        filter: Option<String>,
+        /// name of a saved search from [searches] of the config
+        #[structopt(long, conflicts_with = "filter")]
+        search: Option<String>,
+        /// output file, `-` for stdout (ndjson only) [default: records_filtered.<format> or records_<search>.<format> in out_dir]
+        #[structopt(long, parse(from_os_str))]
+        output: Option<PathBuf>,
+        /// csv, parquet, sqlite, ndjson, xlsx or postgres (to postgres_url of the config) [default: export_format of the config]
+        #[structopt(long)]
+        format: Option<settings::ExportFormat>,
+        /// gzip the output (ndjson only); the default output file gets .gz
+        #[structopt(long)]
+        gzip: bool,
+        /// name of a column profile from [export_profiles] of the config [default: export_profile of the config]
+        #[structopt(long)]
+        profile: Option<String>,
+        /// only records new or changed (price, status, mileage, description) since the last incremental export,
+        /// and removed ones; the default output file gets _changes
+        #[structopt(long)]
+        incremental: bool,
+    },
+    /// report cards lost between harvest and export (unreadable, with error, not found, no text, not fetched) to gaps.csv
+    Gaps {
+        /// fetch the affected cards again
+        #[structopt(long)]
+        refetch: bool,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum QuarantineCommand {
+    /// list quarantined cards
+    List,
+    /// group quarantined cards by failing json path
+    Group,
+    /// replay quarantined cards against the current parser
+    Replay,
 }
+
 use settings::Settings;
 
 #[tokio::main]
@@ -62,13 +133,172 @@ async fn main() -> Result<()> {
 
     let opt = Opt::from_args();
 
+    // при выгрузке в стандартный вывод в него не пишется ничего, кроме записей
+    let quiet = match &opt.cmd {
+        Some(Command::Export {output: Some(output), ..}) => writer::is_stdout(output),
+        _ => false,
+    };
+
     let settings = Settings::new(&opt.config).map_err(|err| anyhow!("{:?}: {}", opt.config, err))?;
-    println!("config: {:?}, settings: {}", opt.config, settings.as_string_pretty()?);
+    if !quiet {
+        println!("config: {:?}, settings: {}", opt.config, settings.as_string_pretty()?);
+    }
 
     let settings_rmq = rmq::Settings::new(&opt.rmq).map_err(|err| anyhow!("{:?}: {}", opt.rmq, err))?;
-    println!("rmq: {:?}, settings: {}", opt.config, settings_rmq.as_string_pretty()?);
+    if !quiet {
+        println!("rmq: {:?}, settings: {}", opt.config, settings_rmq.as_string_pretty()?);
+    }
+
+    if let Some(file_path) = &settings.firebase_params {
+        cards::mapping::set(cards::mapping::Mapping::from_file(Path::new(file_path)).await?);
+    }
 
     let out_dir = PathBuf::from(&settings.out_dir);
+
+    let store_kind = match settings.card_storage {
+        settings::CardStorage::Files => cards::store::Kind::Files,
+        settings::CardStorage::Pack => cards::store::Kind::Pack,
+        settings::CardStorage::Sqlite => cards::store::Kind::Sqlite,
+    };
+    // хранилище и все его вызовы - блокирующие (см. cards::CardStore), поэтому через spawn_blocking
+    let store = {
+        let out_dir = out_dir.clone();
+        tokio::task::spawn_blocking(move || cards::store::open(store_kind, &out_dir)).await??
+    };
+
+    let profile = match &settings.export_profile {
+        Some(name) => Some(export_profile(&settings, name)?),
+        None => None,
+    };
+
+    let mut searches: Vec<(String, filter::Filter)> = Vec::new();
+    for (name, s) in settings.searches.iter() {
+        searches.push((name.to_owned(), filter::Filter::new(s).context(format!("search {:?}", name))?));
+    }
+
+    if let Some(cmd) = opt.cmd {
+        return match cmd {
+            Command::Quarantine {cmd} => quarantine(cmd, &out_dir, settings.lenient, &store).await,
+            Command::Import => {
+                if store_kind == cards::store::Kind::Files {
+                    bail!("card_storage = \"files\": nothing to import");
+                }
+                let start = Instant::now();
+                let (imported_qt, len) = {
+                    let out_dir = out_dir.clone();
+                    let store = store.clone();
+                    tokio::task::spawn_blocking(move || -> Result<(usize, usize)> {
+                        let imported_qt = cards::store::copy(&cards::files::Files::new(&out_dir), store.as_ref())?;
+                        Ok((imported_qt, store.len()?))
+                    }).await??
+                };
+                println!("{}, Перенесены в {:?}: {}, всего в хранилище: {}", arrange_millis::get(Instant::now().duration_since(start).as_millis()), store_kind, imported_qt, len);
+                Ok(())
+            },
+            Command::Migrate => {
+                let start = Instant::now();
+                let migrated = {
+                    let store = store.clone();
+                    tokio::task::spawn_blocking(move || store.migrate()).await??
+                };
+                println!("{}, Версия схемы карточек: {}, обновлены: {} из {}", arrange_millis::get(Instant::now().duration_since(start).as_millis()), cards::schema::VERSION, migrated.migrated, migrated.total);
+                Ok(())
+            },
+            Command::Export {filter, search, output, format, gzip, profile: profile_name, incremental} => {
+                let format = format.unwrap_or(settings.export_format);
+                let profile = match profile_name {
+                    Some(name) => Some(export_profile(&settings, &name)?),
+                    None => profile,
+                };
+                let (filter, file_stem) = match (filter, search) {
+                    (Some(s), _) => (Some(filter::Filter::new(&s)?), "records_filtered".to_owned()),
+                    (None, Some(name)) => match searches.into_iter().find(|(item, _)| *item == name) {
+                        Some((_, filter)) => (Some(filter), format!("records_{}", name)),
+                        None => bail!("no search {:?} in [searches] of {:?}", name, opt.config),
+                    },
+                    (None, None) => (None, "records".to_owned()),
+                };
+                if incremental && format == settings::ExportFormat::Postgres {
+                    bail!("incremental export is not supported for postgres: it upserts all records");
+                }
+                let file_path = output.unwrap_or_else(|| out_dir.join(format!("{}{}.{}{}", file_stem, if incremental { "_changes" } else { "" }, format.extension(), if gzip { ".gz" } else { "" })));
+                export(ExportArg {
+                    filter,
+                    file_path: &file_path,
+                    state_file_spec: if incremental { Some(changes::file_spec(&out_dir, &file_stem)) } else { None },
+                    format,
+                    gzip,
+                    language: settings.export_language,
+                    profile,
+                    postgres_url: settings.postgres_url.as_deref(),
+                    out_dir: &out_dir,
+                    dedup_threshold: settings.dedup_threshold,
+                    enrichers: &settings.enrichers,
+                    autocatalog_min_confidence: settings.autocatalog_min_confidence,
+                    store_kind,
+                    store: &store,
+                }).await
+            },
+            Command::Gaps {refetch} => {
+                let start = Instant::now();
+                let id_store = match IdStore::from_file(&out_dir.join("ids.json")).await {
+                    Ok(id_store) => id_store,
+                    Err(_) => IdStore::new(),
+                };
+                let files_out_dir = if store_kind == cards::store::Kind::Files { Some(out_dir.clone()) } else { None };
+                let harvested = id_store.all_ids();
+                let gaps = {
+                    let store = store.clone();
+                    tokio::task::spawn_blocking(move || collect::gaps::collect(store.as_ref(), files_out_dir.as_deref(), &harvested)).await??
+                };
+                let file_path = out_dir.join("gaps.csv");
+                let mut writer = to_csv::Writer::new(&file_path).await?;
+                for item in gaps.items.iter() {
+                    writer.write(item)?;
+                }
+                writer.finish()?;
+                for (category, qt) in gaps.counts() {
+                    println!("{:?}: {}", category, qt);
+                }
+                println!("{}, Пробелы ({}) записаны в {:?}", arrange_millis::get(Instant::now().duration_since(start).as_millis()), gaps.items.len(), file_path.to_string_lossy());
+                if !refetch {
+                    return Ok(());
+                }
+
+                let ids = gaps.ids_to_refetch();
+                let pool = rmq::get_pool(settings_rmq)?;
+                let queue_name = format!("response-{}", uuid::Uuid::new_v4());
+                let client_provider = client::Provider::new(client::Kind::ViaProxy(pool.clone(), queue_name));
+                let mut auth = auth(&settings)?;
+                let mut term = Term::init(term::Arg::new().header("Повторное получение объявлений . . ."));
+                let arg = cards::Arg {
+                    ids: &ids,
+                    out_dir: &out_dir,
+                    thread_limit_network: settings.thread_limit_network,
+                    thread_limit_file: settings.thread_limit_file,
+                    client_provider,
+                    lenient: settings.lenient,
+                    store: store.clone(),
+                    refetch: true,
+                };
+                let start = Instant::now();
+                let ret = cards::fetch_and_save(&mut auth, arg, Some(|arg: cards::CallbackArg| -> Result<()> {
+                    term.output(format!("time: {}/{}-{}, per: {}, qt: {}/{}-{}",
+                        arrange_millis::get(arg.elapsed_millis),
+                        arrange_millis::get(arg.elapsed_millis + arg.remained_millis),
+                        arrange_millis::get(arg.remained_millis),
+                        arrange_millis::get(arg.per_millis),
+                        arg.elapsed_qt,
+                        arg.elapsed_qt + arg.remained_qt,
+                        arg.remained_qt,
+                    ));
+                    Ok(())
+                })).await?;
+                println!("{}, Объявления получены повторно: {} из {}", arrange_millis::get(Instant::now().duration_since(start).as_millis()), ret.received_qt, ids.len());
+                Ok(())
+            },
+        };
+    }
 // if opt.convert {
 //
 // } else {
@@ -77,7 +307,14 @@ async fn main() -> Result<()> {
     let queue_name = format!("response-{}", queue_uuid);
     let client_provider = client::Provider::new(client::Kind::ViaProxy(pool.clone(), queue_name));
 
+    let lifecycle_file_spec = lifecycle::file_spec(&out_dir);
+    let mut lifecycle = match Lifecycle::from_file(&lifecycle_file_spec).await {
+        Ok(lifecycle) => lifecycle,
+        Err(_) => Lifecycle::new(),
+    };
+
 if !opt.scan_skip {
+    let mut auth = auth(&settings)?;
     let params = settings.params;
     let id_store_file_spec= {
         let mut id_store_file_spec = out_dir.clone();
@@ -96,18 +333,10 @@ if !opt.scan_skip {
     let thread_limit_file = settings.thread_limit_file;
     //
 
-    let mut auth = if let Some(key) = settings.auth_key {
-        auth::Lazy::new(auth::Arg::new_ready(key))
-    } else if let Some(url) = settings.auth_url {
-        auth::Lazy::new(auth::Arg::new_lazy(url))
-    } else {
-        bail!("nor auth_key, neighter auth_url is specifed in settings");
-    };
-
-    let ids = match id_store.get_ids(&params, id_fresh_duration) {
+    let ids_item = match id_store.get_ids(&params, id_fresh_duration) {
         Some(item) => {
             println!("{} ids loaded from {:?}", item.ret.len(), id_store_file_spec.to_string_lossy());
-            &item.ret
+            item
         },
         None => {
 
@@ -202,9 +431,11 @@ if !opt.scan_skip {
 
             println!("{}, Список идентификаторов ({}) получен и записан в {:?}", arrange_millis::get(Instant::now().duration_since(start).as_millis()), ids_len, id_store_file_spec.to_string_lossy());
 
-            &id_store.get_ids(&params, id_fresh_duration).unwrap().ret
+            id_store.get_ids(&params, id_fresh_duration).unwrap()
         },
     };
+    let ids = &ids_item.ret;
+    lifecycle.adopt_ids(&params, ids, ids_item.timestamp);
 
     let mut term = Term::init(term::Arg::new().header("Получение объявлений . . ."));
     let arg = cards::Arg {
@@ -213,6 +444,9 @@ if !opt.scan_skip {
         thread_limit_network,
         thread_limit_file,
         client_provider: client_provider.clone(),
+        lenient: settings.lenient,
+        store: store.clone(),
+        refetch: false,
     };
     let start = Instant::now();
     let ret = cards::fetch_and_save(&mut auth, arg, Some(|arg: cards::CallbackArg| -> Result<()> {
@@ -229,58 +463,102 @@ if !opt.scan_skip {
         Ok(())
     })).await?;
     println!("{}, Объявления получены: {}", arrange_millis::get(Instant::now().duration_since(start).as_millis()), ret.received_qt);
+
+    let now = chrono::Utc::now();
+    for id in ret.not_found.iter() {
+        lifecycle.adopt_not_found(*id, now);
+    }
+    lifecycle.to_file(&lifecycle_file_spec).await?;
+
+    if !ret.drift.is_empty() {
+        let drift_file_spec = {
+            let mut drift_file_spec = out_dir.clone();
+            drift_file_spec.push("drift.json");
+            drift_file_spec
+        };
+        ret.drift.to_file(&drift_file_spec).await?;
+        // отчет только по полученным в этом запуске; по всем сохраненным карточкам он составляется при чтении объявлений
+        println!("Неожиданные ключи в карточках, полученных в этом запуске (записаны в {:?}):\n{}", drift_file_spec.to_string_lossy(), ret.drift.report());
+    }
 }
 
-// let records = 
-if opt.collect_skip { None } else {
+if !opt.collect_skip {
     let cards_dir = {
         let mut cards_dir = out_dir.clone();
         cards_dir.push("cards");
         cards_dir
     };
-    let arg = collect::Arg { 
-        out_dir: &cards_dir.as_path(),
-        thread_limit_file: 3,
-    };
 
-    let mut term = Term::init(term::Arg::new().header("Чтение объявлений . . ."));
-    let start = Instant::now();
-    let mut records = collect::Records::new();
-    collect::items(arg, &mut records, Some(|arg: collect::CallbackArg| -> Result<()> {
-        match arg {
-            collect::CallbackArg::ReadDir {elapsed_millis, dir_qt, file_qt} => {
-                term.output(format!("time: {}, dirs: {}, files: {}", 
-                    arrange_millis::get(elapsed_millis), 
-                    dir_qt,
-                    file_qt,
-                ));
-                Ok(())
-            },
-            collect::CallbackArg::ReadFile {elapsed_millis, remained_millis, per100_millis, elapsed_qt, remained_qt} => {
-                term.output(format!("time: {}/{}-{}, per100: {}, qt: {}/{}-{}", 
-                    arrange_millis::get(elapsed_millis), 
-                    arrange_millis::get(elapsed_millis + remained_millis), 
-                    arrange_millis::get(remained_millis), 
-                    arrange_millis::get(per100_millis), 
-                    elapsed_qt,
-                    elapsed_qt + remained_qt,
-                    remained_qt,
-                ));
-                Ok(())
-            },
+    // Объявления не держатся в памяти целиком, а читаются потоком дважды: сначала собираются
+    // ссылки на автокаталог и изображения и сведения для поиска повторов, затем записи дополняются и выгружаются
+    let mut autocatalog_urls: HashSet<String> = HashSet::new();
+    let mut image_cards: Vec<images::Card> = Vec::new();
+    let mut dedup = dedup::Dedup::new(settings.dedup_threshold);
+    let mut drift = cards::Drift::new();
+    {
+        let mut term = Term::init(term::Arg::new().header("Чтение объявлений . . ."));
+        let start = Instant::now();
+        let mut last_output = Instant::now();
+        let mut qt = 0;
+        let mut records = records(store_kind, &cards_dir, &store);
+        while let Some(record) = records.next().await {
+            let record = record?;
+            dedup.adopt_record(&record);
+            if let (Some(id), Some(extra)) = (record.id, record.extra.as_ref()) {
+                drift.adopt(id, extra);
+            }
+            if let Some(autocatalog_url) = record.autocatalog_url {
+                autocatalog_urls.insert(autocatalog_url);
+            }
+            if opt.images {
+                if let (Some(id), Some(urls)) = (record.id, record.images) {
+                    image_cards.push(images::Card { id, urls: urls.0 });
+                }
+            }
+            qt += 1;
+            if Instant::now().duration_since(last_output).as_millis() > OUTPUT_THROTTLE {
+                term.output(format!("time: {}, qt: {}", arrange_millis::get(Instant::now().duration_since(start).as_millis()), qt));
+                last_output = Instant::now();
+            }
         }
-    })).await?;
-    println!("{}, Объявления прочитаны: {}", arrange_millis::get(Instant::now().duration_since(start).as_millis()), records.0.len());
+        println!("{}, Объявления прочитаны: {}", arrange_millis::get(Instant::now().duration_since(start).as_millis()), qt);
+    }
+    let clusters = dedup.clusters();
+    println!("Повторы объявлений: {}", clusters.duplicate_qt());
+    if !drift.is_empty() {
+        let drift_file_spec = out_dir.join("drift.json");
+        drift.to_file(&drift_file_spec).await?;
+        println!("Неожиданные ключи во всех сохраненных карточках (записаны в {:?}):\n{}", drift_file_spec.to_string_lossy(), drift.report());
+    }
 
+    if opt.images {
+        let arg = images::Arg {
+            cards: image_cards,
+            out_dir: &out_dir,
+            thread_limit_network: settings.thread_limit_images,
+            thread_limit_file: settings.thread_limit_file,
+            client_provider: client_provider.clone(),
+        };
 
-    if !opt.autocatalog_skip {
+        let mut term = Term::init(term::Arg::new().header("Получение изображений . . ."));
+        let start = Instant::now();
+        let ret = images::fetch_and_save(arg, Some(|arg: images::CallbackArg| -> Result<()> {
+            term.output(format!("time: {}/{}-{}, per: {}, qt: {}/{}-{}", 
+                arrange_millis::get(arg.elapsed_millis), 
+                arrange_millis::get(arg.elapsed_millis + arg.remained_millis), 
+                arrange_millis::get(arg.remained_millis), 
+                arrange_millis::get(arg.per_millis), 
+                arg.elapsed_qt,
+                arg.elapsed_qt + arg.remained_qt,
+                arg.remained_qt,
+            ));
+            Ok(())
+        })).await?;
+        println!("{}, Изображения получены: {}, новых: {}, повторов: {}", arrange_millis::get(Instant::now().duration_since(start).as_millis()), ret.received_qt, ret.stored_qt, ret.dup_qt);
+    }
 
-        let mut autocatalog_urls: HashSet<String> = HashSet::new();
-        for record in records.0.iter() {
-            if let Some(autocatalog_url) = &record.autocatalog_url {
-                autocatalog_urls.insert(autocatalog_url.to_owned());
-            }
-        }
+
+    if !opt.autocatalog_skip {
 
         trace!("autocatalog_urls: {}", autocatalog_urls.len());
 
@@ -309,92 +587,72 @@ if opt.collect_skip { None } else {
         })).await?;
     }
 
-    for mut record in records.0.iter_mut() {
-        if let Some(autocatalog_url) = &record.autocatalog_url {
-            match autocatalog::get(&out_dir, autocatalog_url).await {
-                Err(err) => error!("autocatalog_url not found: {}", err),
-                Ok(autocatalog_record) => {
-                    record.autocatalog_id = Some(autocatalog_record.id);
-                    record.autocatalog_title = Some(autocatalog_record.title);
-
-                    record.autocatalog_transmission = autocatalog_record.transmission;
-
-                    record.autocatalog_engine_displacement = autocatalog_record.engine_displacement;
-                    record.autocatalog_engine_displacement_precise = autocatalog_record.engine_displacement_precise;
-
-                    record.autocatalog_drive = autocatalog_record.drive;
-                    record.autocatalog_fuel_type = autocatalog_record.fuel_type;
-
-                    record.autocatalog_engine_power = autocatalog_record.engine_power;
-                    record.autocatalog_maximum_speed = autocatalog_record.maximum_speed;
-                    record.autocatalog_acceleration = autocatalog_record.acceleration;
-
-                    record.autocatalog_brand_country = autocatalog_record.brand_country;
-                    record.autocatalog_assembly_country = autocatalog_record.assembly_country;
-
-                    record.autocatalog_number_of_seats = autocatalog_record.number_of_seats;
-
-                    record.autocatalog_rating = autocatalog_record.rating;
-
-                    record.autocatalog_number_of_cylinders = autocatalog_record.number_of_cylinders;
-                    record.autocatalog_configuration = autocatalog_record.configuration;
-
-                    record.autocatalog_torque = autocatalog_record.torque;
-                    record.autocatalog_torque_max = autocatalog_record.torque_max;
-                    record.autocatalog_max_power_speed = autocatalog_record.max_power_speed;
-
-                    record.autocatalog_height = autocatalog_record.height;
-                    record.autocatalog_length = autocatalog_record.length;
-                    record.autocatalog_turning_diameter = autocatalog_record.turning_diameter;
-                    record.autocatalog_clearance = autocatalog_record.clearance;
-                    record.autocatalog_wheelbase = autocatalog_record.wheelbase;
-                    record.autocatalog_rear_track = autocatalog_record.rear_track;
-                    record.autocatalog_front_track = autocatalog_record.front_track;
-
-                    record.autocatalog_trunk_volume = autocatalog_record.trunk_volume;
-
-                    record.autocatalog_fuel_tank_capacity = autocatalog_record.fuel_tank_capacity;
-
-                    record.autocatalog_fuel_consumption_city = autocatalog_record.fuel_consumption_city;
-                    record.autocatalog_fuel_consumption_highway = autocatalog_record.fuel_consumption_highway;
-                    record.autocatalog_fuel_consumption_mixed = autocatalog_record.fuel_consumption_mixed;
-
-                    record.autocatalog_environmental_class = autocatalog_record.environmental_class;
-
-                    record.autocatalog_rear_breaks = autocatalog_record.rear_breaks;
-                    record.autocatalog_front_breaks = autocatalog_record.front_breaks;
-
-                    record.autocatalog_rear_tire_dimension = autocatalog_record.rear_tire_dimension;
-                    record.autocatalog_front_tire_dimension = autocatalog_record.front_tire_dimension;
-
-                    record.autocatalog_rear_suspension = autocatalog_record.rear_suspension;
-                    record.autocatalog_front_suspension = autocatalog_record.front_suspension;
-
-                    record.autocatalog_world_premier = autocatalog_record.world_premier;
-                    record.autocatalog_pending_update = autocatalog_record.pending_update;
-                    record.autocatalog_width_with_mirrors = autocatalog_record.width_with_mirrors;
-                    record.autocatalog_rear_disc_dimension = autocatalog_record.rear_disc_dimension;
-                    record.autocatalog_front_disc_dimension = autocatalog_record.front_disc_dimension;
-                }
-            };
-        }
-    }
-
-    let start = Instant::now();
+    let export_format = settings.export_format;
     let file_path = {
         let mut file_path = out_dir.clone();
-        file_path.push("records.csv");
+        file_path.push(format!("records.{}", export_format.extension()));
         file_path
     };
     // Path::new("/out/records.csv");
-    let arg = to_csv::Arg {
-        file_path: &file_path,
-        records: &records,
-    };
-    to_csv::write(arg).await?;
-    println!("{}, Записаны в файл {:?}", arrange_millis::get(Instant::now().duration_since(start).as_millis()), file_path);
-    Some(records)
-};
+    let mut writer = Writer::new(export_format, &file_path, false, settings.export_language, profile.clone(), settings.postgres_url.as_deref()).await?;
+    let mut search_writers = Vec::new();
+    // в postgres выгружаются все объявления, отборы - запросами к базе
+    let searches = if export_format == settings::ExportFormat::Postgres { &searches[..0] } else { &searches[..] };
+    for (name, filter) in searches.iter() {
+        match writer.add_sheet(name)? {
+            Some(sheet) => search_writers.push((filter, Search::Sheet(sheet), file_path.clone(), 0)),
+            None => {
+                let file_path = out_dir.join(format!("records_{}.{}", name, export_format.extension()));
+                let search_writer = Writer::new(export_format, &file_path, false, settings.export_language, profile.clone(), None).await?;
+                search_writers.push((filter, Search::File(Box::new(search_writer)), file_path, 0));
+            },
+        }
+    }
+
+    let mut term = Term::init(term::Arg::new().header("Выгрузка объявлений . . ."));
+    let start = Instant::now();
+    let mut last_output = Instant::now();
+    let mut qt = 0;
+    let now = chrono::Utc::now();
+    let mut units = units::Report::new();
+    let catalog = catalog(&settings.enrichers, &out_dir).await?;
+    let autocatalog = enrich::Autocatalog::new(&out_dir, &catalog, settings.autocatalog_min_confidence);
+    let mut pipeline = pipeline(&settings.enrichers, autocatalog, &mut lifecycle, &clusters, &mut units, now)?;
+    let mut records = records(store_kind, &cards_dir, &store);
+    while let Some(record) = records.next().await {
+        let mut record = record?;
+        pipeline.run(&mut record).await;
+        writer.write(&record, &store).await?;
+        for (filter, search, _, qt) in search_writers.iter_mut() {
+            if filter.matches(&record) {
+                match search {
+                    Search::File(search_writer) => search_writer.write(&record, &store).await?,
+                    Search::Sheet(sheet) => writer.write_sheet(*sheet, &record)?,
+                }
+                *qt += 1;
+            }
+        }
+        qt += 1;
+        if Instant::now().duration_since(last_output).as_millis() > OUTPUT_THROTTLE {
+            term.output(format!("time: {}, qt: {}", arrange_millis::get(Instant::now().duration_since(start).as_millis()), qt));
+            last_output = Instant::now();
+        }
+    }
+    drop(pipeline);
+    mark_inactive(&mut writer, export_format, &out_dir).await?;
+    writer.finish()?;
+    lifecycle.to_file(&lifecycle_file_spec).await?;
+    println!("{}, Объявления ({}) записаны в {}", arrange_millis::get(Instant::now().duration_since(start).as_millis()), qt, writer::target(export_format, &file_path));
+    if let Some(message) = write_units_report(&units, &out_dir).await? {
+        println!("{}", message);
+    }
+    for (filter, search, file_path, qt) in search_writers {
+        if let Search::File(writer) = search {
+            writer.finish()?;
+        }
+        println!("Отобраны ({}) по {:?} и записаны в файл {:?}", qt, filter.as_str(), file_path);
+    }
+}
 
 // if opt.extract_bmw {
 //     let records = match records {
@@ -512,3 +770,245 @@ if opt.collect_skip { None } else {
     Ok(())
 }
 
+fn auth(settings: &Settings) -> Result<auth::Lazy> {
+    Ok(if let Some(key) = &settings.auth_key {
+        auth::Lazy::new(auth::Arg::new_ready(key.to_owned()))
+    } else if let Some(url) = &settings.auth_url {
+        auth::Lazy::new(auth::Arg::new_lazy(url.to_owned()))
+    } else {
+        bail!("nor auth_key, neighter auth_url is specifed in settings");
+    })
+}
+
+async fn quarantine(cmd: QuarantineCommand, out_dir: &Path, lenient: bool, store: &Arc<dyn cards::CardStore>) -> Result<()> {
+    match cmd {
+        QuarantineCommand::List => {
+            let items = cards::quarantine::list(out_dir).await?;
+            for item in items.iter() {
+                println!("{}: {}", item.id, item.error);
+            }
+            println!("В карантине: {}", items.len());
+        },
+        QuarantineCommand::Group => {
+            let items = cards::quarantine::list(out_dir).await?;
+            for (path, ids) in cards::quarantine::group(&items) {
+                println!("{}: {}, ids: {}", path, ids.len(), ids.iter().map(|id| id.to_string()).collect::<Vec<String>>().join(", "));
+            }
+            println!("В карантине: {}", items.len());
+        },
+        QuarantineCommand::Replay => {
+            let start = Instant::now();
+            let ret = cards::quarantine::replay(out_dir, lenient, store).await?;
+            for item in ret.failed.iter() {
+                println!("{}: {}", item.id, item.error);
+            }
+            println!("{}, Разобраны и извлечены из карантина: {}, остались в карантине: {}", arrange_millis::get(Instant::now().duration_since(start).as_millis()), ret.fixed.len(), ret.failed.len());
+        },
+    }
+    Ok(())
+}
+
+// Модификации автокаталога для сопоставления (см. enrich::matcher) - до выгрузки, если он в enrichers
+async fn catalog(enrichers: &[settings::Enricher], out_dir: &Path) -> Result<enrich::matcher::Catalog> {
+    if !enrichers.contains(&settings::Enricher::Autocatalog) {
+        return Ok(enrich::matcher::Catalog::default());
+    }
+    let ret = enrich::matcher::Catalog::load(out_dir).await.context("autocatalog modifications")?;
+    info!("autocatalog modifications to match: {}", ret.len());
+    Ok(ret)
+}
+
+// Конвейер дополнения записей перед выгрузкой из настройки enrichers
+fn pipeline<'a>(
+    enrichers: &[settings::Enricher],
+    autocatalog: enrich::Autocatalog<'a>,
+    lifecycle: &'a mut Lifecycle,
+    clusters: &'a dedup::Clusters,
+    units: &'a mut units::Report,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Result<enrich::Pipeline<'a>> {
+    let mut autocatalog = Some(autocatalog);
+    let mut lifecycle = Some(lifecycle);
+    let mut units = Some(units);
+    let mut ret = enrich::Pipeline::new();
+    for kind in enrichers {
+        let enricher: Box<dyn enrich::Enricher + 'a> = match kind {
+            settings::Enricher::Autocatalog => match autocatalog.take() {
+                Some(autocatalog) => Box::new(autocatalog),
+                None => bail!("enrichers: {:?} is listed twice", kind),
+            },
+            settings::Enricher::Units => match units.take() {
+                Some(units) => Box::new(enrich::Units::new(units)),
+                None => bail!("enrichers: {:?} is listed twice", kind),
+            },
+            settings::Enricher::Lifecycle => match lifecycle.take() {
+                Some(lifecycle) => Box::new(enrich::Lifecycle::new(lifecycle, now)),
+                None => bail!("enrichers: {:?} is listed twice", kind),
+            },
+            settings::Enricher::Duplicates => Box::new(enrich::Duplicates::new(clusters)),
+        };
+        ret.push(enricher)?;
+    }
+    Ok(ret)
+}
+
+// Выгрузка собранных объявлений без обращения к сети; жизненный цикл только читается
+struct ExportArg<'a> {
+    filter: Option<filter::Filter>,
+    file_path: &'a Path,
+    // состояние прошлой выгрузки (только при инкрементной)
+    state_file_spec: Option<PathBuf>,
+    format: settings::ExportFormat,
+    gzip: bool,
+    language: settings::Language,
+    profile: Option<columns::Profile>,
+    postgres_url: Option<&'a str>,
+    out_dir: &'a Path,
+    dedup_threshold: f64,
+    enrichers: &'a [settings::Enricher],
+    autocatalog_min_confidence: f64,
+    store_kind: cards::store::Kind,
+    store: &'a Arc<dyn cards::CardStore>,
+}
+
+async fn export(arg: ExportArg<'_>) -> Result<()> {
+    let ExportArg { filter, file_path, state_file_spec, format, gzip, language, profile, postgres_url, out_dir, dedup_threshold, enrichers, autocatalog_min_confidence, store_kind, store } = arg;
+    let mut lifecycle = match Lifecycle::from_file(&lifecycle::file_spec(out_dir)).await {
+        Ok(lifecycle) => lifecycle,
+        Err(_) => Lifecycle::new(),
+    };
+    let cards_dir = out_dir.join("cards");
+
+    // повторы ищутся по всем объявлениям, а не только по отобранным
+    let mut dedup = dedup::Dedup::new(dedup_threshold);
+    if enrichers.contains(&settings::Enricher::Duplicates) {
+        let mut stream = records(store_kind, &cards_dir, store);
+        while let Some(record) = stream.next().await {
+            dedup.adopt_record(&record?);
+        }
+    }
+    let clusters = dedup.clusters();
+    let mut writer = Writer::new(format, file_path, gzip, language, profile, postgres_url).await?;
+    let mut changes = match &state_file_spec {
+        None => None,
+        Some(file_path) => Some(changes::Changes::new(changes::State::from_file(file_path).await?)),
+    };
+    let quiet = writer::is_stdout(file_path);
+
+    let mut term = if quiet { None } else { Some(Term::init(term::Arg::new().header("Выгрузка объявлений . . ."))) };
+    let start = Instant::now();
+    let mut last_output = Instant::now();
+    let mut qt = 0;
+    let mut matched_qt = 0;
+    let now = chrono::Utc::now();
+    let mut units = units::Report::new();
+    let catalog = catalog(enrichers, out_dir).await?;
+    let autocatalog = enrich::Autocatalog::new(out_dir, &catalog, autocatalog_min_confidence);
+    let mut pipeline = pipeline(enrichers, autocatalog, &mut lifecycle, &clusters, &mut units, now)?;
+    let mut records = records(store_kind, &cards_dir, store);
+    while let Some(record) = records.next().await {
+        let mut record = record?;
+        pipeline.run(&mut record).await;
+        qt += 1;
+        if filter.as_ref().map(|filter| filter.matches(&record)) != Some(false) {
+            let is_changed = match changes.as_mut() {
+                None => true,
+                Some(changes) => {
+                    let change_type = changes.check(&record);
+                    record.change_type = change_type.map(|change_type| change_type.as_str().to_owned());
+                    change_type.is_some()
+                },
+            };
+            if is_changed {
+                writer.write(&record, store).await?;
+                matched_qt += 1;
+            }
+        }
+        if let Some(term) = term.as_mut() {
+            if Instant::now().duration_since(last_output).as_millis() > OUTPUT_THROTTLE {
+                term.output(format!("time: {}, qt: {}, matched: {}", arrange_millis::get(Instant::now().duration_since(start).as_millis()), qt, matched_qt));
+                last_output = Instant::now();
+            }
+        }
+    }
+    if let Some(changes) = changes.as_ref() {
+        for id in changes.removed() {
+            writer.write(&changes::removed_record(id), store).await?;
+            matched_qt += 1;
+        }
+    }
+    drop(pipeline);
+    mark_inactive(&mut writer, format, out_dir).await?;
+    writer.finish()?;
+    if let (Some(changes), Some(state_file_spec)) = (changes, state_file_spec) {
+        changes.into_state(now).to_file(&state_file_spec).await?;
+    }
+    let message = format!("{}, Из {} объявлений отобраны ({}) и записаны в {}", arrange_millis::get(Instant::now().duration_since(start).as_millis()), qt, matched_qt, writer::target(format, file_path));
+    let units_message = write_units_report(&units, out_dir).await?;
+    for message in std::iter::once(message).chain(units_message) {
+        if quiet {
+            eprintln!("{}", message);
+        } else {
+            println!("{}", message);
+        }
+    }
+    Ok(())
+}
+
+// Значения, не разобранные в числовые характеристики, записываются в units_unparsed.csv;
+// возвращает сообщение для вывода (None - все разобраны)
+async fn write_units_report(units: &units::Report, out_dir: &Path) -> Result<Option<String>> {
+    if units.is_empty() {
+        return Ok(None);
+    }
+    let items = units.items();
+    let file_path = out_dir.join("units_unparsed.csv");
+    let mut writer = to_csv::Writer::new(&file_path).await?;
+    for item in items.iter() {
+        writer.write(item)?;
+    }
+    writer.finish()?;
+    Ok(Some(format!("Не разобраны значения характеристик ({}), см. {:?}", items.len(), file_path)))
+}
+
+// Объявления, которых нет ни в одном из списков идентификаторов (ids.json), помечаются неактивными (только postgres):
+// в таблицу выгружаются карточки всех поисков с общим out_dir, поэтому учитываются списки по всем параметрам поиска
+async fn mark_inactive(writer: &mut Writer, format: settings::ExportFormat, out_dir: &Path) -> Result<()> {
+    if format != settings::ExportFormat::Postgres {
+        return Ok(());
+    }
+    let id_store = match IdStore::from_file(&out_dir.join("ids.json")).await {
+        Ok(id_store) => id_store,
+        Err(err) => {
+            warn!("ids.json: {}, active flags are not updated", err);
+            return Ok(());
+        },
+    };
+    let ids = id_store.all_ids();
+    if ids.is_empty() {
+        warn!("no ids in ids.json, active flags are not updated");
+        return Ok(());
+    }
+    if let Some(inactive_qt) = writer.mark_inactive(&ids)? {
+        println!("Неактивны (нет ни в одном списке идентификаторов): {}", inactive_qt);
+    }
+    Ok(())
+}
+
+// Набор столбцов выгрузки по имени из [export_profiles]
+fn export_profile(settings: &Settings, name: &str) -> Result<columns::Profile> {
+    let specs = settings.export_profiles.get(name)
+        .ok_or_else(|| anyhow!("no profile {:?} in [export_profiles]", name))?;
+    columns::Profile::new(specs).context(format!("profile {:?}", name))
+}
+
+// Поток объявлений из настроенного хранилища
+fn records<'a>(store_kind: cards::store::Kind, cards_dir: &'a Path, store: &Arc<dyn cards::CardStore>) -> LocalBoxStream<'a, Result<cards::Record>> {
+    match store_kind {
+        cards::store::Kind::Files => collect::stream(collect::Arg {
+            out_dir: cards_dir,
+            thread_limit_file: 3,
+        }).boxed_local(),
+        _ => collect::stream_stored(store.clone()).boxed_local(),
+    }
+}
@@ -0,0 +1,139 @@
+
+#[allow(unused_imports)]
+use log::{error, warn, info, debug, trace};
+#[allow(unused_imports)]
+use anyhow::{Result, Error, bail, anyhow, Context};
+
+use settings::{ExportFormat, Language};
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::Arc;
+
+// ============================================================================
+// ============================================================================
+
+// Выгрузка записей в формате из настроек (export_format) или из ключа --format
+pub enum Writer {
+    Csv(to_csv::Writer),
+    Parquet(to_parquet::Writer),
+    Sqlite(to_sqlite::Writer),
+    Ndjson(to_ndjson::Writer),
+    Xlsx(Box<to_xlsx::Writer>),
+    Postgres(to_postgres::Writer),
+}
+
+impl Writer {
+    // gzip - сжатие выгрузки (только ndjson), language - язык заголовков (только xlsx);
+    // profile - столбцы выгрузки (для всех форматов, кроме postgres), None - все поля записи;
+    // postgres_url - куда выгружается postgres, file_path для него не используется
+    pub async fn new(format: ExportFormat, file_path: &Path, gzip: bool, language: Language, profile: Option<columns::Profile>, postgres_url: Option<&str>) -> Result<Self> {
+        if gzip && format != ExportFormat::Ndjson {
+            bail!("gzip is supported for ndjson only, not for {:?}", format);
+        }
+        if is_stdout(file_path) && format != ExportFormat::Ndjson {
+            bail!("output to stdout is supported for ndjson only, not for {:?}", format);
+        }
+        Ok(match (format, profile) {
+            (ExportFormat::Csv, None) => Writer::Csv(to_csv::Writer::new(file_path).await?),
+            (ExportFormat::Csv, Some(profile)) => Writer::Csv(to_csv::Writer::with_profile(file_path, profile).await?),
+            (ExportFormat::Parquet, None) => Writer::Parquet(to_parquet::Writer::new(file_path).await?),
+            (ExportFormat::Parquet, Some(profile)) => Writer::Parquet(to_parquet::Writer::with_profile(file_path, profile).await?),
+            (ExportFormat::Sqlite, None) => Writer::Sqlite(to_sqlite::Writer::new(file_path).await?),
+            (ExportFormat::Sqlite, Some(profile)) => Writer::Sqlite(to_sqlite::Writer::with_profile(file_path, profile).await?),
+            (ExportFormat::Ndjson, profile) => {
+                let writer = if is_stdout(file_path) {
+                    to_ndjson::Writer::stdout(gzip)
+                } else {
+                    to_ndjson::Writer::new(file_path, gzip).await?
+                };
+                Writer::Ndjson(match profile {
+                    None => writer,
+                    Some(profile) => writer.with_profile(profile),
+                })
+            },
+            (ExportFormat::Xlsx, None) => {
+                let lang = match language {
+                    Language::Ru => to_xlsx::Lang::Ru,
+                    Language::En => to_xlsx::Lang::En,
+                };
+                Writer::Xlsx(Box::new(to_xlsx::Writer::new(file_path, lang, "records").await?))
+            },
+            (ExportFormat::Xlsx, Some(profile)) => Writer::Xlsx(Box::new(to_xlsx::Writer::with_profile(file_path, "records", profile).await?)),
+            // таблица cards - постоянная схема для upsert, профиль к ней не применяется
+            (ExportFormat::Postgres, Some(_)) => bail!("column profiles are not supported for postgres"),
+            (ExportFormat::Postgres, None) => match postgres_url {
+                Some(url) => Writer::Postgres(to_postgres::Writer::new(url)?),
+                None => bail!("postgres_url is not set in the config"),
+            },
+        })
+    }
+    // Из хранилища берутся версии карточки: sqlite выгружает историю цены
+    pub async fn write(&mut self, record: &cards::Record, store: &Arc<dyn cards::CardStore>) -> Result<()> {
+        match self {
+            Writer::Csv(writer) => writer.write_record(record),
+            Writer::Parquet(writer) => writer.write(record),
+            Writer::Ndjson(writer) => writer.write(record),
+            Writer::Xlsx(writer) => writer.write(0, record),
+            Writer::Postgres(writer) => writer.write(record),
+            Writer::Sqlite(writer) => {
+                writer.write(record)?;
+                if let Some(id) = record.id {
+                    let store = store.clone();
+                    let versions = tokio::task::spawn_blocking(move || store.versions(id)).await??;
+                    writer.write_versions(id, &versions)?;
+                }
+                Ok(())
+            },
+        }
+    }
+    // Лист для отбора в той же книге (xlsx); None - отбор выгружается в отдельный файл
+    pub fn add_sheet(&mut self, name: &str) -> Result<Option<usize>> {
+        match self {
+            Writer::Xlsx(writer) => Ok(Some(writer.add_sheet(name)?)),
+            _ => Ok(None),
+        }
+    }
+    // Объявления не из списков идентификаторов ids - неактивные (postgres);
+    // Some - число ставших неактивными, None - формат неактивность не хранит
+    pub fn mark_inactive(&mut self, ids: &HashSet<u64>) -> Result<Option<u64>> {
+        match self {
+            Writer::Postgres(writer) => Ok(Some(writer.mark_inactive(ids)?)),
+            _ => Ok(None),
+        }
+    }
+    pub fn write_sheet(&mut self, sheet: usize, record: &cards::Record) -> Result<()> {
+        match self {
+            Writer::Xlsx(writer) => writer.write(sheet, record),
+            _ => bail!("sheets are supported for xlsx only"),
+        }
+    }
+    pub fn finish(self) -> Result<()> {
+        match self {
+            Writer::Csv(writer) => writer.finish(),
+            Writer::Parquet(writer) => writer.finish(),
+            Writer::Sqlite(writer) => writer.finish(),
+            Writer::Ndjson(writer) => writer.finish(),
+            Writer::Xlsx(writer) => writer.finish(),
+            Writer::Postgres(writer) => writer.finish(),
+        }
+    }
+}
+
+// Путь "-" - стандартный вывод
+pub fn is_stdout(file_path: &Path) -> bool {
+    file_path == Path::new("-")
+}
+
+// Куда записана выгрузка, для сообщений (строка подключения postgres не выводится: в ней может быть пароль)
+pub fn target(format: ExportFormat, file_path: &Path) -> String {
+    match format {
+        ExportFormat::Postgres => "postgres (postgres_url)".to_owned(),
+        _ => format!("{:?}", file_path),
+    }
+}
+
+// Куда выгружается сохраненный отбор: отдельный файл или лист книги xlsx основной выгрузки
+pub enum Search {
+    File(Box<Writer>),
+    Sheet(usize),
+}
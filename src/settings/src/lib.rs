@@ -7,6 +7,7 @@ use anyhow::{Result, Error, bail, anyhow};
 // use std::env;
 use config::{ConfigError, Config, File, Environment};
 use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
 // use std::sync::RwLock;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -24,6 +25,46 @@ pub struct Settings {
     pub thread_limit_file: usize,
     pub diap_fresh_duration_mins: i64,
     pub items_per_page: usize,
+    // Нестрогий разбор карточек: неизвестные ключи собираются в extra, а не приводят к ошибке
+    #[serde(default)]
+    pub lenient: bool,
+    // Описание разбора firebaseParams карточки вместо встроенного (см. cards/firebase_params.toml)
+    #[serde(default)]
+    pub firebase_params: Option<String>,
+    // Число одновременных загрузок изображений (этап images)
+    #[serde(default = "default_thread_limit_images")]
+    pub thread_limit_images: usize,
+    // Где хранятся карточки: files - файл на карточку, pack - упакованные сегменты, sqlite - база SQLite
+    #[serde(default)]
+    pub card_storage: CardStorage,
+    // Сохраненные отборы: имя -> выражение фильтра (см. crate filter)
+    #[serde(default)]
+    pub searches: BTreeMap<String, String>,
+    // Порог оценки сходства (0..1), с которого пара объявлений считается повтором (см. crate dedup)
+    #[serde(default = "default_dedup_threshold")]
+    pub dedup_threshold: f64,
+    // Формат выгрузки записей (records.<ext>, records_<search>.<ext>, scan export)
+    #[serde(default)]
+    pub export_format: ExportFormat,
+    // Язык заголовков столбцов выгрузки xlsx
+    #[serde(default)]
+    pub export_language: Language,
+    // Наборы столбцов выгрузки: имя -> столбцы (поле, заголовок, формат значения; см. crate columns)
+    #[serde(default)]
+    pub export_profiles: BTreeMap<String, Vec<columns::Spec>>,
+    // Набор из export_profiles для всех выгрузок (для scan export переопределяется ключом --profile);
+    // без него выгружаются все поля записи
+    #[serde(default)]
+    pub export_profile: Option<String>,
+    // Строка подключения к PostgreSQL для export_format = "postgres"
+    #[serde(default)]
+    pub postgres_url: Option<String>,
+    // Источники дополнения записей перед выгрузкой, в порядке применения (см. crate enrich)
+    #[serde(default = "default_enrichers")]
+    pub enrichers: Vec<Enricher>,
+    // Порог уверенности (0..1) сопоставления карточки без ссылки на автокаталог с модификацией (см. crate enrich)
+    #[serde(default = "default_autocatalog_min_confidence")]
+    pub autocatalog_min_confidence: f64,
 
     // pub sources: Vec<String>,
     // pub proxy_timeout_secs: u64,
@@ -41,6 +82,89 @@ pub struct Settings {
 //     pub static ref SINGLETON: RwLock<Option<Settings>> = RwLock::new(None);
 // }
 
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CardStorage {
+    #[default]
+    Files,
+    Pack,
+    Sqlite,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Parquet,
+    Sqlite,
+    Ndjson,
+    Xlsx,
+    Postgres,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Parquet => "parquet",
+            ExportFormat::Sqlite => "sqlite",
+            ExportFormat::Ndjson => "ndjson",
+            ExportFormat::Xlsx => "xlsx",
+            ExportFormat::Postgres => "postgres",
+        }
+    }
+}
+
+impl std::str::FromStr for ExportFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "csv" => ExportFormat::Csv,
+            "parquet" => ExportFormat::Parquet,
+            "sqlite" => ExportFormat::Sqlite,
+            "ndjson" => ExportFormat::Ndjson,
+            "xlsx" => ExportFormat::Xlsx,
+            "postgres" => ExportFormat::Postgres,
+            _ => bail!("unknown export format {:?}, expected csv, parquet, sqlite, ndjson, xlsx or postgres", s),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Language {
+    #[default]
+    Ru,
+    En,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Enricher {
+    Autocatalog,
+    Units,
+    Lifecycle,
+    Duplicates,
+}
+
+fn default_enrichers() -> Vec<Enricher> {
+    vec![Enricher::Autocatalog, Enricher::Units, Enricher::Lifecycle, Enricher::Duplicates]
+}
+
+fn default_autocatalog_min_confidence() -> f64 {
+    0.7
+}
+
+fn default_thread_limit_images() -> usize {
+    10
+}
+
+fn default_dedup_threshold() -> f64 {
+    0.8
+}
+
 use std::path::Path;
 impl Settings {
     pub fn new(source: &Path) -> Result<Self, ConfigError> {
@@ -4,28 +4,51 @@ use log::{error, warn, info, debug, trace};
 #[allow(unused_imports)]
 use anyhow::{anyhow, bail, Result, Error, Context};
 
+use serde::Serialize;
 use tokio::fs;
 use std::path::Path;
 
 // ============================================================================
 // ============================================================================
 
-pub struct Arg<'a> {
-    pub file_path: &'a Path,
-    pub records: &'a collect::Records,
+// Построчная запись: записи приходят по одной (например, из collect::stream) и в памяти не копятся
+pub struct Writer {
+    wtr: csv::Writer<std::fs::File>,
+    profile: Option<columns::Profile>,
 }
 
-pub async fn write<'a>(arg: Arg<'a>) -> Result<()> {
-    if let Some(dir_path) = arg.file_path.parent() {
-        fs::create_dir_all(dir_path).await?;
+impl Writer {
+    pub async fn new(file_path: &Path) -> Result<Self> {
+        if let Some(dir_path) = file_path.parent() {
+            fs::create_dir_all(dir_path).await?;
+        }
+        let wtr = csv::Writer::from_path(file_path).context(format!("{:?}", file_path))?;
+        Ok(Self { wtr, profile: None })
     }
-    let mut wtr = csv::Writer::from_path(arg.file_path)?;
-
-    for record in &arg.records.0 {
-        wtr.serialize(record)?;
+    // Столбцы и заголовки - из профиля (см. columns::Profile), а не все поля cards::Record
+    pub async fn with_profile(file_path: &Path, profile: columns::Profile) -> Result<Self> {
+        let mut ret = Self::new(file_path).await?;
+        ret.wtr.write_record(profile.labels())?;
+        ret.profile = Some(profile);
+        Ok(ret)
+    }
+    pub fn write<T: Serialize>(&mut self, record: &T) -> Result<()> {
+        self.wtr.serialize(record)?;
+        Ok(())
+    }
+    pub fn write_record(&mut self, record: &cards::Record) -> Result<()> {
+        match &self.profile {
+            None => self.write(record),
+            Some(profile) => {
+                self.wtr.write_record(profile.row(record).iter().map(|cell| cell.to_string()))?;
+                Ok(())
+            },
+        }
+    }
+    pub fn finish(mut self) -> Result<()> {
+        self.wtr.flush()?;
+        Ok(())
     }
-    wtr.flush()?;
-    Ok(())
 }
 
 // ============================================================================
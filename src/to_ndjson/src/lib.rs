@@ -0,0 +1,175 @@
+
+#[allow(unused_imports)]
+use log::{error, warn, info, debug, trace};
+#[allow(unused_imports)]
+use anyhow::{anyhow, bail, Result, Error, Context};
+
+use flate2::{write::GzEncoder, Compression};
+use serde_json::Value;
+use std::io::{LineWriter, Write};
+use std::path::Path;
+use tokio::fs;
+
+// ============================================================================
+// ============================================================================
+
+// Запись в строку NDJSON: имена полей - serde-имена cards::Record (они же у record::card::Record),
+// но images - массив ссылок, а extra - объект, а не строки, как в csv
+pub fn to_value(record: &cards::Record) -> Result<Value> {
+    let mut ret = serde_json::to_value(record)?;
+    if let Value::Object(map) = &mut ret {
+        if let Some(images) = &record.images {
+            map.insert("images".to_owned(), Value::Array(images.0.iter().map(|url| Value::String(url.to_owned())).collect()));
+        }
+        if let Some(extra) = &record.extra {
+            map.insert("extra".to_owned(), Value::Object(extra.0.clone().into_iter().collect()));
+        }
+    }
+    Ok(ret)
+}
+
+fn cell_value(cell: columns::Cell) -> Value {
+    match cell {
+        columns::Cell::Null => Value::Null,
+        columns::Cell::Str(val) => Value::String(val),
+        columns::Cell::Int(val) => Value::from(val),
+        columns::Cell::Float(val) => Value::from(val),
+        columns::Cell::Bool(val) => Value::Bool(val),
+        cell @ columns::Cell::Time(_) | cell @ columns::Cell::Vocab(_) => Value::String(cell.to_string()),
+    }
+}
+
+// Строка по профилю: ключи - заголовки столбцов в порядке профиля
+// (объект собирается вручную: serde_json::Map упорядочивает ключи по алфавиту)
+fn to_line(profile: &columns::Profile, record: &cards::Record) -> Result<Vec<u8>> {
+    let mut ret = b"{".to_vec();
+    for (i, (label, cell)) in profile.labels().into_iter().zip(profile.row(record)).enumerate() {
+        if i > 0 {
+            ret.push(b',');
+        }
+        serde_json::to_writer(&mut ret, label)?;
+        ret.push(b':');
+        serde_json::to_writer(&mut ret, &cell_value(cell))?;
+    }
+    ret.push(b'}');
+    Ok(ret)
+}
+
+enum Inner {
+    Plain(Box<dyn Write + Send>),
+    Gzip(GzEncoder<Box<dyn Write + Send>>),
+}
+
+// Построчная запись, как у to_csv::Writer. Без сжатия каждая строка сразу попадает в файл,
+// так что файл можно читать по мере записи (tail -f)
+pub struct Writer {
+    inner: Inner,
+    profile: Option<columns::Profile>,
+}
+
+impl Writer {
+    pub async fn new(file_path: &Path, gzip: bool) -> Result<Self> {
+        if let Some(dir_path) = file_path.parent() {
+            fs::create_dir_all(dir_path).await?;
+        }
+        let file = std::fs::File::create(file_path).context(format!("{:?}", file_path))?;
+        Ok(Self::with(Box::new(LineWriter::new(file)), gzip))
+    }
+    pub fn stdout(gzip: bool) -> Self {
+        Self::with(Box::new(std::io::stdout()), gzip)
+    }
+    fn with(wtr: Box<dyn Write + Send>, gzip: bool) -> Self {
+        let inner = if gzip {
+            Inner::Gzip(GzEncoder::new(wtr, Compression::default()))
+        } else {
+            Inner::Plain(wtr)
+        };
+        Self { inner, profile: None }
+    }
+    // Поля, их порядок и ключи - из профиля (см. columns::Profile), а не to_value
+    pub fn with_profile(mut self, profile: columns::Profile) -> Self {
+        self.profile = Some(profile);
+        self
+    }
+    pub fn write(&mut self, record: &cards::Record) -> Result<()> {
+        let mut line = match &self.profile {
+            None => serde_json::to_vec(&to_value(record)?)?,
+            Some(profile) => to_line(profile, record)?,
+        };
+        line.push(b'\n');
+        match &mut self.inner {
+            Inner::Plain(wtr) => wtr.write_all(&line)?,
+            Inner::Gzip(wtr) => wtr.write_all(&line)?,
+        }
+        Ok(())
+    }
+    pub fn finish(self) -> Result<()> {
+        match self.inner {
+            Inner::Plain(mut wtr) => wtr.flush()?,
+            Inner::Gzip(wtr) => wtr.finish()?.flush()?,
+        }
+        Ok(())
+    }
+}
+
+// ============================================================================
+// ============================================================================
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use log::{error, warn, info, debug, trace};
+    use super::*;
+
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    #[tokio::test]
+    async fn test_to_ndjson() -> Result<()> {
+        test_helper::init();
+
+        let json = json::Json::from_file("../cards/test_data/card.json").await?;
+        let mut record = cards::Fetched::parse_json(&json, false)?;
+        record.images = Some(cards::Images(vec!["https://1.jpg".to_owned(), "https://2.jpg".to_owned()]));
+        let mut extra = cards::Extra::new();
+        extra.0.insert("firebaseParams.some_key".to_owned(), Value::from(1));
+        record.extra = Some(extra);
+
+        for (file_name, gzip) in &[("records.ndjson", false), ("records.ndjson.gz", true)] {
+            let file_path = Path::new("out_test").join(file_name);
+            let mut writer = Writer::new(&file_path, *gzip).await?;
+            writer.write(&record)?;
+            writer.write(&record)?;
+            writer.finish()?;
+
+            let mut s = String::new();
+            if *gzip {
+                GzDecoder::new(std::fs::File::open(&file_path)?).read_to_string(&mut s)?;
+            } else {
+                std::fs::File::open(&file_path)?.read_to_string(&mut s)?;
+            }
+            let lines: Vec<&str> = s.lines().collect();
+            assert_eq!(lines.len(), 2);
+            let value: Value = serde_json::from_str(lines[1])?;
+            assert_eq!(value["brand"], "KIA");
+            assert!(value.get("autoCatalogUrl").is_some());
+            assert_eq!(value["autocatalogId"], Value::Null);
+            assert_eq!(value["images"][1], "https://2.jpg");
+            assert_eq!(value["extra"]["firebaseParams.some_key"], 1);
+            assert!(value.get("Привод").is_some());
+        }
+
+        let profile = columns::Profile::new(&[
+            columns::Spec { label: Some("Марка".to_owned()), ..columns::Spec::new("brand") },
+            columns::Spec::new("id"),
+        ])?;
+        let file_path = Path::new("out_test/records_profile.ndjson");
+        let mut writer = Writer::new(file_path, false).await?.with_profile(profile);
+        writer.write(&record)?;
+        writer.finish()?;
+        assert_eq!(std::fs::read_to_string(file_path)?, "{\"Марка\":\"KIA\",\"id\":1767797249}\n");
+
+        Ok(())
+    }
+}
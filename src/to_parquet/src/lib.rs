@@ -0,0 +1,250 @@
+
+#[allow(unused_imports)]
+use log::{error, warn, info, debug, trace};
+#[allow(unused_imports)]
+use anyhow::{anyhow, bail, Result, Error, Context};
+
+use columns::{Cell, Kind};
+use parquet::{
+    basic::{LogicalType, Repetition, TimeUnit, Type as PhysicalType},
+    data_type::{BoolType, ByteArray, ByteArrayType, DoubleType, Int64Type},
+    file::{properties::WriterProperties, writer::SerializedFileWriter},
+    format::MilliSeconds,
+    schema::types::{Type as SchemaType, TypePtr},
+};
+use std::path::Path;
+use std::sync::Arc;
+use tokio::fs;
+
+// ============================================================================
+// ============================================================================
+
+// Число записей в группе строк: столько записей (по столбцам) держится в памяти до записи в файл
+pub const ROW_GROUP_SIZE: usize = 10_000;
+
+// Схема строится построителем, а не разбором текста: заголовки профиля (см. columns::Profile)
+// могут быть не идентификаторами ("Цена, руб")
+fn schema(columns: &[(&str, Kind)]) -> Result<TypePtr> {
+    let mut fields = Vec::new();
+    for (name, kind) in columns.iter() {
+        let (physical_type, logical_type) = match kind {
+            Kind::Str => (PhysicalType::BYTE_ARRAY, Some(LogicalType::String)),
+            Kind::Int => (PhysicalType::INT64, None),
+            Kind::Float | Kind::NumStr => (PhysicalType::DOUBLE, None),
+            Kind::Bool => (PhysicalType::BOOLEAN, None),
+            Kind::Time => (PhysicalType::INT64, Some(LogicalType::Timestamp {
+                is_adjusted_to_u_t_c: true,
+                unit: TimeUnit::MILLIS(MilliSeconds {}),
+            })),
+        };
+        let field = SchemaType::primitive_type_builder(name, physical_type)
+            .with_repetition(Repetition::OPTIONAL)
+            .with_logical_type(logical_type)
+            .build()
+            .context(format!("column {:?}", name))?;
+        fields.push(Arc::new(field));
+    }
+    Ok(Arc::new(SchemaType::group_type_builder("record").with_fields(fields).build()?))
+}
+
+// ============================================================================
+
+enum Values {
+    Str(Vec<ByteArray>),
+    Int(Vec<i64>),
+    Float(Vec<f64>),
+    Bool(Vec<bool>),
+}
+
+// Значения столбца текущей группы строк; def_levels - 1 для значения, 0 для null
+struct Column {
+    kind: Kind,
+    values: Values,
+    def_levels: Vec<i16>,
+}
+
+impl Column {
+    fn new(kind: Kind) -> Self {
+        let values = match kind {
+            Kind::Str => Values::Str(Vec::new()),
+            Kind::Int | Kind::Time => Values::Int(Vec::new()),
+            Kind::Float | Kind::NumStr => Values::Float(Vec::new()),
+            Kind::Bool => Values::Bool(Vec::new()),
+        };
+        Self { kind, values, def_levels: Vec::new() }
+    }
+    // Число автокаталога текстом (Kind::NumStr) пишется числом, если разбирается, иначе null
+    fn push(&mut self, cell: &Cell) {
+        let is_some = match (&mut self.values, self.kind, cell) {
+            (_, _, Cell::Null) => false,
+            (Values::Str(values), _, Cell::Str(val)) => { values.push(ByteArray::from(val.as_str())); true },
+            (Values::Str(values), _, Cell::Vocab(val)) => { values.push(ByteArray::from(val.code())); true },
+            (Values::Float(values), Kind::NumStr, Cell::Str(val)) => match columns::parse_num(val) {
+                Some(val) => { values.push(val); true },
+                None => false,
+            },
+            (Values::Int(values), _, Cell::Int(val)) => { values.push(*val); true },
+            (Values::Int(values), _, Cell::Time(val)) => { values.push(val.timestamp_millis()); true },
+            (Values::Float(values), _, Cell::Float(val)) => { values.push(*val); true },
+            (Values::Bool(values), _, Cell::Bool(val)) => { values.push(*val); true },
+            (_, kind, _) => unreachable!("value of other kind for {:?} column", kind),
+        };
+        self.def_levels.push(if is_some { 1 } else { 0 });
+    }
+    fn clear(&mut self) {
+        match &mut self.values {
+            Values::Str(values) => values.clear(),
+            Values::Int(values) => values.clear(),
+            Values::Float(values) => values.clear(),
+            Values::Bool(values) => values.clear(),
+        }
+        self.def_levels.clear();
+    }
+}
+
+// Построчная запись, как у to_csv::Writer: записи копятся по столбцам и сбрасываются
+// в файл группами строк по row_group_size, так что память не зависит от числа записей
+pub struct Writer {
+    wtr: SerializedFileWriter<std::fs::File>,
+    columns: Vec<Column>,
+    // без профиля - поля записи по порядку столбцов (индексы в columns::FIELDS)
+    fields: Vec<usize>,
+    profile: Option<columns::Profile>,
+    row_group_size: usize,
+    row_qt: usize,
+}
+
+impl Writer {
+    pub async fn new(file_path: &Path) -> Result<Self> {
+        Self::with_row_group_size(file_path, ROW_GROUP_SIZE).await
+    }
+    // Столбцы - поля записи без вложенных (см. columns::flat)
+    pub async fn with_row_group_size(file_path: &Path, row_group_size: usize) -> Result<Self> {
+        let columns: Vec<(&str, Kind)> = columns::flat().map(|(_, name, kind)| (name, kind)).collect();
+        let fields = columns::flat().map(|(field, _, _)| field).collect();
+        Self::create(file_path, &columns, fields, None, row_group_size).await
+    }
+    // Столбцы, их порядок и имена - из профиля (см. columns::Profile)
+    pub async fn with_profile(file_path: &Path, profile: columns::Profile) -> Result<Self> {
+        let labels: Vec<String> = profile.labels().iter().map(|label| label.to_string()).collect();
+        let columns: Vec<(&str, Kind)> = labels.iter().zip(profile.columns.iter())
+            .map(|(label, column)| (label.as_str(), column.kind))
+            .collect();
+        Self::create(file_path, &columns, Vec::new(), Some(profile), ROW_GROUP_SIZE).await
+    }
+    async fn create(file_path: &Path, columns: &[(&str, Kind)], fields: Vec<usize>, profile: Option<columns::Profile>, row_group_size: usize) -> Result<Self> {
+        if let Some(dir_path) = file_path.parent() {
+            fs::create_dir_all(dir_path).await?;
+        }
+        let props = Arc::new(WriterProperties::builder().build());
+        let file = std::fs::File::create(file_path).context(format!("{:?}", file_path))?;
+        let wtr = SerializedFileWriter::new(file, schema(columns)?, props)?;
+        let columns = columns.iter().map(|(_, kind)| Column::new(*kind)).collect();
+        Ok(Self { wtr, columns, fields, profile, row_group_size, row_qt: 0 })
+    }
+    pub fn write(&mut self, record: &cards::Record) -> Result<()> {
+        match &self.profile {
+            None => for (column, field) in self.columns.iter_mut().zip(self.fields.iter()) {
+                column.push(&columns::get(record, *field));
+            },
+            Some(profile) => for (column, cell) in self.columns.iter_mut().zip(profile.row(record).iter()) {
+                column.push(cell);
+            },
+        }
+        self.row_qt += 1;
+        if self.row_qt >= self.row_group_size {
+            self.flush()?;
+        }
+        Ok(())
+    }
+    pub fn finish(mut self) -> Result<()> {
+        self.flush()?;
+        self.wtr.close()?;
+        Ok(())
+    }
+    fn flush(&mut self) -> Result<()> {
+        if self.row_qt == 0 {
+            return Ok(());
+        }
+        let mut row_group = self.wtr.next_row_group()?;
+        let mut columns = self.columns.iter_mut();
+        while let Some(mut col) = row_group.next_column()? {
+            let column = columns.next().ok_or_else(|| anyhow!("more columns in schema than in writer"))?;
+            let def_levels = Some(column.def_levels.as_slice());
+            match &column.values {
+                Values::Str(values) => { col.typed::<ByteArrayType>().write_batch(values, def_levels, None)?; },
+                Values::Int(values) => { col.typed::<Int64Type>().write_batch(values, def_levels, None)?; },
+                Values::Float(values) => { col.typed::<DoubleType>().write_batch(values, def_levels, None)?; },
+                Values::Bool(values) => { col.typed::<BoolType>().write_batch(values, def_levels, None)?; },
+            }
+            col.close()?;
+            column.clear();
+        }
+        row_group.close()?;
+        self.row_qt = 0;
+        Ok(())
+    }
+}
+
+// ============================================================================
+// ============================================================================
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use log::{error, warn, info, debug, trace};
+    use super::*;
+
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    #[tokio::test]
+    async fn test_to_parquet() -> Result<()> {
+        test_helper::init();
+
+        let json = json::Json::from_file("../cards/test_data/card.json").await?;
+        let file_path = Path::new("out_test/records.parquet");
+        let mut writer = Writer::with_row_group_size(file_path, 2).await?;
+        for i in 0..3 {
+            let mut record = cards::Fetched::parse_json(&json, false)?;
+            record.item_price = Some(1_000_000 + i);
+            record.autocatalog_engine_power = Some("150".to_owned());
+            record.autocatalog_torque = Some("-".to_owned());
+            writer.write(&record)?;
+        }
+        writer.finish()?;
+
+        let reader = SerializedFileReader::new(std::fs::File::open(file_path)?)?;
+        let metadata = reader.metadata();
+        assert_eq!(metadata.file_metadata().num_rows(), 3);
+        assert_eq!(metadata.num_row_groups(), 2);
+
+        let column = |name: &str| columns::flat().position(|(_, item, _)| item == name).unwrap();
+        let rows = reader.get_row_iter(None)?.collect::<std::result::Result<Vec<_>, _>>()?;
+        use parquet::record::RowAccessor;
+        assert_eq!(rows[2].get_long(column("item_price"))?, 1_000_002);
+        assert_eq!(rows[0].get_string(column("brand"))?, "KIA");
+        assert_eq!(rows[0].get_double(column("autocatalog_engine_power"))?, 150.0);
+        assert!(rows[0].get_double(column("autocatalog_torque")).is_err());
+        assert!(rows[0].get_long(column("autocatalog_id")).is_err());
+
+        let profile = columns::Profile::new(&[
+            columns::Spec { label: Some("Цена, руб".to_owned()), ..columns::Spec::new("item_price") },
+            columns::Spec::new("brand"),
+            columns::Spec { decimal_separator: Some(",".to_owned()), ..columns::Spec::new("views_total") },
+        ])?;
+        let file_path = Path::new("out_test/records_profile.parquet");
+        let mut writer = Writer::with_profile(file_path, profile).await?;
+        writer.write(&cards::Fetched::parse_json(&json, false)?)?;
+        writer.finish()?;
+        let reader = SerializedFileReader::new(std::fs::File::open(file_path)?)?;
+        let schema = reader.metadata().file_metadata().schema_descr();
+        assert_eq!(schema.num_columns(), 3);
+        assert_eq!(schema.column(0).name(), "Цена, руб");
+        let rows = reader.get_row_iter(None)?.collect::<std::result::Result<Vec<_>, _>>()?;
+        assert_eq!(rows[0].get_string(1)?, "KIA");
+        assert!(rows[0].get_string(2).is_ok());
+
+        Ok(())
+    }
+}
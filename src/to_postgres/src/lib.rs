@@ -0,0 +1,306 @@
+
+#[allow(unused_imports)]
+use log::{error, warn, info, debug, trace};
+#[allow(unused_imports)]
+use anyhow::{anyhow, bail, Result, Error, Context};
+
+use chrono::{DateTime, Utc};
+use columns::Cell;
+use postgres::{types::ToSql, Client, NoTls, Statement};
+use std::collections::HashSet;
+use std::sync::mpsc;
+use std::thread;
+
+// ============================================================================
+// ============================================================================
+
+// Выгрузка в PostgreSQL: в отличие от to_sqlite база не пересоздается, а пополняется от запуска к запуску:
+//   cards         - объявления, ключ id; запись заменяет прежнюю (upsert), active - есть ли id
+//                   хоть в одном из списков идентификаторов (см. mark_inactive)
+//   price_history - изменения цены: строка, когда цена объявления отличается от прежней в cards
+// Таблицы создаются, если их нет; вся выгрузка - одна транзакция.
+// Клиент postgres синхронный и держит внутри свой runtime (tokio 1), поэтому запросы выполняются
+// не в потоке, который вызывает Writer (там runtime tokio 0.2), а в отдельном потоке (см. run)
+
+type Param = Box<dyn ToSql + Sync>;
+
+// Тип изменения относится к инкрементальной выгрузке файлов, а в cards запись всегда полная (upsert)
+const SKIPPED: &[&str] = &["change_type"];
+
+// Столбцы таблицы cards: поле (индекс в columns::FIELDS), имя, тип PostgreSQL
+fn table_columns() -> Vec<(usize, &'static str, &'static str)> {
+    columns::flat()
+        .filter(|(_, name, _)| !SKIPPED.contains(name))
+        .map(|(field, name, kind)| (field, name, sql_type(kind)))
+        .collect()
+}
+
+// В PostgreSQL нет беззнаковых целых: все целые - BIGINT
+fn sql_type(kind: columns::Kind) -> &'static str {
+    match kind {
+        columns::Kind::Str | columns::Kind::NumStr => "TEXT",
+        columns::Kind::Int => "BIGINT",
+        columns::Kind::Float => "FLOAT8",
+        columns::Kind::Bool => "BOOLEAN",
+        columns::Kind::Time => "TIMESTAMPTZ",
+    }
+}
+
+// Параметр типа столбца: null тоже типизирован, иначе PostgreSQL не примет его для BIGINT или TIMESTAMPTZ
+fn param(kind: columns::Kind, cell: Cell) -> Param {
+    match (kind, cell) {
+        (_, Cell::Str(val)) => Box::new(Some(val)),
+        (_, Cell::Vocab(val)) => Box::new(Some(val.code().to_owned())),
+        (_, Cell::Int(val)) => Box::new(Some(val)),
+        (_, Cell::Float(val)) => Box::new(Some(val)),
+        (_, Cell::Bool(val)) => Box::new(Some(val)),
+        (_, Cell::Time(val)) => Box::new(Some(val)),
+        (columns::Kind::Str, Cell::Null) | (columns::Kind::NumStr, Cell::Null) => Box::new(None::<String>),
+        (columns::Kind::Int, Cell::Null) => Box::new(None::<i64>),
+        (columns::Kind::Float, Cell::Null) => Box::new(None::<f64>),
+        (columns::Kind::Bool, Cell::Null) => Box::new(None::<bool>),
+        (columns::Kind::Time, Cell::Null) => Box::new(None::<DateTime<Utc>>),
+    }
+}
+
+fn create_tables() -> String {
+    let columns: Vec<String> = table_columns().iter()
+        .map(|(_, name, type_)| format!("{} {}{}", name, type_, if *name == "id" { " PRIMARY KEY" } else { "" }))
+        .collect();
+    // столбцы, добавленные в cards::Record после создания таблицы
+    let alters: Vec<String> = table_columns().iter()
+        .map(|(_, name, type_)| format!("ALTER TABLE cards ADD COLUMN IF NOT EXISTS {} {};", name, type_))
+        .collect();
+    format!("
+        CREATE TABLE IF NOT EXISTS cards (
+            {},
+            active BOOLEAN NOT NULL DEFAULT TRUE,
+            updated_at TIMESTAMPTZ NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS price_history (
+            id BIGINT NOT NULL,
+            changed_at TIMESTAMPTZ NOT NULL,
+            item_price BIGINT NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS cards_brand ON cards (brand, name);
+        CREATE INDEX IF NOT EXISTS cards_active ON cards (active);
+        CREATE INDEX IF NOT EXISTS price_history_id ON price_history (id);
+        {}
+    ", columns.join(",\n            "), alters.join("\n        "))
+}
+
+// $1..$n - столбцы (см. table_columns), $n+1 - updated_at
+fn upsert() -> String {
+    let names: Vec<&str> = table_columns().iter().map(|(_, name, _)| *name).collect();
+    let params: Vec<String> = (1..=names.len() + 1).map(|i| format!("${}", i)).collect();
+    let updates: Vec<String> = names.iter().skip(1).chain(["updated_at"].iter())
+        .map(|name| format!("{} = EXCLUDED.{}", name, name))
+        .collect();
+    format!(
+        "INSERT INTO cards ({}, updated_at) VALUES ({}) ON CONFLICT (id) DO UPDATE SET {}",
+        names.join(", "), params.join(", "), updates.join(", "),
+    )
+}
+
+// Строка истории, если объявления еще нет или его цена в cards другая; выполняется до upsert
+const PRICE_CHANGE: &str = "
+    INSERT INTO price_history (id, changed_at, item_price)
+    SELECT $1::BIGINT, $2::TIMESTAMPTZ, $3::BIGINT
+    WHERE NOT EXISTS (SELECT 1 FROM cards WHERE id = $1::BIGINT AND item_price IS NOT DISTINCT FROM $3::BIGINT)
+";
+
+// ============================================================================
+
+// Соединение с открытой транзакцией; живет в потоке run
+struct Connection {
+    client: Client,
+    columns: Vec<(usize, &'static str, &'static str)>,
+    upsert: Statement,
+    price_change: Statement,
+    started_at: DateTime<Utc>,
+}
+
+impl Connection {
+    fn new(url: &str) -> Result<Self> {
+        let mut client = Client::connect(url, NoTls).context("postgres connect")?;
+        client.batch_execute(&create_tables())?;
+        client.batch_execute("BEGIN")?;
+        let upsert = client.prepare(&upsert())?;
+        let price_change = client.prepare(PRICE_CHANGE)?;
+        Ok(Self { client, columns: table_columns(), upsert, price_change, started_at: Utc::now() })
+    }
+    fn write(&mut self, id: i64, item_price: Option<i64>, cells: Vec<Cell>) -> Result<()> {
+        if let Some(item_price) = item_price {
+            self.client.execute(&self.price_change, &[&id, &self.started_at, &item_price])?;
+        }
+        let mut values: Vec<Param> = self.columns.iter().zip(cells)
+            .map(|((field, _, _), cell)| param(columns::FIELDS[*field].1, cell))
+            .collect();
+        values.push(Box::new(self.started_at));
+        let params: Vec<&(dyn ToSql + Sync)> = values.iter().map(|value| value.as_ref()).collect();
+        self.client.execute(&self.upsert, &params)?;
+        Ok(())
+    }
+    fn mark_inactive(&mut self, ids: &[i64]) -> Result<u64> {
+        let ret = self.client.execute("UPDATE cards SET active = FALSE WHERE active AND NOT id = ANY($1)", &[&ids])?;
+        self.client.execute("UPDATE cards SET active = TRUE WHERE NOT active AND id = ANY($1)", &[&ids])?;
+        Ok(ret)
+    }
+    fn finish(mut self) -> Result<()> {
+        self.client.batch_execute("COMMIT")?;
+        Ok(())
+    }
+}
+
+enum Command {
+    // значения столбцов (см. table_columns) готовятся в потоке Writer: cards::Record не передается
+    Write { id: i64, item_price: Option<i64>, cells: Vec<Cell> },
+    MarkInactive(Vec<i64>, mpsc::Sender<Result<u64>>),
+    Finish,
+}
+
+// Столько записей может ждать в очереди потока run
+const QUEUE_SIZE: usize = 1_000;
+
+// Первая ошибка завершает поток; Writer узнает ее при следующей команде. Writer удален без finish -
+// очередь закрыта, транзакция откатывается вместе с соединением
+fn run(mut conn: Connection, rx: mpsc::Receiver<Command>) -> Result<()> {
+    for command in rx.iter() {
+        match command {
+            Command::Write { id, item_price, cells } => conn.write(id, item_price, cells)?,
+            Command::MarkInactive(ids, ret_tx) => {
+                let ret = conn.mark_inactive(&ids);
+                let is_err = ret.is_err();
+                let _ = ret_tx.send(ret);
+                if is_err {
+                    return Ok(());
+                }
+            },
+            Command::Finish => return conn.finish(),
+        }
+    }
+    Ok(())
+}
+
+// Построчная запись, как у to_csv::Writer
+pub struct Writer {
+    tx: mpsc::SyncSender<Command>,
+    columns: Vec<(usize, &'static str, &'static str)>,
+    thread: Option<thread::JoinHandle<Result<()>>>,
+}
+
+impl Writer {
+    // url - строка подключения: "host=localhost user=postgres dbname=scan" или "postgresql://..."
+    pub fn new(url: &str) -> Result<Self> {
+        let url = url.to_owned();
+        let (tx, rx) = mpsc::sync_channel(QUEUE_SIZE);
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let thread = thread::Builder::new().name("to_postgres".to_owned()).spawn(move || {
+            match Connection::new(&url) {
+                Ok(conn) => {
+                    let _ = ready_tx.send(Ok(()));
+                    run(conn, rx)
+                },
+                Err(err) => {
+                    let _ = ready_tx.send(Err(err));
+                    Ok(())
+                },
+            }
+        })?;
+        ready_rx.recv().map_err(|_| anyhow!("postgres writer thread failed to start"))??;
+        Ok(Self { tx, columns: table_columns(), thread: Some(thread) })
+    }
+    pub fn write(&mut self, record: &cards::Record) -> Result<()> {
+        let id = match record.id {
+            Some(id) => id as i64,
+            None => bail!("record without id can not be upserted"),
+        };
+        let cells = self.columns.iter().map(|(field, _, _)| columns::get(record, *field)).collect();
+        let command = Command::Write { id, item_price: record.item_price.map(|val| val as i64), cells };
+        self.send(command)
+    }
+    // Объявления, которых нет в ids (списки идентификаторов), помечаются неактивными (и наоборот);
+    // возвращает число объявлений, ставших неактивными
+    pub fn mark_inactive(&mut self, ids: &HashSet<u64>) -> Result<u64> {
+        let ids: Vec<i64> = ids.iter().map(|id| *id as i64).collect();
+        let (ret_tx, ret_rx) = mpsc::channel();
+        self.send(Command::MarkInactive(ids, ret_tx))?;
+        match ret_rx.recv() {
+            Ok(ret) => ret,
+            Err(_) => Err(self.stopped()),
+        }
+    }
+    pub fn finish(mut self) -> Result<()> {
+        self.send(Command::Finish)?;
+        self.join()
+    }
+    fn send(&mut self, command: Command) -> Result<()> {
+        self.tx.send(command).map_err(|_| self.stopped())
+    }
+    // Ошибка, на которой завершился поток run
+    fn stopped(&mut self) -> Error {
+        match self.join() {
+            Ok(()) => anyhow!("postgres writer is stopped"),
+            Err(err) => err,
+        }
+    }
+    fn join(&mut self) -> Result<()> {
+        match self.thread.take() {
+            Some(thread) => thread.join().map_err(|_| anyhow!("postgres writer thread panicked"))?,
+            None => Ok(()),
+        }
+    }
+}
+
+// ============================================================================
+// ============================================================================
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use log::{error, warn, info, debug, trace};
+    use super::*;
+
+    // Нужен PostgreSQL, например: docker run --rm -p 5432:5432 -e POSTGRES_PASSWORD=postgres postgres
+    // и TEST_POSTGRES_URL="host=localhost user=postgres password=postgres"; без TEST_POSTGRES_URL тест пропускается
+    #[tokio::test]
+    async fn test_to_postgres() -> Result<()> {
+        test_helper::init();
+
+        let url = match std::env::var("TEST_POSTGRES_URL") {
+            Ok(url) => url,
+            Err(_) => {
+                warn!("TEST_POSTGRES_URL is not set, test_to_postgres skipped");
+                return Ok(());
+            },
+        };
+        let mut client = Client::connect(&url, NoTls)?;
+        client.batch_execute("DROP TABLE IF EXISTS cards; DROP TABLE IF EXISTS price_history;")?;
+
+        let json = json::Json::from_file("../cards/test_data/card.json").await?;
+        for (item_price, ids) in [(900_000, vec![1, 2]), (900_000, vec![1, 2]), (850_000, vec![1])].iter() {
+            let mut writer = Writer::new(&url)?;
+            for id in 1..=2 {
+                let mut record = cards::Fetched::parse_json(&json, false)?;
+                record.id = Some(id);
+                record.item_price = Some(*item_price);
+                writer.write(&record)?;
+            }
+            writer.mark_inactive(&ids.iter().cloned().collect())?;
+            writer.finish()?;
+        }
+
+        let count = |client: &mut Client, sql: &str| -> Result<i64> { Ok(client.query_one(sql, &[])?.get(0)) };
+        assert_eq!(count(&mut client, "SELECT COUNT(*) FROM cards WHERE brand = 'KIA'")?, 2);
+        assert_eq!(count(&mut client, "SELECT COUNT(*) FROM cards WHERE item_price = 850000")?, 2);
+        assert_eq!(count(&mut client, "SELECT COUNT(*) FROM price_history WHERE id = 1")?, 2);
+        assert_eq!(count(&mut client, "SELECT COUNT(*) FROM cards WHERE NOT active")?, 1);
+        assert_eq!(count(&mut client, "SELECT id FROM cards WHERE NOT active")?, 2);
+
+        // ошибка соединения в потоке выгрузки возвращается из new
+        assert!(Writer::new("host=localhost port=1 user=postgres connect_timeout=1").is_err());
+
+        Ok(())
+    }
+}
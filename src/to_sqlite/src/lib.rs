@@ -0,0 +1,290 @@
+
+#[allow(unused_imports)]
+use log::{error, warn, info, debug, trace};
+#[allow(unused_imports)]
+use anyhow::{anyhow, bail, Result, Error, Context};
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, types::Value};
+use std::path::Path;
+use tokio::fs;
+
+// ============================================================================
+// ============================================================================
+
+// Выгрузка в базу SQLite:
+//   cards                    - объявления (без полей автокаталога и жизненного цикла), ключ id
+//   autocatalog_modifications - модификации автокаталога, ключ autocatalog_id (cards.autocatalog_id)
+//   lifecycle                - жизненный цикл объявления, ключ id
+//   price_history            - цена по сохраненным версиям карточки (см. cards::CardStore::versions)
+//   runs                     - выгрузки: время и число записей
+// С профилем (см. columns::Profile) столбцы cards - столбцы профиля, остальные таблицы - как без него
+
+// Поля жизненного цикла - в таблице lifecycle, а не cards
+const LIFECYCLE: &[&str] = &["first_seen", "last_seen", "closed_at", "days_on_market"];
+
+// Поля модификации автокаталога - в таблице autocatalog_modifications, кроме ключа autocatalog_id (он и в cards);
+// ссылка и сопоставление с автокаталогом относятся к объявлению
+fn is_autocatalog(name: &str) -> bool {
+    name.starts_with("autocatalog_") && name != "autocatalog_url" && !name.starts_with("autocatalog_match_")
+}
+
+fn is_cards(name: &str) -> bool {
+    (!is_autocatalog(name) || name == "autocatalog_id") && !LIFECYCLE.contains(&name)
+}
+
+// Столбцы таблицы: поле (индекс в columns::FIELDS), имя столбца, тип SQLite; первый - ключ
+type Columns = Vec<(usize, String, &'static str)>;
+
+fn table_columns(filter: impl Fn(&str) -> bool, column_name: fn(&str) -> &str) -> Columns {
+    columns::flat()
+        .filter(|(_, name, _)| filter(name))
+        .map(|(field, name, kind)| (field, column_name(name).to_owned(), sql_type(kind)))
+        .collect()
+}
+
+// Время - текстом RFC3339, как принято в SQLite (date(), datetime() его понимают)
+fn sql_type(kind: columns::Kind) -> &'static str {
+    match kind {
+        columns::Kind::Str | columns::Kind::NumStr | columns::Kind::Time => "TEXT",
+        columns::Kind::Int | columns::Kind::Bool => "INTEGER",
+        columns::Kind::Float => "REAL",
+    }
+}
+
+fn sql_value(cell: columns::Cell) -> Value {
+    match cell {
+        columns::Cell::Null => Value::Null,
+        columns::Cell::Str(val) => Value::Text(val),
+        columns::Cell::Vocab(val) => Value::Text(val.code().to_owned()),
+        columns::Cell::Int(val) => Value::Integer(val),
+        columns::Cell::Float(val) => Value::Real(val),
+        columns::Cell::Bool(val) => Value::Integer(if val { 1 } else { 0 }),
+        columns::Cell::Time(val) => Value::Text(val.to_rfc3339()),
+    }
+}
+
+fn values(record: &cards::Record, columns: &Columns) -> Vec<Value> {
+    columns.iter().map(|(field, _, _)| sql_value(columns::get(record, *field))).collect()
+}
+
+// В таблице autocatalog_modifications имена без префикса autocatalog_, кроме ключа
+fn autocatalog_column(name: &str) -> &str {
+    if name == "autocatalog_id" { name } else { name.trim_start_matches("autocatalog_") }
+}
+
+fn create_table(table: &str, columns: &Columns) -> String {
+    let columns: Vec<String> = columns.iter().enumerate()
+        .map(|(i, (_, name, type_))| format!("{} {}{}", name, type_, if i == 0 { " PRIMARY KEY" } else { "" }))
+        .collect();
+    format!("CREATE TABLE {} ({});\n", table, columns.join(", "))
+}
+
+fn insert(verb: &str, table: &str, columns: &Columns) -> String {
+    let names: Vec<&str> = columns.iter().map(|(_, name, _)| name.as_str()).collect();
+    let params: Vec<String> = (1..=columns.len()).map(|i| format!("?{}", i)).collect();
+    format!("{} INTO {} ({}) VALUES ({})", verb, table, names.join(", "), params.join(", "))
+}
+
+fn as_is(name: &str) -> &str {
+    name
+}
+
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn create_profile_table(table: &str, profile: &columns::Profile) -> String {
+    let columns: Vec<String> = profile.columns.iter()
+        .map(|column| format!("{} {}", quote(&column.label), sql_type(column.kind)))
+        .collect();
+    format!("CREATE TABLE {} ({});\n", table, columns.join(", "))
+}
+
+fn insert_profile(table: &str, profile: &columns::Profile) -> String {
+    let names: Vec<String> = profile.labels().iter().map(|label| quote(label)).collect();
+    let params: Vec<String> = (1..=names.len()).map(|i| format!("?{}", i)).collect();
+    format!("INSERT INTO {} ({}) VALUES ({})", table, names.join(", "), params.join(", "))
+}
+
+fn profile_values(profile: &columns::Profile, record: &cards::Record) -> Vec<Value> {
+    profile.row(record).into_iter().map(sql_value).collect()
+}
+
+// ============================================================================
+
+// Построчная запись, как у to_csv::Writer; вся выгрузка - одна транзакция, индексы строятся в finish
+pub struct Writer {
+    conn: Connection,
+    started_at: DateTime<Utc>,
+    record_qt: usize,
+    profile: Option<columns::Profile>,
+    cards: Columns,
+    autocatalog: Columns,
+    lifecycle: Columns,
+}
+
+impl Writer {
+    pub async fn new(file_path: &Path) -> Result<Self> {
+        Self::create(file_path, None).await
+    }
+    pub async fn with_profile(file_path: &Path, profile: columns::Profile) -> Result<Self> {
+        Self::create(file_path, Some(profile)).await
+    }
+    async fn create(file_path: &Path, profile: Option<columns::Profile>) -> Result<Self> {
+        if let Some(dir_path) = file_path.parent() {
+            fs::create_dir_all(dir_path).await?;
+        }
+        // выгрузка каждый раз полная: прежняя база заменяется
+        if file_path.exists() {
+            fs::remove_file(file_path).await.context(format!("{:?}", file_path))?;
+        }
+        let conn = Connection::open(file_path).context(format!("{:?}", file_path))?;
+        let cards = table_columns(is_cards, as_is);
+        let autocatalog = table_columns(is_autocatalog, autocatalog_column);
+        let lifecycle = table_columns(|name| name == "id" || LIFECYCLE.contains(&name), as_is);
+        let mut sql = "PRAGMA journal_mode = OFF;\nPRAGMA synchronous = OFF;\n".to_owned();
+        sql.push_str(&match &profile {
+            None => create_table("cards", &cards),
+            Some(profile) => create_profile_table("cards", profile),
+        });
+        sql.push_str(&create_table("autocatalog_modifications", &autocatalog));
+        sql.push_str(&create_table("lifecycle", &lifecycle));
+        sql.push_str("
+            CREATE TABLE price_history (
+                id INTEGER NOT NULL,
+                saved_at TEXT,
+                item_price INTEGER NOT NULL
+            );
+            CREATE TABLE runs (
+                started_at TEXT NOT NULL,
+                finished_at TEXT NOT NULL,
+                record_qt INTEGER NOT NULL
+            );
+            BEGIN;
+        ");
+        conn.execute_batch(&sql).context(format!("{:?}", file_path))?;
+        Ok(Self { conn, started_at: Utc::now(), record_qt: 0, profile, cards, autocatalog, lifecycle })
+    }
+    pub fn write(&mut self, record: &cards::Record) -> Result<()> {
+        match &self.profile {
+            None => self.conn.prepare_cached(&insert("INSERT OR REPLACE", "cards", &self.cards))?
+                .execute(values(record, &self.cards))?,
+            Some(profile) => self.conn.prepare_cached(&insert_profile("cards", profile))?
+                .execute(profile_values(profile, record))?,
+        };
+        if record.autocatalog_id.is_some() {
+            self.conn.prepare_cached(&insert("INSERT OR IGNORE", "autocatalog_modifications", &self.autocatalog))?
+                .execute(values(record, &self.autocatalog))?;
+        }
+        if record.id.is_some() && record.first_seen.is_some() {
+            self.conn.prepare_cached(&insert("INSERT OR REPLACE", "lifecycle", &self.lifecycle))?
+                .execute(values(record, &self.lifecycle))?;
+        }
+        self.record_qt += 1;
+        Ok(())
+    }
+    // Цена по версиям карточки: строка на каждое изменение цены
+    pub fn write_versions(&mut self, id: u64, versions: &[cards::store::Version]) -> Result<()> {
+        let mut stmt = self.conn.prepare_cached("INSERT INTO price_history (id, saved_at, item_price) VALUES (?1, ?2, ?3)")?;
+        let mut last_price = None;
+        for version in versions.iter() {
+            let item_price = match &version.fetched {
+                cards::Fetched::Record(record) => record.item_price,
+                _ => None,
+            };
+            if let Some(item_price) = item_price {
+                if last_price != Some(item_price) {
+                    stmt.execute(params![id as i64, version.saved_at.map(|val| val.to_rfc3339()), item_price as i64])?;
+                    last_price = Some(item_price);
+                }
+            }
+        }
+        Ok(())
+    }
+    pub fn finish(self) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO runs (started_at, finished_at, record_qt) VALUES (?1, ?2, ?3)",
+            params![self.started_at.to_rfc3339(), Utc::now().to_rfc3339(), self.record_qt as i64],
+        )?;
+        // столбцы cards по профилю произвольны: индексы только для полного набора
+        if self.profile.is_none() {
+            self.conn.execute_batch("
+                CREATE INDEX cards_brand ON cards (brand);
+                CREATE INDEX cards_model ON cards (brand, name);
+                CREATE INDEX cards_production_date ON cards (production_date);
+                CREATE INDEX cards_item_price ON cards (item_price);
+                CREATE INDEX cards_autocatalog_id ON cards (autocatalog_id);
+            ")?;
+        }
+        self.conn.execute_batch("
+            CREATE INDEX price_history_id ON price_history (id);
+            COMMIT;
+        ")?;
+        Ok(())
+    }
+}
+
+// ============================================================================
+// ============================================================================
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use log::{error, warn, info, debug, trace};
+    use super::*;
+
+    #[tokio::test]
+    async fn test_to_sqlite() -> Result<()> {
+        test_helper::init();
+
+        let json = json::Json::from_file("../cards/test_data/card.json").await?;
+        let file_path = Path::new("out_test/records.sqlite");
+        let mut writer = Writer::new(file_path).await?;
+        let mut versions = Vec::new();
+        for (i, item_price) in [900_000, 900_000, 850_000].iter().enumerate() {
+            let mut record = cards::Fetched::parse_json(&json, false)?;
+            record.id = Some(i as u64 + 1);
+            record.item_price = Some(*item_price);
+            record.autocatalog_id = Some(363804);
+            record.autocatalog_engine_power = Some("150".to_owned());
+            record.first_seen = record.time;
+            writer.write(&record)?;
+            versions.push(cards::store::Version { saved_at: record.time, fetched: cards::Fetched::Record(record) });
+        }
+        writer.write_versions(1, &versions)?;
+        writer.finish()?;
+
+        let conn = Connection::open(file_path)?;
+        let count = |sql: &str| -> Result<i64> { Ok(conn.query_row(sql, params![], |row| row.get(0))?) };
+        assert_eq!(count("SELECT COUNT(*) FROM cards WHERE brand = 'KIA'")?, 3);
+        assert_eq!(count("SELECT COUNT(*) FROM autocatalog_modifications")?, 1);
+        assert_eq!(count("SELECT COUNT(*) FROM lifecycle")?, 3);
+        assert_eq!(count("SELECT COUNT(*) FROM price_history WHERE id = 1")?, 2);
+        assert_eq!(count("SELECT record_qt FROM runs")?, 3);
+        let engine_power: String = conn.query_row(
+            "SELECT m.engine_power FROM cards c JOIN autocatalog_modifications m USING (autocatalog_id) WHERE c.id = 3",
+            params![],
+            |row| row.get(0),
+        )?;
+        assert_eq!(engine_power, "150");
+        assert_eq!(conn.prepare("SELECT * FROM lifecycle")?.column_count(), 5);
+        assert_eq!(count("SELECT COUNT(*) FROM pragma_table_info('cards') WHERE name IN ('first_seen', 'title', 'autocatalog_title')")?, 1);
+
+        let profile = columns::Profile::new(&[
+            columns::Spec { label: Some("Марка".to_owned()), ..columns::Spec::new("brand") },
+            columns::Spec::new("item_price"),
+        ])?;
+        let file_path = Path::new("out_test/records_profile.sqlite");
+        let mut writer = Writer::with_profile(file_path, profile).await?;
+        writer.write(&cards::Fetched::parse_json(&json, false)?)?;
+        writer.finish()?;
+        let conn = Connection::open(file_path)?;
+        let brand: String = conn.query_row(r#"SELECT "Марка" FROM cards"#, params![], |row| row.get(0))?;
+        assert_eq!(brand, "KIA");
+        assert_eq!(conn.prepare("SELECT * FROM cards")?.column_count(), 2);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,343 @@
+
+#[allow(unused_imports)]
+use log::{error, warn, info, debug, trace};
+#[allow(unused_imports)]
+use anyhow::{anyhow, bail, Result, Error, Context};
+
+use columns::{Cell, Kind};
+use rust_xlsxwriter::{ExcelDateTime, Format, Workbook, Worksheet};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+// ============================================================================
+// ============================================================================
+
+// Язык заголовков столбцов
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Lang {
+    Ru,
+    En,
+}
+
+// Заголовки столбцов по-русски и по-английски для полей записи без вложенных (см. columns::flat)
+const LABELS: &[(&str, &str, &str)] = &[
+    ("id", "Номер объявления", "Listing ID"),
+    ("body_type", "Тип кузова", "Body type"),
+    ("brand", "Марка", "Brand"),
+    ("color", "Цвет", "Color"),
+    ("fuel_type", "Тип двигателя", "Fuel type"),
+    ("name", "Модель", "Model"),
+    ("title", "Модель по автокаталогу", "Catalog model"),
+    ("number_of_doors", "Количество дверей", "Doors"),
+    ("production_date", "Год выпуска", "Year"),
+    ("vehicle_transmission", "Коробка передач", "Transmission"),
+    ("engine_displacement", "Объем двигателя", "Engine displacement"),
+    ("engine_power", "Мощность", "Engine power"),
+    ("description", "Описание", "Description"),
+    ("mileage", "Пробег, км", "Mileage, km"),
+    ("drive", "Привод", "Drive"),
+    ("steering_wheel", "Руль", "Steering wheel"),
+    ("condition", "Состояние", "Condition"),
+    ("owners", "Владельцы", "Owners"),
+    ("power_windows", "Электростеклоподъемники", "Power windows"),
+    ("power_steering", "Усилитель руля", "Power steering"),
+    ("audio_system", "Аудиосистема", "Audio system"),
+    ("headlights", "Фары", "Headlights"),
+    ("climate_control", "Климат-контроль", "Climate control"),
+    ("interior", "Салон", "Interior"),
+    ("rims", "Диски", "Rims"),
+    ("autocatalog_url", "Ссылка на автокаталог", "Catalog URL"),
+    ("item_price", "Цена, руб", "Price, RUB"),
+    ("market_price", "Рыночная цена, руб", "Market price, RUB"),
+    ("status", "Статус", "Status"),
+    ("closing_reason", "Причина закрытия", "Closing reason"),
+    ("complectation", "Комплектация", "Trim"),
+    ("modification", "Модификация", "Modification"),
+    ("generation", "Поколение", "Generation"),
+    ("type_of_trade", "Тип продажи", "Type of trade"),
+    ("canonical_url", "Ссылка на объявление", "Listing URL"),
+    ("location_id", "Номер места", "Location ID"),
+    ("lat", "Широта", "Latitude"),
+    ("lng", "Долгота", "Longitude"),
+    ("time", "Опубликовано", "Published"),
+    ("views_total", "Просмотры всего", "Views total"),
+    ("views_today", "Просмотры сегодня", "Views today"),
+    ("images_qt", "Количество фото", "Photos"),
+    ("has_video", "Есть видео", "Has video"),
+    ("first_seen", "Впервые замечено", "First seen"),
+    ("last_seen", "Последний раз замечено", "Last seen"),
+    ("closed_at", "Снято с публикации", "Closed at"),
+    ("days_on_market", "Дней в продаже", "Days on market"),
+    ("cluster_id", "Кластер повторов", "Duplicate cluster"),
+    ("duplicate_of", "Повтор объявления", "Duplicate of"),
+    ("change_type", "Изменение", "Change"),
+    ("power_hp", "Мощность, л.с.", "Power, hp"),
+    ("power_kw", "Мощность, кВт", "Power, kW"),
+    ("displacement_l", "Объем двигателя, л", "Displacement, l"),
+    ("displacement_cc", "Объем двигателя, см³", "Displacement, cc"),
+    ("torque_nm", "Крутящий момент, Н⋅м", "Torque, N⋅m"),
+    ("max_speed_kmh", "Максимальная скорость, км/ч", "Maximum speed, km/h"),
+    ("acceleration_s", "Разгон до 100 км/ч, с", "Acceleration 0-100 km/h, s"),
+    ("consumption_city_l100km", "Расход в городе, л/100 км", "Consumption city, l/100 km"),
+    ("consumption_highway_l100km", "Расход по трассе, л/100 км", "Consumption highway, l/100 km"),
+    ("consumption_mixed_l100km", "Расход смешанный, л/100 км", "Consumption mixed, l/100 km"),
+    ("fuel_tank_l", "Топливный бак, л", "Fuel tank, l"),
+    ("length_mm", "Длина, мм", "Length, mm"),
+    ("width_with_mirrors_mm", "Ширина с зеркалами, мм", "Width with mirrors, mm"),
+    ("height_mm", "Высота, мм", "Height, mm"),
+    ("wheelbase_mm", "Колесная база, мм", "Wheelbase, mm"),
+    ("clearance_mm", "Дорожный просвет, мм", "Clearance, mm"),
+    ("front_track_mm", "Колея передняя, мм", "Front track, mm"),
+    ("rear_track_mm", "Колея задняя, мм", "Rear track, mm"),
+    ("autocatalog_id", "Номер модификации", "Catalog modification ID"),
+    ("autocatalog_title", "Модификация по автокаталогу", "Catalog modification"),
+    ("autocatalog_match_method", "Сопоставление с автокаталогом", "Catalog match method"),
+    ("autocatalog_match_confidence", "Уверенность сопоставления", "Catalog match confidence"),
+    ("autocatalog_match_candidates", "Подходящих модификаций", "Catalog match candidates"),
+    ("autocatalog_transmission", "Коробка передач (автокаталог)", "Transmission (catalog)"),
+    ("autocatalog_engine_displacement", "Объем двигателя, л (автокаталог)", "Engine displacement, l (catalog)"),
+    ("autocatalog_engine_displacement_precise", "Рабочий объем, см³ (автокаталог)", "Engine displacement, cm³ (catalog)"),
+    ("autocatalog_drive", "Привод (автокаталог)", "Drive (catalog)"),
+    ("autocatalog_fuel_type", "Тип двигателя (автокаталог)", "Fuel type (catalog)"),
+    ("autocatalog_engine_power", "Мощность, л.с. (автокаталог)", "Engine power, hp (catalog)"),
+    ("autocatalog_maximum_speed", "Максимальная скорость, км/ч (автокаталог)", "Top speed, km/h (catalog)"),
+    ("autocatalog_acceleration", "Разгон до 100 км/ч, с (автокаталог)", "0-100 km/h, s (catalog)"),
+    ("autocatalog_brand_country", "Страна происхождения бренда (автокаталог)", "Brand country (catalog)"),
+    ("autocatalog_assembly_country", "Страна сборки (автокаталог)", "Assembly country (catalog)"),
+    ("autocatalog_number_of_seats", "Количество мест (автокаталог)", "Seats (catalog)"),
+    ("autocatalog_rating", "Рейтинг EuroNCAP (автокаталог)", "EuroNCAP rating (catalog)"),
+    ("autocatalog_number_of_cylinders", "Количество цилиндров (автокаталог)", "Cylinders (catalog)"),
+    ("autocatalog_configuration", "Конфигурация (автокаталог)", "Engine configuration (catalog)"),
+    ("autocatalog_torque", "Крутящий момент, Н⋅м (автокаталог)", "Torque, N⋅m (catalog)"),
+    ("autocatalog_torque_max", "Обороты максимального крутящего момента, об/мин (автокаталог)", "Max torque speed, rpm (catalog)"),
+    ("autocatalog_max_power_speed", "Обороты максимальной мощности, об/мин (автокаталог)", "Max power speed, rpm (catalog)"),
+    ("autocatalog_height", "Высота, мм (автокаталог)", "Height, mm (catalog)"),
+    ("autocatalog_length", "Длина, мм (автокаталог)", "Length, mm (catalog)"),
+    ("autocatalog_turning_diameter", "Диаметр разворота, м (автокаталог)", "Turning diameter, m (catalog)"),
+    ("autocatalog_clearance", "Дорожный просвет, мм (автокаталог)", "Ground clearance, mm (catalog)"),
+    ("autocatalog_wheelbase", "Колесная база, мм (автокаталог)", "Wheelbase, mm (catalog)"),
+    ("autocatalog_rear_track", "Колея задняя, мм (автокаталог)", "Rear track, mm (catalog)"),
+    ("autocatalog_front_track", "Колея передняя, мм (автокаталог)", "Front track, mm (catalog)"),
+    ("autocatalog_trunk_volume", "Объем багажника, л (автокаталог)", "Trunk volume, l (catalog)"),
+    ("autocatalog_fuel_tank_capacity", "Емкость топливного бака, л (автокаталог)", "Fuel tank, l (catalog)"),
+    ("autocatalog_fuel_consumption_city", "Расход топлива в городе, л/100 км (автокаталог)", "Fuel consumption city, l/100 km (catalog)"),
+    ("autocatalog_fuel_consumption_highway", "Расход топлива по трассе, л/100 км (автокаталог)", "Fuel consumption highway, l/100 km (catalog)"),
+    ("autocatalog_fuel_consumption_mixed", "Расход топлива смешанный, л/100 км (автокаталог)", "Fuel consumption mixed, l/100 km (catalog)"),
+    ("autocatalog_environmental_class", "Экологический класс (автокаталог)", "Emission standard (catalog)"),
+    ("autocatalog_rear_breaks", "Задние тормоза (автокаталог)", "Rear brakes (catalog)"),
+    ("autocatalog_front_breaks", "Передние тормоза (автокаталог)", "Front brakes (catalog)"),
+    ("autocatalog_rear_tire_dimension", "Размерность задних шин (автокаталог)", "Rear tire size (catalog)"),
+    ("autocatalog_front_tire_dimension", "Размерность передних шин (автокаталог)", "Front tire size (catalog)"),
+    ("autocatalog_rear_suspension", "Задняя подвеска (автокаталог)", "Rear suspension (catalog)"),
+    ("autocatalog_front_suspension", "Передняя подвеска (автокаталог)", "Front suspension (catalog)"),
+    ("autocatalog_world_premier", "Мировая премьера (автокаталог)", "World premiere (catalog)"),
+    ("autocatalog_pending_update", "Ожидаемое обновление (автокаталог)", "Pending update (catalog)"),
+    ("autocatalog_width_with_mirrors", "Ширина с зеркалами, мм (автокаталог)", "Width with mirrors, mm (catalog)"),
+    ("autocatalog_rear_disc_dimension", "Размерность задних дисков (автокаталог)", "Rear rim size (catalog)"),
+    ("autocatalog_front_disc_dimension", "Размерность передних дисков (автокаталог)", "Front rim size (catalog)"),
+];
+
+fn label(name: &str, lang: Lang) -> &str {
+    match LABELS.iter().find(|(field, _, _)| *field == name) {
+        Some((_, ru, en)) => match lang {
+            Lang::Ru => ru,
+            Lang::En => en,
+        },
+        None => name,
+    }
+}
+
+// Строк на листе Excel не больше
+const ROW_MAX: u32 = 1_048_576;
+
+// ============================================================================
+
+// Построчная запись, как у to_csv::Writer, но в листы одной книги: лист на каждый отбор.
+// Строки пишутся на диск по мере записи (constant memory), заголовок закреплен, на нем автофильтр
+pub struct Writer {
+    file_path: PathBuf,
+    lang: Lang,
+    workbook: Workbook,
+    rows: Vec<u32>,
+    time_format: Format,
+    profile: Option<columns::Profile>,
+}
+
+impl Writer {
+    // Книга с одним листом sheet (индекс 0); остальные листы - add_sheet
+    pub async fn new(file_path: &Path, lang: Lang, sheet: &str) -> Result<Self> {
+        Self::create(file_path, lang, sheet, None).await
+    }
+    // Столбцы и заголовки - из профиля (см. columns::Profile), а не LABELS и lang
+    pub async fn with_profile(file_path: &Path, sheet: &str, profile: columns::Profile) -> Result<Self> {
+        Self::create(file_path, Lang::Ru, sheet, Some(profile)).await
+    }
+    async fn create(file_path: &Path, lang: Lang, sheet: &str, profile: Option<columns::Profile>) -> Result<Self> {
+        if let Some(dir_path) = file_path.parent() {
+            fs::create_dir_all(dir_path).await?;
+        }
+        let mut ret = Self {
+            file_path: file_path.to_owned(),
+            lang,
+            workbook: Workbook::new(),
+            rows: Vec::new(),
+            time_format: Format::new().set_num_format("yyyy-mm-dd hh:mm:ss"),
+            profile,
+        };
+        ret.add_sheet(sheet)?;
+        Ok(ret)
+    }
+    // Индекс нового листа, для write
+    pub fn add_sheet(&mut self, name: &str) -> Result<usize> {
+        let header_format = Format::new().set_bold();
+        let lang = self.lang;
+        let labels: Vec<&str> = match &self.profile {
+            Some(profile) => profile.labels(),
+            None => columns::flat().map(|(_, name, _)| label(name, lang)).collect(),
+        };
+        let worksheet = self.workbook.add_worksheet_with_constant_memory();
+        worksheet.set_name(name).context(format!("sheet {:?}", name))?;
+        for (col, label) in labels.into_iter().enumerate() {
+            worksheet.write_string_with_format(0, col as u16, label, &header_format)?;
+        }
+        worksheet.set_freeze_panes(1, 0)?;
+        self.rows.push(1);
+        Ok(self.rows.len() - 1)
+    }
+    pub fn write(&mut self, sheet: usize, record: &cards::Record) -> Result<()> {
+        let row = self.rows[sheet];
+        if row >= ROW_MAX {
+            bail!("sheet {} of {:?} is full: {} rows", sheet, self.file_path, ROW_MAX);
+        }
+        let worksheet = self.workbook.worksheet_from_index(sheet)?;
+        match &self.profile {
+            // справочные значения профиля - кодом, как в остальных форматах
+            Some(profile) => for (i, (column, cell)) in profile.columns.iter().zip(profile.row(record)).enumerate() {
+                write_cell(worksheet, row, i as u16, column.kind, cell, None, &self.time_format)?;
+            },
+            None => for (i, (field, _, kind)) in columns::flat().enumerate() {
+                write_cell(worksheet, row, i as u16, kind, columns::get(record, field), Some(self.lang), &self.time_format)?;
+            },
+        }
+        self.rows[sheet] += 1;
+        Ok(())
+    }
+    pub fn finish(mut self) -> Result<()> {
+        let last_col = match &self.profile {
+            Some(profile) => profile.columns.len(),
+            None => columns::flat().count(),
+        } as u16 - 1;
+        for (sheet, row) in self.rows.iter().enumerate() {
+            self.workbook.worksheet_from_index(sheet)?.autofilter(0, 0, row - 1, last_col)?;
+        }
+        self.workbook.save(&self.file_path).context(format!("{:?}", self.file_path))?;
+        Ok(())
+    }
+}
+
+// Число автокаталога текстом (Kind::NumStr) пишется числом, если разбирается;
+// справочное значение - подписью на языке lang, None - кодом
+fn write_cell(worksheet: &mut Worksheet, row: u32, col: u16, kind: Kind, cell: Cell, lang: Option<Lang>, time_format: &Format) -> Result<()> {
+    match (kind, cell) {
+        (_, Cell::Null) => {},
+        (Kind::NumStr, Cell::Str(val)) => match columns::parse_num(&val) {
+            Some(num) => { worksheet.write_number(row, col, num)?; },
+            None => { worksheet.write_string(row, col, val)?; },
+        },
+        (_, Cell::Str(val)) => { worksheet.write_string(row, col, val)?; },
+        (_, Cell::Vocab(val)) => { worksheet.write_string(row, col, match lang {
+            Some(Lang::Ru) => val.label_ru(),
+            Some(Lang::En) => val.label_en(),
+            None => val.code(),
+        })?; },
+        (_, Cell::Int(val)) => { worksheet.write_number(row, col, val as f64)?; },
+        (_, Cell::Float(val)) => { worksheet.write_number(row, col, val)?; },
+        (_, Cell::Bool(val)) => { worksheet.write_boolean(row, col, val)?; },
+        (_, Cell::Time(val)) => {
+            let datetime = ExcelDateTime::from_timestamp(val.timestamp())?;
+            worksheet.write_datetime_with_format(row, col, &datetime, time_format)?;
+        },
+    }
+    Ok(())
+}
+
+// ============================================================================
+// ============================================================================
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use log::{error, warn, info, debug, trace};
+    use super::*;
+
+    use std::io::Read;
+
+    #[tokio::test]
+    async fn test_to_xlsx() -> Result<()> {
+        test_helper::init();
+
+        let unlabeled: Vec<&str> = columns::flat()
+            .map(|(_, name, _)| name)
+            .filter(|name| !LABELS.iter().any(|(field, _, _)| field == name))
+            .collect();
+        assert!(unlabeled.is_empty(), "fields without labels: {:?}", unlabeled);
+        // заголовки различимы: поле из автокаталога не повторяет заголовок типизированного поля
+        for lang in [Lang::Ru, Lang::En].iter() {
+            let mut labels = std::collections::HashSet::new();
+            let repeated: Vec<&str> = columns::flat()
+                .map(|(_, name, _)| label(name, *lang))
+                .filter(|label| !labels.insert(*label))
+                .collect();
+            assert!(repeated.is_empty(), "{:?}: repeated labels: {:?}", lang, repeated);
+        }
+
+        let json = json::Json::from_file("../cards/test_data/card.json").await?;
+        let mut record = cards::Fetched::parse_json(&json, false)?;
+        record.autocatalog_engine_power = Some("150".to_owned());
+
+        let file_path = Path::new("out_test/records.xlsx");
+        let mut writer = Writer::new(file_path, Lang::En, "records").await?;
+        assert_eq!(writer.add_sheet("kia")?, 1);
+        writer.write(0, &record)?;
+        writer.write(0, &record)?;
+        writer.write(1, &record)?;
+        writer.finish()?;
+
+        let mut archive = zip::ZipArchive::new(std::fs::File::open(file_path)?)?;
+        let mut read = |name: &str| -> Result<String> {
+            let mut s = String::new();
+            archive.by_name(name)?.read_to_string(&mut s)?;
+            Ok(s)
+        };
+        let workbook = read("xl/workbook.xml")?;
+        assert!(workbook.contains(r#"name="records""#) && workbook.contains(r#"name="kia""#));
+        let sheet = read("xl/worksheets/sheet1.xml")?;
+        assert!(sheet.contains(r#"<pane ySplit="1""#));
+        assert!(sheet.contains(r#"<autoFilter ref="A1:"#));
+        assert!(sheet.contains(r#"<row r="3""#));
+        let strings = read("xl/sharedStrings.xml").unwrap_or_default();
+        assert!(sheet.contains("Listing ID") || strings.contains("Listing ID"));
+
+        let profile = columns::Profile::new(&[
+            columns::Spec::new("brand"),
+            columns::Spec { label: Some("Цена".to_owned()), thousands_separator: Some(" ".to_owned()), ..columns::Spec::new("item_price") },
+        ])?;
+        let file_path = Path::new("out_test/records_profile.xlsx");
+        let mut writer = Writer::with_profile(file_path, "records", profile).await?;
+        writer.write(0, &record)?;
+        writer.finish()?;
+        let mut archive = zip::ZipArchive::new(std::fs::File::open(file_path)?)?;
+        let mut read = |name: &str| -> Result<String> {
+            let mut s = String::new();
+            archive.by_name(name)?.read_to_string(&mut s)?;
+            Ok(s)
+        };
+        let sheet = read("xl/worksheets/sheet1.xml")?;
+        assert!(sheet.contains(r#"<autoFilter ref="A1:B2""#));
+        let strings = read("xl/sharedStrings.xml").unwrap_or_default();
+        assert!(sheet.contains("Цена") || strings.contains("Цена"));
+
+        Ok(())
+    }
+}
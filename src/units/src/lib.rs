@@ -0,0 +1,246 @@
+#[allow(unused_imports)]
+use log::{error, warn, info, debug, trace};
+#[allow(unused_imports)]
+use anyhow::{Result, Error, bail, anyhow, Context};
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+// ============================================================================
+// ============================================================================
+
+// Числовые характеристики из строковых полей карточки ("150 л.с.", "2.0") и автокаталога ("150", "1995", "7,4", "-"):
+// значение разбирается в число с необязательной единицей измерения и приводится к единице поля записи
+
+// Единица измерения (в нормализованном виде, см. normalize_unit) и множитель к единице поля;
+// пустая строка - значение без единицы, в единице поля
+type Units = &'static [(&'static str, f64)];
+
+pub const KW_PER_HP: f64 = 0.735_498_75;
+
+const HP: Units = &[("", 1.0), ("лс", 1.0), ("hp", 1.0), ("квт", 1.0 / KW_PER_HP), ("kw", 1.0 / KW_PER_HP)];
+const LITERS: Units = &[("", 1.0), ("л", 1.0), ("l", 1.0), ("см³", 0.001), ("см3", 0.001), ("кубсм", 0.001), ("cc", 0.001)];
+const CC: Units = &[("", 1.0), ("см³", 1.0), ("см3", 1.0), ("кубсм", 1.0), ("cc", 1.0), ("л", 1000.0), ("l", 1000.0)];
+const NM: Units = &[("", 1.0), ("нм", 1.0), ("nm", 1.0)];
+const KMH: Units = &[("", 1.0), ("км/ч", 1.0), ("km/h", 1.0)];
+const SECONDS: Units = &[("", 1.0), ("с", 1.0), ("сек", 1.0), ("s", 1.0)];
+const L100KM: Units = &[("", 1.0), ("л/100км", 1.0), ("l/100km", 1.0)];
+const MM: Units = &[("", 1.0), ("мм", 1.0), ("mm", 1.0), ("см", 10.0), ("м", 1000.0)];
+
+// Значения, означающие отсутствие данных, а не ошибку разбора
+const ABSENT: &[&str] = &["", "-", "–", "—", "нет данных", "н/д"];
+
+#[derive(Debug, PartialEq)]
+enum Parsed {
+    Absent,
+    Value(f64),
+    Unparsed,
+}
+
+fn parse(s: &str, units: Units) -> Parsed {
+    let s = s.trim();
+    if ABSENT.contains(&s) {
+        return Parsed::Absent;
+    }
+    let (num, unit) = match split(s) {
+        Some(ret) => ret,
+        None => return Parsed::Unparsed,
+    };
+    let unit = normalize_unit(unit);
+    match units.iter().find(|(name, _)| *name == unit) {
+        Some((_, factor)) => Parsed::Value(num * factor),
+        None => Parsed::Unparsed,
+    }
+}
+
+// Число в начале строки и остаток (единица): "1 995 см³" -> (1995.0, "см³"), "7,4" -> (7.4, "");
+// пробелы допускаются только между цифрами (разделитель тысяч), запятая - десятичный разделитель
+fn split(s: &str) -> Option<(f64, &str)> {
+    let mut num = String::new();
+    let mut has_point = false;
+    let mut end = 0;
+    let mut chars = s.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let next_is_digit = chars.peek().map(|(_, c)| c.is_ascii_digit()) == Some(true);
+        if c.is_ascii_digit() {
+            num.push(c);
+        } else if (c == '.' || c == ',') && !has_point && !num.is_empty() && next_is_digit {
+            num.push('.');
+            has_point = true;
+        } else if c.is_whitespace() && !has_point && !num.is_empty() && next_is_digit {
+            // разделитель тысяч пропускается
+        } else {
+            break;
+        }
+        end = i + c.len_utf8();
+    }
+    if num.is_empty() {
+        return None;
+    }
+    Some((num.parse().ok()?, &s[end..]))
+}
+
+// "л. с." -> "лс", "Н⋅м" -> "нм", "л/100 км" -> "л/100км"
+fn normalize_unit(s: &str) -> String {
+    s.chars()
+        .filter(|c| !c.is_whitespace() && !['.', '⋅', '·', '*'].contains(c))
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+// Значение в единице поля: мощность в л.с., объем двигателя в литрах (None - нет данных или не разобрано)
+pub fn power_hp(s: &str) -> Option<f64> {
+    value(s, HP)
+}
+
+pub fn displacement_l(s: &str) -> Option<f64> {
+    value(s, LITERS)
+}
+
+fn value(s: &str, units: Units) -> Option<f64> {
+    match parse(s, units) {
+        Parsed::Value(val) => Some(val),
+        _ => None,
+    }
+}
+
+fn round(val: f64, decimals: i32) -> f64 {
+    let factor = 10f64.powi(decimals);
+    (val * factor).round() / factor
+}
+
+// ============================================================================
+
+// Неразобранное значение: поле, значение, сколько раз встретилось и id первой такой записи
+#[derive(Debug, Serialize)]
+pub struct Unparsed {
+    pub field: &'static str,
+    pub value: String,
+    pub qt: usize,
+    pub example_id: Option<u64>,
+}
+
+// Заполняет числовые поля записей и копит неразобранные значения для отчета
+#[derive(Default)]
+pub struct Report {
+    unparsed: BTreeMap<(&'static str, String), (usize, Option<u64>)>,
+}
+
+impl Report {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    // Значение автокаталога (точной модификации) предпочтительнее значения карточки;
+    // разбираются оба, чтобы в отчет попали неразобранные значения из обоих источников
+    pub fn adopt(&mut self, record: &mut cards::Record) {
+        let id = record.id;
+        let mut get = |field: &'static str, value: &Option<String>, units: Units| -> Option<f64> {
+            let value = value.as_ref()?;
+            match parse(value, units) {
+                Parsed::Value(val) => Some(val),
+                Parsed::Absent => None,
+                Parsed::Unparsed => {
+                    let entry = self.unparsed.entry((field, value.trim().to_owned())).or_insert((0, id));
+                    entry.0 += 1;
+                    None
+                },
+            }
+        };
+
+        let power_hp = get("autocatalog_engine_power", &record.autocatalog_engine_power, HP)
+            .or(get("engine_power", &record.engine_power, HP));
+        let displacement_cc = get("autocatalog_engine_displacement_precise", &record.autocatalog_engine_displacement_precise, CC);
+        let displacement_l = get("autocatalog_engine_displacement", &record.autocatalog_engine_displacement, LITERS)
+            .or(get("engine_displacement", &record.engine_displacement, LITERS));
+        record.power_hp = power_hp.map(|val| round(val, 0));
+        record.power_kw = power_hp.map(|val| round(val * KW_PER_HP, 1));
+        record.displacement_cc = displacement_cc.or(displacement_l.map(|val| val * 1000.0)).map(|val| round(val, 0));
+        record.displacement_l = displacement_l.or(displacement_cc.map(|val| val / 1000.0)).map(|val| round(val, 1));
+
+        record.torque_nm = get("autocatalog_torque", &record.autocatalog_torque, NM);
+        record.max_speed_kmh = get("autocatalog_maximum_speed", &record.autocatalog_maximum_speed, KMH);
+        record.acceleration_s = get("autocatalog_acceleration", &record.autocatalog_acceleration, SECONDS);
+
+        record.consumption_city_l100km = get("autocatalog_fuel_consumption_city", &record.autocatalog_fuel_consumption_city, L100KM);
+        record.consumption_highway_l100km = get("autocatalog_fuel_consumption_highway", &record.autocatalog_fuel_consumption_highway, L100KM);
+        record.consumption_mixed_l100km = get("autocatalog_fuel_consumption_mixed", &record.autocatalog_fuel_consumption_mixed, L100KM);
+        record.fuel_tank_l = get("autocatalog_fuel_tank_capacity", &record.autocatalog_fuel_tank_capacity, LITERS);
+
+        record.length_mm = get("autocatalog_length", &record.autocatalog_length, MM);
+        record.width_with_mirrors_mm = get("autocatalog_width_with_mirrors", &record.autocatalog_width_with_mirrors, MM);
+        record.height_mm = get("autocatalog_height", &record.autocatalog_height, MM);
+        record.wheelbase_mm = get("autocatalog_wheelbase", &record.autocatalog_wheelbase, MM);
+        record.clearance_mm = get("autocatalog_clearance", &record.autocatalog_clearance, MM);
+        record.front_track_mm = get("autocatalog_front_track", &record.autocatalog_front_track, MM);
+        record.rear_track_mm = get("autocatalog_rear_track", &record.autocatalog_rear_track, MM);
+    }
+    pub fn is_empty(&self) -> bool {
+        self.unparsed.is_empty()
+    }
+    // Неразобранные значения, самые частые первыми
+    pub fn items(&self) -> Vec<Unparsed> {
+        let mut ret: Vec<Unparsed> = self.unparsed.iter()
+            .map(|((field, value), (qt, example_id))| Unparsed { field, value: value.clone(), qt: *qt, example_id: *example_id })
+            .collect();
+        ret.sort_by_key(|item| std::cmp::Reverse(item.qt));
+        ret
+    }
+}
+
+// ============================================================================
+// ============================================================================
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use log::{error, warn, info, debug, trace};
+    use super::*;
+
+    #[tokio::test]
+    async fn test_units() -> Result<()> {
+        test_helper::init();
+
+        assert_eq!(parse("150 л.с.", HP), Parsed::Value(150.0));
+        assert_eq!(parse("110 кВт", HP), Parsed::Value(110.0 / KW_PER_HP));
+        assert_eq!(parse("1 995 см³", CC), Parsed::Value(1995.0));
+        assert_eq!(parse("7,4", L100KM), Parsed::Value(7.4));
+        assert_eq!(parse("6.9 л/100 км", L100KM), Parsed::Value(6.9));
+        assert_eq!(parse("196 Н⋅м", NM), Parsed::Value(196.0));
+        assert_eq!(parse("-", MM), Parsed::Absent);
+        assert_eq!(parse("1750-4500", NM), Parsed::Unparsed);
+        assert_eq!(parse("нет", MM), Parsed::Unparsed);
+        assert_eq!(power_hp("150 л.с."), Some(150.0));
+        assert_eq!(displacement_l("2.0 л"), Some(2.0));
+        assert_eq!(displacement_l("-"), None);
+
+        let json = json::Json::from_file("../cards/test_data/card.json").await?;
+        let mut record = cards::Fetched::parse_json(&json, false)?;
+        let mut report = Report::new();
+        report.adopt(&mut record);
+        assert_eq!(record.power_hp, Some(150.0));
+        assert_eq!(record.power_kw, Some(110.3));
+        assert_eq!(record.displacement_l, Some(2.0));
+        assert_eq!(record.displacement_cc, Some(2000.0));
+        assert!(report.is_empty());
+
+        record.autocatalog_engine_displacement_precise = Some("1999".to_owned());
+        record.autocatalog_clearance = Some("160".to_owned());
+        record.autocatalog_fuel_consumption_mixed = Some("7,4".to_owned());
+        record.autocatalog_length = Some("около 4,5 м".to_owned());
+        record.autocatalog_height = Some("-".to_owned());
+        report.adopt(&mut record);
+        report.adopt(&mut record);
+        assert_eq!(record.displacement_cc, Some(1999.0));
+        assert_eq!(record.displacement_l, Some(2.0));
+        assert_eq!(record.clearance_mm, Some(160.0));
+        assert_eq!(record.consumption_mixed_l100km, Some(7.4));
+        assert_eq!(record.length_mm, None);
+        assert_eq!(record.height_mm, None);
+        let items = report.items();
+        assert_eq!(items.len(), 1);
+        assert_eq!((items[0].field, items[0].value.as_str(), items[0].qt), ("autocatalog_length", "около 4,5 м", 2));
+
+        Ok(())
+    }
+}
@@ -60,6 +60,7 @@ enum Msg {
 #[derive(Debug)]
 struct Req {
     url: Url,
+    binary: bool,
     promise: Promise<Res>,
 }
 
@@ -146,7 +147,7 @@ impl Client {
                                                 stop_promise.resolve(());
                                                 break;
                                             },
-                                            Msg::Req (Req { url, mut promise }) => {
+                                            Msg::Req (Req { url, binary, mut promise }) => {
                                                 let correlation_id = uuid::Uuid::new_v4();
                                                 trace!("received request, made correlation_id: {:?}", correlation_id);
                                                 reqs.insert(correlation_id, promise);
@@ -156,6 +157,7 @@ impl Client {
                                                     method: http::Method::GET,
                                                     url,
                                                     timeout: None,
+                                                    binary: if binary { Some(true) } else { None },
                                                 };
                                                 let payload = serde_json::to_string_pretty(&req)?;
                                                 trace!("before basic_publish");
@@ -221,14 +223,24 @@ impl Client {
         promise.await;
     }
     pub async fn get_text_status(&self, url: Url) -> Result<(String, http::StatusCode)> {
+        let res = self.get(url, false).await;
+        return Ok((res.text, res.status))
+    }
+    // Тело ответа прокси передает в base64
+    pub async fn get_bytes_status(&self, url: Url) -> Result<(Vec<u8>, http::StatusCode)> {
+        let res = self.get(url.clone(), true).await;
+        let bytes = base64::decode(&res.text).context(format!("via_proxy::Client::get_bytes_status({})", url))?;
+        return Ok((bytes, res.status))
+    }
+    async fn get(&self, url: Url, binary: bool) -> Res {
         let promise: Promise<Res> = Promise::new();
-        let req = Req { url: url.clone(), promise: promise.clone() };
+        let req = Req { url: url.clone(), binary, promise: promise.clone() };
         // &self.broker.sender.lock().unwrap().send(Msg::Req(req)).await?;
         &self.broker.sender.lock().unwrap().try_send(Msg::Req(req)).unwrap();
         trace!("get_text_status is waiting for promise of {}", url);
         let res = promise.await;
         trace!("resolved get_text_status for {}", url);
-        return Ok((res.text, res.status))
+        res
     }
 }
 
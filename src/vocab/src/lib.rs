@@ -0,0 +1,219 @@
+#[allow(unused_imports)]
+use log::{error, warn, info, debug, trace};
+#[allow(unused_imports)]
+use anyhow::{Result, Error, bail, anyhow, Context};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+// ============================================================================
+// ============================================================================
+
+// Справочные значения полей карточки и автокаталога: у каждого канонический код (им значение выгружается
+// и сравнивается в фильтре) и подписи по-русски и по-английски. Значение разбирается из любого написания:
+// кода, подписи или известного варианта источника ("Хетчбэк", "Механика", "AT") без учета регистра;
+// неизвестное значение сохраняется как есть в Other
+
+// Для сравнения написаний: без учета регистра, е/ё и лишних пробелов
+fn normalize(s: &str) -> String {
+    s.split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .to_lowercase()
+        .replace('ё', "е")
+}
+
+macro_rules! vocab {
+    ($name: ident { $($variant: ident: $code: expr, $ru: expr, $en: expr, [$($alias: expr),* $(,)?]);* $(;)? }) => {
+        #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+        pub enum $name {
+            $($variant,)*
+            Other(String),
+        }
+
+        impl $name {
+            pub const KNOWN: &'static [$name] = &[$($name::$variant),*];
+
+            pub fn parse(s: &str) -> Self {
+                let key = normalize(s);
+                $(
+                    if [$code, $ru, $en $(, $alias)*].iter().any(|s| normalize(s) == key) {
+                        return $name::$variant;
+                    }
+                )*
+                $name::Other(s.trim().to_owned())
+            }
+            pub fn code(&self) -> &str {
+                match self {
+                    $($name::$variant => $code,)*
+                    $name::Other(s) => s,
+                }
+            }
+            pub fn label_ru(&self) -> &str {
+                match self {
+                    $($name::$variant => $ru,)*
+                    $name::Other(s) => s,
+                }
+            }
+            pub fn label_en(&self) -> &str {
+                match self {
+                    $($name::$variant => $en,)*
+                    $name::Other(s) => s,
+                }
+            }
+            pub fn is_other(&self) -> bool {
+                matches!(self, $name::Other(_))
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{}", self.code())
+            }
+        }
+
+        impl Serialize for $name {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: Serializer,
+            {
+                serializer.serialize_str(self.code())
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: Deserializer<'de>,
+            {
+                let s = String::deserialize(deserializer)?;
+                Ok(Self::parse(&s))
+            }
+        }
+    };
+}
+
+vocab!(BodyType {
+    Suv: "suv", "внедорожник", "SUV", [];
+    Sedan: "sedan", "седан", "Sedan", [];
+    Coupe: "coupe", "купе", "Coupe", [];
+    Hatchback: "hatchback", "хэтчбек", "Hatchback", ["хетчбэк", "хэтчбэк", "хетчбек"];
+    Liftback: "liftback", "лифтбек", "Liftback", ["лифтбэк"];
+    Wagon: "wagon", "универсал", "Wagon", ["estate"];
+    Minivan: "minivan", "минивэн", "Minivan", ["минивен"];
+    Convertible: "convertible", "кабриолет", "Convertible", ["cabriolet"];
+    Van: "van", "фургон", "Van", [];
+    Pickup: "pickup", "пикап", "Pickup", [];
+    Minibus: "minibus", "микроавтобус", "Minibus", [];
+    Limousine: "limousine", "лимузин", "Limousine", [];
+});
+
+vocab!(Drive {
+    Front: "front", "передний", "Front", ["fwd"];
+    Rear: "rear", "задний", "Rear", ["rwd"];
+    All: "all", "полный", "All-wheel", ["4wd", "awd", "полный подключаемый", "полный постоянный"];
+});
+
+vocab!(Transmission {
+    Manual: "manual", "механическая", "Manual", ["механика", "mt"];
+    Automatic: "automatic", "автоматическая", "Automatic", ["автомат", "at"];
+    Cvt: "cvt", "вариатор", "CVT", [];
+    Robot: "robot", "робот", "Robotized", ["роботизированная", "amt"];
+});
+
+vocab!(FuelType {
+    Petrol: "petrol", "бензин", "Petrol", ["gasoline"];
+    Diesel: "diesel", "дизель", "Diesel", [];
+    Hybrid: "hybrid", "гибрид", "Hybrid", [];
+    Electric: "electric", "электро", "Electric", ["электричество", "электрический"];
+    Gas: "gas", "газ", "Gas", ["lpg", "cng"];
+});
+
+vocab!(Condition {
+    NotDamaged: "not_damaged", "не битый", "Not damaged", [];
+    Damaged: "damaged", "битый", "Damaged", [];
+});
+
+// Значение любого справочника: выгрузка (см. crate columns) пишет код или подпись, не зная, какой справочник у поля
+macro_rules! any {
+    ($($name: ident),* $(,)?) => {
+        #[derive(Debug, Clone, PartialEq)]
+        pub enum Any {
+            $($name($name),)*
+        }
+
+        impl Any {
+            pub fn code(&self) -> &str {
+                match self {
+                    $(Any::$name(val) => val.code(),)*
+                }
+            }
+            pub fn label_ru(&self) -> &str {
+                match self {
+                    $(Any::$name(val) => val.label_ru(),)*
+                }
+            }
+            pub fn label_en(&self) -> &str {
+                match self {
+                    $(Any::$name(val) => val.label_en(),)*
+                }
+            }
+        }
+
+        $(
+            impl From<$name> for Any {
+                fn from(val: $name) -> Self {
+                    Any::$name(val)
+                }
+            }
+        )*
+    };
+}
+
+any!(BodyType, Drive, Transmission, FuelType, Condition);
+
+impl fmt::Display for Any {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.code())
+    }
+}
+
+// ============================================================================
+// ============================================================================
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use log::{error, warn, info, debug, trace};
+    use super::*;
+
+    #[test]
+    fn test_vocab() -> Result<()> {
+        test_helper::init();
+
+        assert_eq!(BodyType::parse("Хетчбэк"), BodyType::Hatchback);
+        assert_eq!(BodyType::parse("хэтчбек"), BodyType::Hatchback);
+        assert_eq!(BodyType::parse(" Внедорожник "), BodyType::Suv);
+        assert_eq!(Transmission::parse("Механика"), Transmission::Manual);
+        assert_eq!(Transmission::parse("механическая"), Transmission::Manual);
+        assert_eq!(Transmission::parse("AT").code(), "automatic");
+        assert_eq!(Drive::parse("Передний").label_en(), "Front");
+        assert_eq!(FuelType::parse("Бензин"), FuelType::Petrol);
+        assert_eq!(Condition::parse("Не битый"), Condition::NotDamaged);
+
+        let other = BodyType::parse("Тарга");
+        assert!(other.is_other());
+        assert_eq!((other.code(), other.label_ru()), ("Тарга", "Тарга"));
+
+        assert_eq!(serde_json::to_string(&Some(Drive::All))?, r#""all""#);
+        assert_eq!(serde_json::from_str::<Drive>(r#""Полный""#)?, Drive::All);
+        assert_eq!(BodyType::KNOWN.len(), 12);
+
+        let any = Any::from(Drive::All);
+        assert_eq!((any.code(), any.label_ru(), any.label_en()), ("all", "полный", "All-wheel"));
+        assert_eq!(Any::from(BodyType::parse("Тарга")).to_string(), "Тарга");
+
+        Ok(())
+    }
+}